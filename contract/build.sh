@@ -3,4 +3,4 @@ set -e
 
 RUSTFLAGS='-C link-arg=-s' cargo build --target wasm32-unknown-unknown --release
 mkdir -p ../out
-cp target/wasm32-unknown-unknown/release/*.wasm ../out/main.wasm
+cp ../target/wasm32-unknown-unknown/release/contract.wasm ../out/main.wasm
@@ -25,5 +25,5 @@ docker start $NAME
 docker exec -it $NAME /bin/bash -c "rustup toolchain install 1.56.0; rustup default 1.56.0; rustup target add wasm32-unknown-unknown; cargo build --target wasm32-unknown-unknown --release"
 
 mkdir -p res
-cp $DIR/target/wasm32-unknown-unknown/release/contract.wasm $DIR/res/contract_release.wasm
+cp $DIR/../target/wasm32-unknown-unknown/release/contract.wasm $DIR/res/contract_release.wasm
 
@@ -0,0 +1,289 @@
+use super::*;
+
+const BURROW_DEPOSIT_GAS: Gas = Gas(50_000_000_000_000);
+const ON_BURROW_DEPOSITED_GAS: Gas = Gas(10_000_000_000_000);
+const BURROW_READ_GAS: Gas = Gas(10_000_000_000_000);
+/// Covers Burrow's `ft_transfer` of the withdrawn tokens and its callback.
+const BURROW_WITHDRAW_GAS: Gas = Gas(70_000_000_000_000);
+const ON_BURROW_WITHDRAWN_GAS: Gas = Gas(10_000_000_000_000);
+const ON_BURROW_BALANCE_READ_GAS: Gas = Gas(BURROW_WITHDRAW_GAS.0
+    + FT_BALANCE_OF_GAS.0
+    + ON_BURROW_WITHDRAWN_GAS.0
+    + 10_000_000_000_000);
+const ON_BURROW_SUPPLY_READ_GAS: Gas =
+    Gas(FT_BALANCE_OF_GAS.0 + ON_BURROW_BALANCE_READ_GAS.0 + 10_000_000_000_000);
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BurrowAsset {
+    pub token_id: AccountId,
+    pub balance: U128,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BurrowAccount {
+    pub supplied: Vec<BurrowAsset>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+enum BurrowAction {
+    Withdraw {
+        token_id: AccountId,
+        max_amount: Option<U128>,
+    },
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct BurrowExecuteArgs {
+    actions: Vec<BurrowAction>,
+}
+
+/// Interface of the Burrow lending protocol
+#[ext_contract(ext_burrow)]
+pub trait Burrow {
+    fn get_account(&self, account_id: AccountId) -> Option<BurrowAccount>;
+}
+
+#[ext_contract(ext_self_burrow)]
+pub trait ExtBurrow {
+    fn on_burrow_deposited(
+        &mut self,
+        #[callback_result] used_amount: Result<U128, PromiseError>,
+        amount: U128,
+    );
+    fn on_burrow_supply_read(
+        &mut self,
+        #[callback_result] account: Result<Option<BurrowAccount>, PromiseError>,
+    );
+    fn on_burrow_balance_read(
+        &mut self,
+        #[callback_result] balance: Result<U128, PromiseError>,
+        supplied: U128,
+    );
+    fn on_burrow_withdrawn(
+        &mut self,
+        #[callback_result] balance: Result<U128, PromiseError>,
+        balance_before: U128,
+    );
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Burrow contract idle wNEAR is supplied to, `None` disables it.
+    pub fn set_burrow_contract(&mut self, burrow_contract_id: Option<AccountId>) -> Promise {
+        self.assert_owner_or_timelock();
+        require!(
+            self.burrow_deposited == 0 && !self.burrow_pending,
+            "Withdraw the wNEAR from Burrow first"
+        );
+        self.burrow_contract_id = burrow_contract_id;
+        Event::ConfigChanged {
+            field: "burrow_contract_id",
+        }
+        .emit();
+        self.internal_register_storage()
+    }
+
+    /// Supplies `amount`, by default all, of the wNEAR buffer to Burrow until the next
+    /// distribution, which withdraws it with the interest first.
+    pub fn deposit_to_burrow(&mut self, amount: Option<U128>) -> Promise {
+        self.assert_operator();
+        let burrow_contract_id = self
+            .burrow_contract_id
+            .clone()
+            .expect("Burrow is not configured");
+        require!(
+            !self.distribution_in_flight && !self.burrow_pending,
+            "A distribution or Burrow withdrawal is in flight"
+        );
+        let amount = amount.map_or(self.wrapped_amount, |amount| amount.0);
+        require!(
+            amount > 0 && amount <= self.wrapped_amount,
+            "Invalid amount of wNEAR"
+        );
+        self.wrapped_amount -= amount;
+        self.burrow_deposited += amount;
+        ext_fungible_token::ft_transfer_call(
+            burrow_contract_id,
+            amount.into(),
+            None,
+            // An empty message supplies the tokens.
+            String::new(),
+            self.wrap_near_contract_id.clone(),
+            ONE_YOCTO,
+            BURROW_DEPOSIT_GAS,
+        )
+        .then(ext_self_burrow::on_burrow_deposited(
+            amount.into(),
+            env::current_account_id(),
+            NO_DEPOSIT,
+            ON_BURROW_DEPOSITED_GAS,
+        ))
+    }
+
+    #[private]
+    pub fn on_burrow_deposited(
+        &mut self,
+        #[callback_result] used_amount: Result<U128, PromiseError>,
+        amount: U128,
+    ) {
+        let used_amount = used_amount.map_or(0, |used_amount| used_amount.0);
+        let unused_amount = amount.0 - used_amount;
+        self.wrapped_amount += unused_amount;
+        self.burrow_deposited -= unused_amount;
+        Event::BurrowDeposited {
+            amount: used_amount.into(),
+        }
+        .emit();
+    }
+
+    /// Withdraws everything supplied to Burrow back into the wNEAR buffer.
+    pub fn withdraw_from_burrow(&mut self) -> Promise {
+        self.assert_operator();
+        require!(self.burrow_deposited > 0, "Nothing deposited in Burrow");
+        self.internal_withdraw_from_burrow()
+    }
+
+    #[private]
+    pub fn on_burrow_supply_read(
+        &mut self,
+        #[callback_result] account: Result<Option<BurrowAccount>, PromiseError>,
+    ) -> PromiseOrValue<()> {
+        let supplied = match account {
+            Ok(account) => self.internal_burrow_supplied(account),
+            Err(_) => {
+                self.burrow_pending = false;
+                return PromiseOrValue::Value(());
+            }
+        };
+        let promise = ext_fungible_token::ft_balance_of(
+            env::current_account_id(),
+            self.wrap_near_contract_id.clone(),
+            NO_DEPOSIT,
+            FT_BALANCE_OF_GAS,
+        )
+        .then(ext_self_burrow::on_burrow_balance_read(
+            supplied.into(),
+            env::current_account_id(),
+            NO_DEPOSIT,
+            ON_BURROW_BALANCE_READ_GAS,
+        ));
+        PromiseOrValue::Promise(promise)
+    }
+
+    /// Withdraws `supplied` with the wNEAR balance from before, as Burrow's `execute` doesn't
+    /// return the amount it transferred.
+    #[private]
+    pub fn on_burrow_balance_read(
+        &mut self,
+        #[callback_result] balance: Result<U128, PromiseError>,
+        supplied: U128,
+    ) -> PromiseOrValue<()> {
+        let balance = match balance {
+            Ok(balance) => balance,
+            Err(_) => {
+                self.burrow_pending = false;
+                return PromiseOrValue::Value(());
+            }
+        };
+        let args = BurrowExecuteArgs {
+            actions: vec![BurrowAction::Withdraw {
+                token_id: self.wrap_near_contract_id.clone(),
+                max_amount: Some(supplied),
+            }],
+        };
+        let promise = Promise::new(self.burrow_contract_id.clone().unwrap())
+            .function_call(
+                "execute".to_string(),
+                serde_json::to_vec(&args).unwrap(),
+                ONE_YOCTO,
+                BURROW_WITHDRAW_GAS,
+            )
+            .then(ext_fungible_token::ft_balance_of(
+                env::current_account_id(),
+                self.wrap_near_contract_id.clone(),
+                NO_DEPOSIT,
+                FT_BALANCE_OF_GAS,
+            ))
+            .then(ext_self_burrow::on_burrow_withdrawn(
+                balance,
+                env::current_account_id(),
+                NO_DEPOSIT,
+                ON_BURROW_WITHDRAWN_GAS,
+            ));
+        PromiseOrValue::Promise(promise)
+    }
+
+    /// Counts what the wNEAR balance grew by since `balance_before`. Without a reading of the
+    /// balance it's unknown what arrived, so nothing is counted and the deposit stays as it was.
+    #[private]
+    pub fn on_burrow_withdrawn(
+        &mut self,
+        #[callback_result] balance: Result<U128, PromiseError>,
+        balance_before: U128,
+    ) {
+        self.burrow_pending = false;
+        let balance = match balance {
+            Ok(balance) => balance.0,
+            Err(_) => {
+                self.internal_record_error("burrow", "balance_read_failed", 0);
+                return;
+            }
+        };
+        let withdrawn = balance.saturating_sub(balance_before.0);
+        let principal = std::cmp::min(withdrawn, self.burrow_deposited);
+        let interest = withdrawn - principal;
+        self.burrow_deposited -= principal;
+        self.wrapped_amount += withdrawn;
+        self.available_rewards += interest;
+        self.burrow_interest_earned += interest;
+        Event::BurrowWithdrawn {
+            amount: withdrawn.into(),
+            interest: interest.into(),
+        }
+        .emit();
+    }
+
+    /// wNEAR principal supplied to Burrow.
+    pub fn get_burrow_deposit(&self) -> U128 {
+        self.burrow_deposited.into()
+    }
+}
+
+impl Contract {
+    /// Reads the supplied wNEAR and withdraws all of it.
+    pub fn internal_withdraw_from_burrow(&mut self) -> Promise {
+        require!(
+            !self.burrow_pending,
+            "A Burrow withdrawal is already in flight"
+        );
+        self.burrow_pending = true;
+        ext_burrow::get_account(
+            env::current_account_id(),
+            self.burrow_contract_id
+                .clone()
+                .expect("Burrow is not configured"),
+            NO_DEPOSIT,
+            BURROW_READ_GAS,
+        )
+        .then(ext_self_burrow::on_burrow_supply_read(
+            env::current_account_id(),
+            NO_DEPOSIT,
+            ON_BURROW_SUPPLY_READ_GAS,
+        ))
+    }
+
+    fn internal_burrow_supplied(&self, account: Option<BurrowAccount>) -> Balance {
+        account
+            .and_then(|account| {
+                account
+                    .supplied
+                    .into_iter()
+                    .find(|asset| asset.token_id == self.wrap_near_contract_id)
+            })
+            .map_or(0, |asset| asset.balance.0)
+    }
+}
@@ -0,0 +1,258 @@
+use super::*;
+
+const BUYBACK_SWAP_GAS: Gas = Gas(40_000_000_000_000);
+const ON_BUYBACK_SWAP_GAS: Gas = Gas(40_000_000_000_000);
+const ON_BUYBACK_BALANCE_GAS: Gas = Gas(25_000_000_000_000);
+const BURN_GAS: Gas = Gas(10_000_000_000_000);
+const ON_TOKENS_BURNED_GAS: Gas = Gas(5_000_000_000_000);
+
+/// What a distribution does with the reward tokens of the swap.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum DistributionMode {
+    /// Adds them to the staking pool farm.
+    Farm,
+    /// Swaps them into the buyback token and burns it.
+    BuybackAndBurn,
+}
+
+/// How bought tokens are taken out of circulation.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub enum BurnTarget {
+    /// `ft_transfer` to an account nobody controls.
+    Account(AccountId),
+    /// Calls this method of the token with `{"amount": "..."}` and 1 yoctoNEAR.
+    Method(String),
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BuybackConfig {
+    pub token_id: AccountId,
+    /// Path from the reward token to `token_id`.
+    pub swap_path: Vec<Action>,
+    pub burn_target: BurnTarget,
+    /// Lowest accepted rate, `min_amount_out` bought tokens per `amount_in` reward tokens.
+    pub amount_in: U128,
+    pub min_amount_out: U128,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct BurnArgs {
+    amount: U128,
+}
+
+#[ext_contract(ext_self_buyback)]
+pub trait ExtBuyback {
+    fn on_buyback_swap(
+        &mut self,
+        #[callback_result] used_amount: Result<U128, PromiseError>,
+        reward_amount: U128,
+    );
+    fn on_buyback_balance(&mut self, #[callback] amount: U128);
+    fn on_tokens_burned(&mut self, token_id: AccountId, amount: U128);
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the token bought and burned in `DistributionMode::BuybackAndBurn`.
+    pub fn set_buyback(&mut self, buyback: Option<BuybackConfig>) {
+        self.assert_owner_or_timelock();
+        match &buyback {
+            Some(buyback) => {
+                require!(
+                    buyback.token_id != self.reward_token_id
+                        && buyback.token_id != self.wrap_near_contract_id,
+                    "Buyback token is accounted for by the contract"
+                );
+                let path = &buyback.swap_path;
+                assert_valid_path_hops(path);
+                assert_eq!(path.first().unwrap().token_in, self.reward_token_id);
+                assert_eq!(path.last().unwrap().token_out, buyback.token_id);
+                assert!(path.iter().all(|action| action.min_amount_out.0 == 0));
+                require!(
+                    buyback.amount_in.0 > 0 && buyback.min_amount_out.0 > 0,
+                    "Invalid buyback rate"
+                );
+            }
+            None => require!(
+                self.distribution_mode == DistributionMode::Farm,
+                "Switch the distribution mode to farming first"
+            ),
+        }
+        self.buyback = buyback;
+        self.next_distribution_mode = None;
+        Event::ConfigChanged { field: "buyback" }.emit();
+        if self.buyback.is_some() {
+            self.internal_register_storage();
+        }
+    }
+
+    pub fn set_distribution_mode(&mut self, distribution_mode: DistributionMode) {
+        self.assert_owner_or_timelock();
+        self.internal_assert_distribution_mode(distribution_mode);
+        self.distribution_mode = distribution_mode;
+        Event::ConfigChanged {
+            field: "distribution_mode",
+        }
+        .emit();
+    }
+
+    /// Overrides `distribution_mode` for the next reward token distribution only.
+    pub fn set_next_distribution_mode(&mut self, distribution_mode: Option<DistributionMode>) {
+        self.assert_operator();
+        self.internal_set_next_distribution_mode(distribution_mode);
+    }
+
+    /// Burns the bought tokens left on the balance, e.g. after a failed burn.
+    pub fn burn_buyback_tokens(&mut self) -> Promise {
+        self.assert_operator();
+        self.internal_burn_buyback_balance()
+    }
+
+    #[private]
+    pub fn on_buyback_swap(
+        &mut self,
+        #[callback_result] used_amount: Result<U128, PromiseError>,
+        reward_amount: U128,
+    ) {
+        self.internal_end_distribution_stage();
+        let used_amount = used_amount.map(|amount| amount.0).unwrap_or(0);
+        // Refunded reward tokens go to the farm with the next distribution instead.
+        let unused_amount = reward_amount.0 - used_amount;
+        if unused_amount > 0 {
+            Event::RewardTokensHeldBack {
+                amount: unused_amount.into(),
+                reason: "buyback_failed",
+            }
+            .emit();
+            self.held_reward_tokens += unused_amount;
+        }
+        if used_amount == 0 {
+            return;
+        }
+        self.reward_tokens_bought_back += used_amount;
+        Event::BuybackExecuted {
+            reward_amount: used_amount.into(),
+        }
+        .emit();
+        self.internal_burn_buyback_balance().as_return();
+    }
+
+    #[private]
+    pub fn on_buyback_balance(&mut self, #[callback] amount: U128) {
+        if amount.0 == 0 {
+            return;
+        }
+        let buyback = self.buyback.clone().expect("Buyback is not configured");
+        let token_id = buyback.token_id;
+        let promise = match buyback.burn_target {
+            BurnTarget::Account(receiver_id) => ext_fungible_token::ft_transfer(
+                receiver_id,
+                amount,
+                Some("Buyback burn".to_string()),
+                token_id.clone(),
+                ONE_YOCTO,
+                BURN_GAS,
+            ),
+            BurnTarget::Method(method_name) => Promise::new(token_id.clone()).function_call(
+                method_name,
+                serde_json::to_vec(&BurnArgs { amount }).unwrap(),
+                ONE_YOCTO,
+                BURN_GAS,
+            ),
+        };
+        promise
+            .then(ext_self_buyback::on_tokens_burned(
+                token_id,
+                amount,
+                env::current_account_id(),
+                NO_DEPOSIT,
+                ON_TOKENS_BURNED_GAS,
+            ))
+            .as_return();
+    }
+
+    #[private]
+    pub fn on_tokens_burned(&mut self, token_id: AccountId, amount: U128) {
+        require!(is_promise_success(), "Burn failed");
+        self.buyback_tokens_burned += amount.0;
+        Event::TokensBurned { token_id, amount }.emit();
+    }
+}
+
+impl Contract {
+    /// Swaps `reward_amount` reward tokens into the buyback token on REF and burns the output.
+    pub fn internal_buyback(&mut self, reward_amount: Balance) -> Promise {
+        let buyback = self.buyback.as_ref().expect("Buyback is not configured");
+        let mut actions = buyback.swap_path.clone();
+        actions.last_mut().unwrap().min_amount_out = U128(u128_ratio(
+            reward_amount,
+            buyback.min_amount_out.0,
+            buyback.amount_in.0,
+        ));
+        ext_fungible_token::ft_transfer_call(
+            self.ref_finance_contract_id.clone(),
+            reward_amount.into(),
+            None,
+            serde_json::to_string(&RefArgs {
+                actions,
+                referral_id: self.referral_id.clone(),
+            })
+            .unwrap(),
+            self.reward_token_id.clone(),
+            ONE_YOCTO,
+            BUYBACK_SWAP_GAS,
+        )
+        .then(ext_self_buyback::on_buyback_swap(
+            reward_amount.into(),
+            env::current_account_id(),
+            NO_DEPOSIT,
+            ON_BUYBACK_SWAP_GAS,
+        ))
+    }
+
+    fn internal_burn_buyback_balance(&self) -> Promise {
+        let buyback = self.buyback.as_ref().expect("Buyback is not configured");
+        ext_fungible_token::ft_balance_of(
+            env::current_account_id(),
+            buyback.token_id.clone(),
+            NO_DEPOSIT,
+            FT_BALANCE_OF_GAS,
+        )
+        .then(ext_self_buyback::on_buyback_balance(
+            env::current_account_id(),
+            NO_DEPOSIT,
+            ON_BUYBACK_BALANCE_GAS,
+        ))
+    }
+
+    /// Mode of the current distribution, consuming a one-off override.
+    pub fn internal_take_distribution_mode(&mut self) -> DistributionMode {
+        self.next_distribution_mode
+            .take()
+            .unwrap_or(self.distribution_mode)
+    }
+
+    pub fn internal_set_next_distribution_mode(
+        &mut self,
+        distribution_mode: Option<DistributionMode>,
+    ) {
+        if let Some(distribution_mode) = distribution_mode {
+            self.internal_assert_distribution_mode(distribution_mode);
+        }
+        self.next_distribution_mode = distribution_mode;
+        Event::ConfigChanged {
+            field: "next_distribution_mode",
+        }
+        .emit();
+    }
+
+    fn internal_assert_distribution_mode(&self, distribution_mode: DistributionMode) {
+        if distribution_mode == DistributionMode::BuybackAndBurn {
+            require!(self.buyback.is_some(), "Buyback is not configured");
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use super::*;
+use near_sdk::json_types::Base64VecU8;
+use near_sdk::CryptoHash;
+
+/// A distribution planned by an operator. Its hash is committed first and the plan is
+/// revealed in a later block with the prices, so the minimum output isn't known to others
+/// until the swap is executed.
+#[derive(BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DistributionPlan {
+    /// Most NEAR the distribution swaps.
+    pub amount: U128,
+    /// Minimum output for `amount`, scaled to the amount actually swapped.
+    pub min_amount_out: U128,
+    /// The plan can't be revealed after this timestamp, in nanoseconds.
+    pub deadline: U64,
+    /// Random value keeping the plan from being guessed from its hash.
+    pub salt: U64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DistributionCommitment {
+    /// sha256 of the borsh serialized `(contract_id, plan)`.
+    pub hash: CryptoHash,
+    #[serde(with = "u64_dec_format")]
+    pub block_height: BlockHeight,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Requires every price triggered distribution to reveal a committed plan.
+    pub fn set_require_commitment(&mut self, require_commitment: bool) {
+        self.assert_owner();
+        self.require_commitment = require_commitment;
+        Event::ConfigChanged {
+            field: "require_commitment",
+        }
+        .emit();
+    }
+
+    /// Commits to the plan of the next distribution, replacing an unrevealed commitment.
+    pub fn commit_distribution(&mut self, hash: Base64VecU8) {
+        self.assert_operator();
+        let hash = CryptoHash::try_from(hash.0).expect("Commitment must be a sha256 hash");
+        self.distribution_commitment = Some(DistributionCommitment {
+            hash,
+            block_height: env::block_height(),
+        });
+        Event::DistributionCommitted {
+            hash: Base64VecU8(hash.to_vec()),
+        }
+        .emit();
+    }
+}
+
+impl Contract {
+    /// Checks a plan against the commitment made in an earlier block and consumes it.
+    pub fn internal_reveal_distribution_plan(
+        &mut self,
+        plan: DistributionPlan,
+    ) -> DistributionPlan {
+        let commitment = self
+            .distribution_commitment
+            .take()
+            .expect("No distribution is committed");
+        require!(
+            env::block_height() > commitment.block_height,
+            "The plan can't be revealed in the block of its commitment"
+        );
+        let message = (env::current_account_id(), &plan).try_to_vec().unwrap();
+        require!(
+            env::sha256(&message) == commitment.hash,
+            "The plan doesn't match the commitment"
+        );
+        require!(
+            env::block_timestamp() <= plan.deadline.0,
+            "The plan has expired"
+        );
+        require!(
+            plan.amount.0 > 0 && plan.min_amount_out.0 > 0,
+            "Invalid plan amounts"
+        );
+        Event::DistributionRevealed {
+            amount: plan.amount,
+            min_amount_out: plan.min_amount_out,
+        }
+        .emit();
+        plan
+    }
+}
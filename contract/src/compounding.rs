@@ -0,0 +1,86 @@
+use super::*;
+
+const DEPOSIT_AND_STAKE_GAS: Gas = Gas(30_000_000_000_000);
+const ON_RESTAKED_GAS: Gas = Gas(5_000_000_000_000);
+
+#[ext_contract(ext_self_compounding)]
+pub trait ExtCompounding {
+    fn on_restaked(&mut self, amount: U128);
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Stakes `restake_bps` of each withdrawn reward, after fees, back into the staking pool
+    /// instead of swapping it. Restaked NEAR stays staked, only its rewards are unstaked.
+    pub fn set_restake_bps(&mut self, restake_bps: u32) {
+        self.assert_owner_or_timelock();
+        require!(restake_bps <= MAX_BPS, "Restake share can't exceed 100%");
+        self.restake_bps = restake_bps;
+        Event::ConfigChanged {
+            field: "restake_bps",
+        }
+        .emit();
+    }
+
+    /// Stops keeping the restaked NEAR staked. It's unstaked with the next ping and returned
+    /// to the rewards as is, the owner fee and keeper reward were taken before restaking.
+    pub fn release_restaked(&mut self) {
+        self.assert_owner();
+        require!(self.restaked > 0, "Nothing is restaked");
+        self.restaked = 0;
+        Event::ConfigChanged { field: "restaked" }.emit();
+    }
+
+    #[private]
+    pub fn on_restaked(&mut self, amount: U128) {
+        if is_promise_success() {
+            Event::Restaked { amount }.emit();
+        } else {
+            // The deposit is refunded and swapped with the rest of the rewards.
+            self.restaked = self.restaked.saturating_sub(amount.0);
+            self.near_restaked -= amount.0;
+            self.available_rewards += amount.0;
+        }
+    }
+}
+
+impl Contract {
+    /// Stakes `restake_bps` of `amount` at the staking pool and returns the restaked amount.
+    pub fn internal_restake(&mut self, amount: Balance) -> Balance {
+        let restake_amount = u128_ratio(amount, self.restake_bps as u128, MAX_BPS as u128);
+        // Nothing is staked into a pool that is being drained.
+        if restake_amount == 0 || self.staking_pool_change.is_some() {
+            return 0;
+        }
+        self.restaked += restake_amount;
+        self.near_restaked += restake_amount;
+        ext_staking_pool::deposit_and_stake(
+            self.staking_pool_account_id.clone(),
+            restake_amount,
+            DEPOSIT_AND_STAKE_GAS,
+        )
+        .then(ext_self_compounding::on_restaked(
+            restake_amount.into(),
+            env::current_account_id(),
+            NO_DEPOSIT,
+            ON_RESTAKED_GAS,
+        ));
+        restake_amount
+    }
+
+    /// Returns up to `amount` withdrawn NEAR to `available_rewards` as far as it's released
+    /// restaked NEAR, without taking fees. Returns the amount returned.
+    pub fn internal_return_released_restake(&mut self, amount: Balance) -> Balance {
+        let returned = std::cmp::min(amount, self.near_restaked - self.restaked);
+        if returned > 0 {
+            self.internal_maybe_snapshot_accounting();
+            self.near_restaked -= returned;
+            self.available_rewards += returned;
+            Event::RestakeReturned {
+                amount: returned.into(),
+            }
+            .emit();
+        }
+        returned
+    }
+}
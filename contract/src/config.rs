@@ -0,0 +1,421 @@
+use super::*;
+
+const MIN_GAS: Gas = Gas(2_000_000_000_000);
+const MAX_GAS: Gas = Gas(300_000_000_000_000);
+/// Gas a callback needs for itself on top of the calls it makes.
+const CALLBACK_GAS: Gas = Gas(5_000_000_000_000);
+
+/// Tunables of the contract in one place. Durations are in seconds.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Config {
+    // Contracts, changed with their own methods
+    pub staking_pool_account_id: AccountId,
+    pub reward_token_id: AccountId,
+    pub oracle_contract_id: AccountId,
+    pub ref_finance_contract_id: AccountId,
+    pub wrap_near_contract_id: AccountId,
+    pub swap_executor_id: Option<AccountId>,
+    pub referral_id: Option<AccountId>,
+    // Durations
+    pub farm_duration_sec: DurationSec,
+    pub full_rewards_duration_sec: DurationSec,
+    pub min_farm_duration_sec: DurationSec,
+    pub max_farm_duration_sec: DurationSec,
+    pub accounting_snapshot_interval_sec: DurationSec,
+    pub min_distribution_interval_sec: DurationSec,
+    pub release_curve: ReleaseCurve,
+    /// Reward tokens per second, 0 without a cap.
+    pub max_reward_rate: U128,
+    pub farm_top_up_window_sec: DurationSec,
+    pub require_top_up_due: bool,
+    // Swaps
+    pub max_near_reward: U128,
+    pub swap_fraction_bps: u32,
+    pub max_swap_chunk: U128,
+    pub min_wrap_amount: U128,
+    pub min_swap_amount: U128,
+    pub max_swap_delay_blocks: BlockHeight,
+    pub min_fill_bps: u32,
+    pub max_price_deviation_bps: u32,
+    pub require_route_prices: bool,
+    pub check_pool_pause: bool,
+    pub pre_swap_quote_tolerance_bps: Option<u32>,
+    pub slippage_policy: SlippagePolicy,
+    // Oracle
+    pub oracle_max_recency_sec: DurationSec,
+    pub oracle_max_staleness_sec: DurationSec,
+    // Fees
+    pub owner_fee_bps: u32,
+    pub keeper_reward_bps: u32,
+    pub max_keeper_reward: U128,
+    pub restake_bps: u32,
+    pub treasury_share_bps: u32,
+    // Donations
+    pub min_donation: U128,
+    pub donations_enabled: bool,
+    pub gas: GasConfig,
+}
+
+/// Fields of `Config` to change, the others are left as they are.
+#[derive(Deserialize, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ConfigUpdate {
+    /// The REF and wNEAR contracts come with a swap path and the staking pool with a
+    /// whitelist check, they keep their own methods.
+    pub oracle_contract_id: Option<AccountId>,
+    /// `null` clears the executor, as does `null` for the referral ID and the tolerance.
+    #[serde(default, deserialize_with = "double_option::deserialize")]
+    pub swap_executor_id: Option<Option<AccountId>>,
+    #[serde(default, deserialize_with = "double_option::deserialize")]
+    pub referral_id: Option<Option<AccountId>>,
+    pub farm_duration_sec: Option<DurationSec>,
+    pub full_rewards_duration_sec: Option<DurationSec>,
+    pub min_farm_duration_sec: Option<DurationSec>,
+    pub max_farm_duration_sec: Option<DurationSec>,
+    pub accounting_snapshot_interval_sec: Option<DurationSec>,
+    pub min_distribution_interval_sec: Option<DurationSec>,
+    pub release_curve: Option<ReleaseCurve>,
+    pub max_reward_rate: Option<U128>,
+    pub farm_top_up_window_sec: Option<DurationSec>,
+    pub require_top_up_due: Option<bool>,
+    pub max_near_reward: Option<U128>,
+    /// Below 100% only with a NEAR payout account, see `set_swap_fraction`.
+    pub swap_fraction_bps: Option<u32>,
+    pub max_swap_chunk: Option<U128>,
+    pub min_wrap_amount: Option<U128>,
+    pub min_swap_amount: Option<U128>,
+    pub max_swap_delay_blocks: Option<BlockHeight>,
+    pub min_fill_bps: Option<u32>,
+    pub max_price_deviation_bps: Option<u32>,
+    pub require_route_prices: Option<bool>,
+    pub check_pool_pause: Option<bool>,
+    #[serde(default, deserialize_with = "double_option::deserialize")]
+    pub pre_swap_quote_tolerance_bps: Option<Option<u32>>,
+    pub slippage_policy: Option<SlippagePolicy>,
+    pub oracle_max_recency_sec: Option<DurationSec>,
+    pub oracle_max_staleness_sec: Option<DurationSec>,
+    pub owner_fee_bps: Option<u32>,
+    pub keeper_reward_bps: Option<u32>,
+    pub max_keeper_reward: Option<U128>,
+    pub restake_bps: Option<u32>,
+    /// Above 0 only with a treasury account, see `set_treasury_split`.
+    pub treasury_share_bps: Option<u32>,
+    pub min_donation: Option<U128>,
+    pub donations_enabled: Option<bool>,
+    pub gas: Option<GasConfig>,
+}
+
+/// Gas attached to the calls of the distribution chain, adjustable when the staking pool,
+/// its farm or REF change their gas profile.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy)]
+#[serde(crate = "near_sdk::serde")]
+pub struct GasConfig {
+    pub staking_pool_ping: Gas,
+    pub withdraw: Gas,
+    pub unstake: Gas,
+    pub on_distribute: Gas,
+    pub on_withdraw: Gas,
+    pub wrap_near: Gas,
+    pub swap: Gas,
+    pub on_swap: Gas,
+    pub on_pool_pause_checked: Gas,
+    /// `ft_transfer_call` of reward tokens to the farm.
+    pub add_farm: Gas,
+    pub on_reward_tokens_farmed: Gas,
+    pub on_pyth_prices: Gas,
+}
+
+impl Default for GasConfig {
+    fn default() -> Self {
+        Self {
+            staking_pool_ping: STAKING_POOL_PING_GAS,
+            withdraw: WITHDRAW_GAS,
+            unstake: UNSTAKE_ALL_GAS,
+            on_distribute: ON_DISTRIBUTE_GAS,
+            on_withdraw: ON_WITHDRAW_GAS,
+            wrap_near: WRAP_NEAR_GAS,
+            swap: SWAP_GAS,
+            on_swap: ON_SWAP_GAS,
+            on_pool_pause_checked: ON_POOL_PAUSE_CHECKED_GAS,
+            add_farm: FT_TRANSFER_CALL_ADD_FARM_GAS,
+            on_reward_tokens_farmed: ON_REWARD_TOKENS_FARMED_GAS,
+            on_pyth_prices: ON_PYTH_PRICES_GAS,
+        }
+    }
+}
+
+impl GasConfig {
+    /// Checks the bounds of every value and that each callback covers the calls it makes.
+    pub fn assert_valid(&self) {
+        let values = [
+            self.staking_pool_ping,
+            self.withdraw,
+            self.unstake,
+            self.on_distribute,
+            self.on_withdraw,
+            self.wrap_near,
+            self.swap,
+            self.on_swap,
+            self.on_pool_pause_checked,
+            self.add_farm,
+            self.on_reward_tokens_farmed,
+            self.on_pyth_prices,
+        ];
+        require!(
+            values.iter().all(|gas| *gas >= MIN_GAS && *gas <= MAX_GAS),
+            "Gas is out of bounds"
+        );
+        // `ping` also starts the farm check and the total stake read.
+        require!(
+            self.staking_pool_ping
+                + STAKING_POOL_READ_GAS
+                + self.on_distribute
+                + FARM_CHECK_GAS
+                + POOL_TOTAL_STAKE_GAS
+                <= MAX_GAS,
+            "Not enough gas left for ping"
+        );
+        require!(
+            self.on_distribute >= self.withdraw + self.on_withdraw + CALLBACK_GAS
+                && self.on_distribute >= self.unstake + CALLBACK_GAS,
+            "Not enough gas for on_distribute"
+        );
+        let farm_gas = self.add_farm + self.on_reward_tokens_farmed + CALLBACK_GAS;
+        require!(
+            self.on_pool_pause_checked >= farm_gas,
+            "Not enough gas for on_pool_pause_checked"
+        );
+        // `on_swap_output` gets what's left after the balance read.
+        let on_swap_output = self.on_swap.0.saturating_sub(ON_SWAP_OUTPUT_READ_GAS.0);
+        require!(
+            on_swap_output >= farm_gas.0
+                && on_swap_output
+                    >= (STAKING_POOL_READ_GAS + self.on_pool_pause_checked + CALLBACK_GAS).0,
+            "Not enough gas for on_swap"
+        );
+        require!(
+            self.on_pyth_prices >= self.wrap_near + self.swap + self.on_swap + CALLBACK_GAS,
+            "Not enough gas for on_pyth_prices"
+        );
+    }
+}
+
+fn to_sec(duration: Duration) -> DurationSec {
+    (duration / 10u64.pow(9)) as DurationSec
+}
+
+fn from_sec(duration_sec: DurationSec) -> Duration {
+    u64::from(duration_sec) * 10u64.pow(9)
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_config(&self) -> Config {
+        Config {
+            staking_pool_account_id: self.staking_pool_account_id.clone(),
+            reward_token_id: self.reward_token_id.clone(),
+            oracle_contract_id: self.oracle_contract_id.clone(),
+            ref_finance_contract_id: self.ref_finance_contract_id.clone(),
+            wrap_near_contract_id: self.wrap_near_contract_id.clone(),
+            swap_executor_id: self.swap_executor_id.clone(),
+            referral_id: self.referral_id.clone(),
+            farm_duration_sec: to_sec(self.farm_duration),
+            full_rewards_duration_sec: to_sec(self.full_rewards_duration),
+            min_farm_duration_sec: to_sec(self.min_farm_duration),
+            max_farm_duration_sec: to_sec(self.max_farm_duration),
+            accounting_snapshot_interval_sec: to_sec(self.accounting_snapshot_interval),
+            min_distribution_interval_sec: to_sec(self.min_distribution_interval),
+            release_curve: self.release_curve,
+            max_reward_rate: self.max_reward_rate.into(),
+            farm_top_up_window_sec: to_sec(self.farm_top_up_window),
+            require_top_up_due: self.require_top_up_due,
+            max_near_reward: self.max_near_reward.into(),
+            swap_fraction_bps: self.swap_fraction_bps,
+            max_swap_chunk: self.max_swap_chunk.into(),
+            min_wrap_amount: self.min_wrap_amount.into(),
+            min_swap_amount: self.min_swap_amount.into(),
+            max_swap_delay_blocks: self.max_swap_delay_blocks,
+            min_fill_bps: self.min_fill_bps,
+            max_price_deviation_bps: self.max_price_deviation_bps,
+            require_route_prices: self.require_route_prices,
+            check_pool_pause: self.check_pool_pause,
+            pre_swap_quote_tolerance_bps: self.pre_swap_quote_tolerance_bps,
+            slippage_policy: self.slippage_policy,
+            oracle_max_recency_sec: self.oracle_max_recency_sec,
+            oracle_max_staleness_sec: to_sec(self.oracle_max_staleness),
+            owner_fee_bps: self.owner_fee_bps,
+            keeper_reward_bps: self.keeper_reward_bps,
+            max_keeper_reward: self.max_keeper_reward.into(),
+            restake_bps: self.restake_bps,
+            treasury_share_bps: self.treasury_share_bps,
+            min_donation: self.min_donation.into(),
+            donations_enabled: self.donations_enabled,
+            gas: self.gas,
+        }
+    }
+
+    /// Applies the given fields and validates the resulting config as a whole. Timelocked, as
+    /// it covers the fees and the swap settings.
+    pub fn update_config(&mut self, update: ConfigUpdate) {
+        self.assert_owner_or_timelock();
+        if let Some(oracle_contract_id) = update.oracle_contract_id {
+            self.internal_set_oracle_contract(oracle_contract_id);
+        }
+        if let Some(swap_executor_id) = update.swap_executor_id {
+            self.swap_executor_id = swap_executor_id;
+        }
+        if let Some(referral_id) = update.referral_id {
+            self.referral_id = referral_id;
+        }
+        if let Some(farm_duration_sec) = update.farm_duration_sec {
+            self.farm_duration = from_sec(farm_duration_sec);
+        }
+        if let Some(full_rewards_duration_sec) = update.full_rewards_duration_sec {
+            self.full_rewards_duration = from_sec(full_rewards_duration_sec);
+        }
+        if let Some(min_farm_duration_sec) = update.min_farm_duration_sec {
+            self.min_farm_duration = from_sec(min_farm_duration_sec);
+        }
+        if let Some(max_farm_duration_sec) = update.max_farm_duration_sec {
+            self.max_farm_duration = from_sec(max_farm_duration_sec);
+        }
+        if let Some(interval_sec) = update.accounting_snapshot_interval_sec {
+            self.accounting_snapshot_interval = from_sec(interval_sec);
+        }
+        if let Some(interval_sec) = update.min_distribution_interval_sec {
+            self.min_distribution_interval = from_sec(interval_sec);
+        }
+        if let Some(release_curve) = update.release_curve {
+            self.release_curve = release_curve;
+        }
+        if let Some(max_reward_rate) = update.max_reward_rate {
+            self.max_reward_rate = max_reward_rate.0;
+        }
+        if let Some(window_sec) = update.farm_top_up_window_sec {
+            self.farm_top_up_window = from_sec(window_sec);
+        }
+        if let Some(require_top_up_due) = update.require_top_up_due {
+            self.require_top_up_due = require_top_up_due;
+        }
+        if let Some(max_near_reward) = update.max_near_reward {
+            self.max_near_reward = max_near_reward.0;
+        }
+        if let Some(swap_fraction_bps) = update.swap_fraction_bps {
+            self.swap_fraction_bps = swap_fraction_bps;
+        }
+        if let Some(max_swap_chunk) = update.max_swap_chunk {
+            self.max_swap_chunk = max_swap_chunk.0;
+        }
+        if let Some(min_wrap_amount) = update.min_wrap_amount {
+            self.min_wrap_amount = min_wrap_amount.0;
+        }
+        if let Some(min_swap_amount) = update.min_swap_amount {
+            self.min_swap_amount = min_swap_amount.0;
+        }
+        if let Some(max_swap_delay_blocks) = update.max_swap_delay_blocks {
+            self.max_swap_delay_blocks = max_swap_delay_blocks;
+        }
+        if let Some(min_fill_bps) = update.min_fill_bps {
+            self.min_fill_bps = min_fill_bps;
+        }
+        if let Some(max_price_deviation_bps) = update.max_price_deviation_bps {
+            self.max_price_deviation_bps = max_price_deviation_bps;
+        }
+        if let Some(require_route_prices) = update.require_route_prices {
+            self.require_route_prices = require_route_prices;
+        }
+        if let Some(check_pool_pause) = update.check_pool_pause {
+            self.check_pool_pause = check_pool_pause;
+        }
+        if let Some(tolerance_bps) = update.pre_swap_quote_tolerance_bps {
+            self.pre_swap_quote_tolerance_bps = tolerance_bps;
+        }
+        if let Some(slippage_policy) = update.slippage_policy {
+            self.slippage_policy = slippage_policy;
+            self.consecutive_slippage_failures = 0;
+            self.extra_slippage_bps = 0;
+        }
+        if let Some(max_recency_sec) = update.oracle_max_recency_sec {
+            self.oracle_max_recency_sec = max_recency_sec;
+        }
+        if let Some(max_staleness_sec) = update.oracle_max_staleness_sec {
+            self.oracle_max_staleness = from_sec(max_staleness_sec);
+        }
+        if let Some(owner_fee_bps) = update.owner_fee_bps {
+            self.owner_fee_bps = owner_fee_bps;
+        }
+        if let Some(keeper_reward_bps) = update.keeper_reward_bps {
+            self.keeper_reward_bps = keeper_reward_bps;
+        }
+        if let Some(max_keeper_reward) = update.max_keeper_reward {
+            self.max_keeper_reward = max_keeper_reward.0;
+        }
+        if let Some(restake_bps) = update.restake_bps {
+            self.restake_bps = restake_bps;
+        }
+        if let Some(treasury_share_bps) = update.treasury_share_bps {
+            self.treasury_share_bps = treasury_share_bps;
+        }
+        if let Some(min_donation) = update.min_donation {
+            self.min_donation = min_donation.0;
+        }
+        if let Some(donations_enabled) = update.donations_enabled {
+            self.donations_enabled = donations_enabled;
+        }
+        if let Some(gas) = update.gas {
+            self.gas = gas;
+        }
+        self.assert_valid_config();
+        Event::ConfigChanged { field: "config" }.emit();
+    }
+}
+
+impl Contract {
+    /// Invariants between the tunables, checked after every `update_config`.
+    pub fn assert_valid_config(&self) {
+        require!(
+            self.min_farm_duration > 0 && self.min_farm_duration <= self.max_farm_duration,
+            "Invalid farm duration bounds"
+        );
+        require!(
+            self.owner_fee_bps + self.keeper_reward_bps <= MAX_BPS,
+            "Owner fee and keeper reward exceed 100%"
+        );
+        require!(
+            self.restake_bps <= MAX_BPS,
+            "Restake share can't exceed 100%"
+        );
+        require!(
+            self.swap_fraction_bps <= MAX_BPS
+                && (self.swap_fraction_bps == MAX_BPS || self.near_payout_account_id.is_some()),
+            "Invalid swap fraction"
+        );
+        require!(
+            self.treasury_share_bps <= MAX_BPS
+                && (self.treasury_share_bps == 0 || self.treasury_account_id.is_some()),
+            "Invalid treasury share"
+        );
+        if let Some(tolerance_bps) = self.pre_swap_quote_tolerance_bps {
+            require!(tolerance_bps <= MAX_BPS, "Tolerance is too large");
+        }
+        require!(
+            self.min_fill_bps > 0 && self.min_fill_bps <= MAX_BPS,
+            "Invalid minimum fill"
+        );
+        require!(
+            self.oracle_max_recency_sec > 0
+                && self.oracle_max_recency_sec <= MAX_ORACLE_RECENCY_SEC,
+            "Invalid oracle recency limit"
+        );
+        require!(
+            self.oracle_max_staleness > 0
+                && self.oracle_max_staleness <= from_sec(MAX_ORACLE_STALENESS_SEC),
+            "Invalid oracle staleness limit"
+        );
+        self.assert_valid_min_swap_amount();
+        self.release_curve.assert_valid();
+        self.slippage_policy.assert_valid();
+        self.gas.assert_valid();
+    }
+}
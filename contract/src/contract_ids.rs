@@ -0,0 +1,156 @@
+use super::*;
+
+const NEAR_WITHDRAW_GAS: Gas = Gas(10_000_000_000_000);
+const ON_WNEAR_UNWRAPPED_GAS: Gas = Gas(40_000_000_000_000);
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NearWithdrawArgs {
+    pub amount: U128,
+}
+
+#[ext_contract(ext_self_contract_ids)]
+pub trait ExtContractIds {
+    fn on_wnear_unwrapped(&mut self, wrap_near_contract_id: AccountId, swap_path: Vec<Action>);
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Replaces the main oracle. Reports of the previous one are dropped.
+    pub fn set_oracle_contract(&mut self, oracle_contract_id: AccountId) {
+        self.assert_owner_or_timelock();
+        self.internal_set_oracle_contract(oracle_contract_id);
+    }
+
+    /// Moves swaps to another REF deployment with a swap path of its pools. Fallback paths,
+    /// their exchanges, split routes and the buyback refer to the previous one and are cleared,
+    /// and the new pools have to be verified with `finalize_setup` again.
+    pub fn set_ref_finance_contract(
+        &mut self,
+        ref_finance_contract_id: AccountId,
+        swap_path: Vec<Action>,
+    ) -> Promise {
+        self.assert_owner_or_timelock();
+        self.assert_no_distribution_in_flight();
+        self.ref_finance_contract_id = ref_finance_contract_id;
+        self.ref_storage_registered = false;
+        self.buyback = None;
+        self.distribution_mode = DistributionMode::Farm;
+        self.next_distribution_mode = None;
+        self.setup_complete = false;
+        Event::ConfigChanged {
+            field: "ref_finance_contract_id",
+        }
+        .emit();
+        self.internal_replace_swap_paths(swap_path);
+        self.internal_register_storage()
+    }
+
+    /// Moves to another wNEAR contract with a swap path starting from it. The wNEAR buffer is
+    /// unwrapped at the previous contract first, the switch only happens if that succeeds.
+    /// Distributions wait for the unwrap, and wNEAR supplied to Burrow has to be withdrawn.
+    pub fn set_wrap_near_contract(
+        &mut self,
+        wrap_near_contract_id: AccountId,
+        swap_path: Vec<Action>,
+    ) -> Promise {
+        self.assert_owner_or_timelock();
+        self.assert_no_distribution_in_flight();
+        require!(
+            self.burrow_deposited == 0 && !self.burrow_pending,
+            "Withdraw the wNEAR from Burrow first"
+        );
+        if self.wrapped_amount == 0 {
+            return self.internal_set_wrap_near_contract(wrap_near_contract_id, swap_path);
+        }
+        self.distribution_in_flight = true;
+        Promise::new(self.wrap_near_contract_id.clone())
+            .function_call(
+                "near_withdraw".to_string(),
+                serde_json::to_vec(&NearWithdrawArgs {
+                    amount: self.wrapped_amount.into(),
+                })
+                .unwrap(),
+                ONE_YOCTO,
+                NEAR_WITHDRAW_GAS,
+            )
+            .then(ext_self_contract_ids::on_wnear_unwrapped(
+                wrap_near_contract_id,
+                swap_path,
+                env::current_account_id(),
+                NO_DEPOSIT,
+                ON_WNEAR_UNWRAPPED_GAS,
+            ))
+    }
+
+    #[private]
+    pub fn on_wnear_unwrapped(
+        &mut self,
+        wrap_near_contract_id: AccountId,
+        swap_path: Vec<Action>,
+    ) -> PromiseOrValue<()> {
+        self.distribution_in_flight = false;
+        if !is_promise_success() {
+            self.internal_record_error("wrap_near_contract", "unwrap_failed", self.wrapped_amount);
+            return PromiseOrValue::Value(());
+        }
+        // The unwrapped NEAR is already part of `available_rewards`.
+        self.wrapped_amount = 0;
+        PromiseOrValue::Promise(
+            self.internal_set_wrap_near_contract(wrap_near_contract_id, swap_path),
+        )
+    }
+}
+
+impl Contract {
+    pub fn internal_set_oracle_contract(&mut self, oracle_contract_id: AccountId) {
+        require!(
+            !self.extra_oracle_ids.contains(&oracle_contract_id),
+            "The oracle is already registered as an extra oracle"
+        );
+        self.oracle_contract_id = oracle_contract_id;
+        self.oracle_reports.clear();
+        Event::ConfigChanged {
+            field: "oracle_contract_id",
+        }
+        .emit();
+    }
+
+    fn assert_no_distribution_in_flight(&self) {
+        require!(
+            !self.distribution_in_flight && self.output_swaps_in_flight == 0,
+            "A distribution is in flight, retry after its swap"
+        );
+    }
+
+    fn internal_set_wrap_near_contract(
+        &mut self,
+        wrap_near_contract_id: AccountId,
+        swap_path: Vec<Action>,
+    ) -> Promise {
+        self.wrap_near_contract_id = wrap_near_contract_id;
+        // A wNEAR farm belongs to the previous token.
+        self.fallback_to_wnear = false;
+        self.wnear_farm_id = None;
+        Event::ConfigChanged {
+            field: "wrap_near_contract_id",
+        }
+        .emit();
+        self.internal_replace_swap_paths(swap_path);
+        self.internal_register_storage()
+    }
+
+    /// Output tokens and the liquid staking source swap through wNEAR on the previous
+    /// exchange, so they are cleared with the paths.
+    fn internal_replace_swap_paths(&mut self, swap_path: Vec<Action>) {
+        self.swap_path = swap_path;
+        self.fallback_swap_paths.clear();
+        self.swap_path_index = 0;
+        self.swap_path_dexes.clear();
+        self.split_routes.clear();
+        self.output_tokens.clear();
+        self.liquid_staking_source = None;
+        self.assert_valid_swap_path();
+        self.internal_fetch_token_decimals();
+    }
+}
@@ -0,0 +1,164 @@
+use super::*;
+use near_sdk::json_types::Base64VecU8;
+
+const MAX_COUNCIL_MEMBERS: usize = 10;
+const MAX_COUNCIL_PROPOSALS: usize = 20;
+/// Gas kept by the approval executing a proposal, the rest goes to the proposed call.
+const COUNCIL_EXECUTE_RESERVED_GAS: Gas = Gas(10_000_000_000_000);
+
+/// Accounts replacing `owner_id` for admin methods, `threshold` of them have to approve.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Council {
+    pub members: Vec<AccountId>,
+    pub threshold: u8,
+}
+
+/// A call of the contract to itself, made once enough council members approve it.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CouncilProposal {
+    pub id: u64,
+    pub method_name: String,
+    /// Arguments of the call, JSON for most methods and the code for `upgrade`.
+    pub args: Base64VecU8,
+    pub approvals: Vec<AccountId>,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Hands the admin methods to a council, or back to `owner_id` with `None`.
+    /// Pending proposals are dropped.
+    pub fn set_council(&mut self, council: Option<Council>) {
+        self.assert_owner();
+        if let Some(council) = &council {
+            let members = &council.members;
+            require!(
+                !members.is_empty() && members.len() <= MAX_COUNCIL_MEMBERS,
+                "Invalid number of council members"
+            );
+            require!(
+                council.threshold > 0 && council.threshold as usize <= members.len(),
+                "Invalid council threshold"
+            );
+            for (index, member) in members.iter().enumerate() {
+                require!(
+                    !members[..index].contains(member),
+                    "Duplicate council member"
+                );
+            }
+        }
+        self.council = council;
+        self.council_proposals.clear();
+        Event::ConfigChanged { field: "council" }.emit();
+    }
+
+    /// Proposes a call to an admin method and approves it, returning the proposal ID.
+    pub fn council_propose(&mut self, method_name: String, args: Base64VecU8) -> u64 {
+        self.assert_council_member();
+        require!(
+            !method_name.starts_with("on_"),
+            "Callbacks can't be proposed"
+        );
+        require!(
+            self.council_proposals.len() < MAX_COUNCIL_PROPOSALS,
+            "Too many council proposals"
+        );
+        let id = self.next_council_proposal_id;
+        self.next_council_proposal_id += 1;
+        Event::CouncilProposed {
+            id,
+            method_name: method_name.clone(),
+        }
+        .emit();
+        self.council_proposals.push(CouncilProposal {
+            id,
+            method_name,
+            args,
+            approvals: vec![],
+        });
+        self.council_approve(id);
+        id
+    }
+
+    /// Approves a proposal, executing it once the threshold is reached.
+    pub fn council_approve(&mut self, id: u64) {
+        self.assert_council_member();
+        let account_id = env::predecessor_account_id();
+        let threshold = self.council.as_ref().unwrap().threshold as usize;
+        let index = self.internal_council_proposal_index(id);
+        let proposal = &mut self.council_proposals[index];
+        require!(
+            !proposal.approvals.contains(&account_id),
+            "Already approved"
+        );
+        proposal.approvals.push(account_id.clone());
+        Event::CouncilApproved {
+            id,
+            account_id,
+            approvals: proposal.approvals.len() as u8,
+        }
+        .emit();
+        if proposal.approvals.len() < threshold {
+            return;
+        }
+        let proposal = self.council_proposals.remove(index);
+        require!(
+            self.timelock_delay == 0 || !is_timelocked(&proposal.method_name),
+            "The method is timelocked, propose `propose_change` instead"
+        );
+        Event::CouncilExecuted { id }.emit();
+        Promise::new(env::current_account_id()).function_call(
+            proposal.method_name,
+            proposal.args.0,
+            NO_DEPOSIT,
+            env::prepaid_gas() - env::used_gas() - COUNCIL_EXECUTE_RESERVED_GAS,
+        );
+    }
+
+    /// Withdraws an approval, dropping the proposal once nobody approves it.
+    pub fn council_withdraw_approval(&mut self, id: u64) {
+        self.assert_council_member();
+        let account_id = env::predecessor_account_id();
+        let index = self.internal_council_proposal_index(id);
+        let approvals = &mut self.council_proposals[index].approvals;
+        let approval = approvals
+            .iter()
+            .position(|approver| approver == &account_id)
+            .expect("Not approved");
+        approvals.remove(approval);
+        if approvals.is_empty() {
+            self.council_proposals.remove(index);
+        }
+    }
+
+    pub fn get_council(&self) -> Option<Council> {
+        self.council.clone()
+    }
+
+    pub fn get_council_proposals(&self) -> Vec<CouncilProposal> {
+        self.council_proposals.clone()
+    }
+}
+
+impl Contract {
+    pub fn is_council_member(&self, account_id: &AccountId) -> bool {
+        self.council
+            .as_ref()
+            .map_or(false, |council| council.members.contains(account_id))
+    }
+
+    fn assert_council_member(&self) {
+        require!(
+            self.is_council_member(&env::predecessor_account_id()),
+            "Not a council member"
+        );
+    }
+
+    fn internal_council_proposal_index(&self, id: u64) -> usize {
+        self.council_proposals
+            .iter()
+            .position(|proposal| proposal.id == id)
+            .expect("Proposal is not found")
+    }
+}
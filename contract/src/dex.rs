@@ -0,0 +1,260 @@
+use super::*;
+
+/// Exchange a swap path is executed on. All take the path in the `ft_transfer_call` message.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum DexAdapter {
+    /// `ref_finance_contract_id`.
+    Ref,
+    Jumbo {
+        contract_id: AccountId,
+    },
+    /// REF DCL (v2) concentrated liquidity pools, `pool_ids` has the `token_x|token_y|fee`
+    /// pool of each hop and replaces the `pool_id` of the actions. Only the output of the
+    /// last hop is protected, DCL doesn't take minimum amounts for the others.
+    RefDcl {
+        contract_id: AccountId,
+        pool_ids: Vec<String>,
+    },
+}
+
+/// Interface of REF DCL (v2)
+#[ext_contract(ext_ref_dcl)]
+pub trait RefDclContract {
+    fn get_pool(&self, pool_id: String);
+    fn quote(
+        &self,
+        pool_ids: Vec<String>,
+        input_token: AccountId,
+        output_token: AccountId,
+        input_amount: U128,
+        tag: Option<String>,
+    );
+}
+
+/// Pool info of REF and Jumbo, or of a DCL pool.
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde", untagged)]
+pub enum DexPoolInfo {
+    Ref {
+        token_account_ids: Vec<AccountId>,
+    },
+    Dcl {
+        token_x: AccountId,
+        token_y: AccountId,
+    },
+}
+
+impl DexPoolInfo {
+    pub fn trades(&self, action: &Action) -> bool {
+        let tokens = match self {
+            DexPoolInfo::Ref { token_account_ids } => token_account_ids.clone(),
+            DexPoolInfo::Dcl { token_x, token_y } => vec![token_x.clone(), token_y.clone()],
+        };
+        tokens.contains(&action.token_in) && tokens.contains(&action.token_out)
+    }
+}
+
+/// Output of a hop quote, a plain amount from `get_return` or a DCL quote.
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde", untagged)]
+pub enum DexQuote {
+    Amount(U128),
+    Dcl { amount: U128 },
+}
+
+impl DexQuote {
+    pub fn amount(&self) -> U128 {
+        match self {
+            DexQuote::Amount(amount) | DexQuote::Dcl { amount } => *amount,
+        }
+    }
+}
+
+/// Jumbo still expects the `force` flag of the REF version it was forked from.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct JumboArgs {
+    force: u8,
+    actions: Vec<Action>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+enum DclArgs {
+    Swap {
+        pool_ids: Vec<String>,
+        output_token: AccountId,
+        min_output_amount: U128,
+    },
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Executes the swap path at `path_index`, 0 for `swap_path` and `i` for
+    /// `fallback_swap_paths[i - 1]`, on the given exchange. Paths default to REF.
+    pub fn set_swap_path_dex(&mut self, path_index: u32, dex: DexAdapter) -> Promise {
+        self.assert_owner_or_timelock();
+        require!(
+            path_index as usize <= self.fallback_swap_paths.len(),
+            "Swap path is not found"
+        );
+        if let DexAdapter::RefDcl { pool_ids, .. } = &dex {
+            let swap_path = self.internal_swap_path_at(path_index);
+            require!(
+                pool_ids.len() == swap_path.len(),
+                "Expected one DCL pool per hop"
+            );
+            for (pool_id, action) in pool_ids.iter().zip(swap_path) {
+                assert_dcl_pool_trades(pool_id, action);
+            }
+        }
+        let path_index = path_index as usize;
+        if self.swap_path_dexes.len() <= path_index {
+            self.swap_path_dexes.resize(path_index + 1, DexAdapter::Ref);
+        }
+        self.swap_path_dexes[path_index] = dex;
+        Event::ConfigChanged {
+            field: "swap_path_dexes",
+        }
+        .emit();
+        self.internal_register_storage()
+    }
+
+    pub fn get_swap_path_dexes(&self) -> Vec<DexAdapter> {
+        (0..=self.fallback_swap_paths.len())
+            .map(|path_index| self.internal_path_dex(path_index as u32).clone())
+            .collect()
+    }
+}
+
+impl Contract {
+    pub fn internal_path_dex(&self, path_index: u32) -> &DexAdapter {
+        self.swap_path_dexes
+            .get(path_index as usize)
+            .unwrap_or(&DexAdapter::Ref)
+    }
+
+    pub fn internal_dex_contract_id(&self, dex: &DexAdapter) -> AccountId {
+        match dex {
+            DexAdapter::Ref => self.ref_finance_contract_id.clone(),
+            DexAdapter::Jumbo { contract_id } | DexAdapter::RefDcl { contract_id, .. } => {
+                contract_id.clone()
+            }
+        }
+    }
+
+    /// Receiver and message of the `ft_transfer_call` swapping through `actions`.
+    pub fn internal_dex_swap_call(
+        &self,
+        dex: &DexAdapter,
+        actions: Vec<Action>,
+    ) -> (AccountId, String) {
+        let msg = match dex {
+            DexAdapter::Ref => serde_json::to_string(&RefArgs {
+                actions,
+                referral_id: self.referral_id.clone(),
+            }),
+            DexAdapter::Jumbo { .. } => serde_json::to_string(&JumboArgs { force: 0, actions }),
+            DexAdapter::RefDcl { pool_ids, .. } => {
+                let last_action = actions.last().unwrap();
+                serde_json::to_string(&DclArgs::Swap {
+                    pool_ids: pool_ids.clone(),
+                    output_token: last_action.token_out.clone(),
+                    min_output_amount: last_action.min_amount_out,
+                })
+            }
+        };
+        (self.internal_dex_contract_id(dex), msg.unwrap())
+    }
+
+    /// Exchanges the contract uses besides REF, to register storage with.
+    pub fn internal_extra_dex_contract_ids(&self) -> Vec<AccountId> {
+        let mut contract_ids: Vec<AccountId> = vec![];
+        for dex in &self.swap_path_dexes {
+            match dex {
+                DexAdapter::Ref => {}
+                DexAdapter::Jumbo { contract_id } | DexAdapter::RefDcl { contract_id, .. } => {
+                    if !contract_ids.contains(contract_id) {
+                        contract_ids.push(contract_id.clone());
+                    }
+                }
+            }
+        }
+        contract_ids
+    }
+
+    /// Reads the pool of the hop at `hop_index` of the path at `path_index`.
+    pub fn internal_dex_get_pool(&self, path_index: u32, hop_index: usize, gas: Gas) -> Promise {
+        let dex = self.internal_path_dex(path_index);
+        let contract_id = self.internal_dex_contract_id(dex);
+        match dex {
+            DexAdapter::RefDcl { pool_ids, .. } => {
+                ext_ref_dcl::get_pool(pool_ids[hop_index].clone(), contract_id, NO_DEPOSIT, gas)
+            }
+            _ => ext_ref_finance::get_pool(
+                self.internal_swap_path_at(path_index)[hop_index].pool_id,
+                contract_id,
+                NO_DEPOSIT,
+                gas,
+            ),
+        }
+    }
+
+    /// Quotes `amount_in` through the hop at `hop_index` of the path at `path_index`.
+    pub fn internal_dex_quote_hop(
+        &self,
+        path_index: u32,
+        hop_index: usize,
+        amount_in: Balance,
+        gas: Gas,
+    ) -> Promise {
+        let dex = self.internal_path_dex(path_index);
+        let contract_id = self.internal_dex_contract_id(dex);
+        let action = &self.internal_swap_path_at(path_index)[hop_index];
+        match dex {
+            DexAdapter::RefDcl { pool_ids, .. } => ext_ref_dcl::quote(
+                vec![pool_ids[hop_index].clone()],
+                action.token_in.clone(),
+                action.token_out.clone(),
+                amount_in.into(),
+                None,
+                contract_id,
+                NO_DEPOSIT,
+                gas,
+            ),
+            _ => ext_ref_finance::get_return(
+                action.pool_id,
+                action.token_in.clone(),
+                amount_in.into(),
+                action.token_out.clone(),
+                contract_id,
+                NO_DEPOSIT,
+                gas,
+            ),
+        }
+    }
+
+    /// Drops a DCL selection of the path at `path_index`, its pools belong to the previous path.
+    pub fn internal_reset_dcl_dex(&mut self, path_index: u32) {
+        if let Some(dex) = self.swap_path_dexes.get_mut(path_index as usize) {
+            if matches!(dex, DexAdapter::RefDcl { .. }) {
+                *dex = DexAdapter::Ref;
+            }
+        }
+    }
+}
+
+/// DCL pool ids are `token_x|token_y|fee`.
+fn assert_dcl_pool_trades(pool_id: &str, action: &Action) {
+    let parts: Vec<&str> = pool_id.split('|').collect();
+    require!(
+        parts.len() == 3 && parts[2].parse::<u32>().is_ok(),
+        format!("Invalid DCL pool id {}", pool_id)
+    );
+    let tokens = [parts[0], parts[1]];
+    require!(
+        tokens.contains(&action.token_in.as_str()) && tokens.contains(&action.token_out.as_str()),
+        format!("DCL pool {} doesn't trade the configured tokens", pool_id)
+    );
+}
@@ -0,0 +1,105 @@
+use super::*;
+
+const ON_DIRECT_WNEAR_FARMED_GAS: Gas = Gas(10_000_000_000_000);
+
+#[ext_contract(ext_self_direct_wnear)]
+pub trait ExtDirectWnear {
+    fn on_direct_wnear_farmed(
+        &mut self,
+        #[callback_result] used_amount: Result<U128, PromiseError>,
+        amount: U128,
+        from_buffer: U128,
+        farm_id: u64,
+    );
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Farms the rewards as wNEAR on the wNEAR farm `farm_id` of the staking pool instead of
+    /// swapping them, `None` goes back to swapping. Price updates are rejected meanwhile.
+    pub fn set_direct_wnear_farm(&mut self, farm_id: Option<u64>) -> Promise {
+        self.assert_owner_or_timelock();
+        self.direct_wnear_farm_id = farm_id;
+        Event::ConfigChanged {
+            field: "direct_wnear_farm_id",
+        }
+        .emit();
+        self.internal_register_storage()
+    }
+
+    /// Wraps the reward due now and adds it to the wNEAR farm, without oracle or swap. Queued
+    /// chunks of earlier rewards are farmed even when no new reward is due.
+    pub fn distribute_wnear(&mut self) -> Promise {
+        self.assert_not_paused();
+        let farm_id = self
+            .direct_wnear_farm_id
+            .expect("Direct wNEAR farming is disabled");
+        require!(
+            !self.distribution_in_flight,
+            "A distribution is already in flight"
+        );
+        let (amount, payout_promise) = self.internal_take_distribution();
+        if amount == 0 {
+            return payout_promise.unwrap();
+        }
+        self.distribution_in_flight = true;
+        self.internal_set_pipeline_stage(PipelineStage::Distributing);
+        let msg = self.internal_farming_msg(Some(farm_id), None, self.farm_duration);
+        let from_buffer = std::cmp::min(self.wrapped_amount, amount);
+        self.internal_wrap_near(amount)
+            .function_call(
+                "ft_transfer_call".to_string(),
+                serde_json::to_vec(&FtTransferCallArgs {
+                    receiver_id: self.staking_pool_account_id.clone(),
+                    amount: amount.into(),
+                    msg,
+                })
+                .unwrap(),
+                ONE_YOCTO,
+                self.gas.add_farm,
+            )
+            .then(ext_self_direct_wnear::on_direct_wnear_farmed(
+                amount.into(),
+                from_buffer.into(),
+                farm_id,
+                env::current_account_id(),
+                NO_DEPOSIT,
+                ON_DIRECT_WNEAR_FARMED_GAS,
+            ))
+    }
+
+    /// A failed batch reverts `near_deposit` as well, so only the part taken `from_buffer`
+    /// is still wNEAR. Refunds of the farm are wNEAR.
+    #[private]
+    pub fn on_direct_wnear_farmed(
+        &mut self,
+        #[callback_result] used_amount: Result<U128, PromiseError>,
+        amount: U128,
+        from_buffer: U128,
+        farm_id: u64,
+    ) {
+        self.distribution_in_flight = false;
+        self.internal_end_distribution_stage();
+        let used_amount = match used_amount {
+            Ok(used_amount) => used_amount.0,
+            Err(_) => {
+                self.internal_revert_wrap_near(amount.0, from_buffer.0);
+                self.available_rewards += amount.0;
+                self.internal_record_error("direct_wnear", "farm_failed", amount.0);
+                return;
+            }
+        };
+        // Refunded wNEAR goes back to the buffer for the next distribution.
+        let refunded_amount = amount.0 - used_amount;
+        self.wrapped_amount += refunded_amount;
+        self.available_rewards += refunded_amount;
+        if used_amount > 0 {
+            self.wnear_distributed += used_amount;
+            Event::WnearDistributed {
+                amount: used_amount.into(),
+                farm_id,
+            }
+            .emit();
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use super::*;
+
+/// NEAR moved by one price triggered distribution.
+pub struct DistributionStep {
+    /// Reward released from `available_rewards`.
+    pub released: Balance,
+    /// Share of `released` paid out as NEAR.
+    pub near_payout: Balance,
+    /// NEAR swapped, including queued chunks of earlier rewards.
+    pub swap_amount: Balance,
+    /// NEAR left queued for the next distributions.
+    pub queued_swap_amount: Balance,
+}
+
+/// Splits `near_reward` into the NEAR payout and the swap share, and takes the next chunk
+/// of at most `max_swap_chunk`, 0 for no limit, from the swap share and `queued_swap_amount`.
+/// Shared by the distributions, the schedule and the simulation.
+pub fn distribution_step(
+    near_reward: Balance,
+    queued_swap_amount: Balance,
+    swap_fraction_bps: u32,
+    max_swap_chunk: Balance,
+) -> DistributionStep {
+    let released_swap_amount = u128_ratio(near_reward, swap_fraction_bps as u128, MAX_BPS as u128);
+    let queued = queued_swap_amount + released_swap_amount;
+    let swap_amount = if max_swap_chunk > 0 {
+        std::cmp::min(queued, max_swap_chunk)
+    } else {
+        queued
+    };
+    DistributionStep {
+        released: near_reward,
+        near_payout: near_reward - released_swap_amount,
+        swap_amount,
+        queued_swap_amount: queued - swap_amount,
+    }
+}
+
+/// NEAR to deposit for wNEAR so the `wrapped_amount` buffer covers `amount`, plus a
+/// yoctoNEAR to cover rounding. 0 when the buffer covers it already.
+pub fn wrap_amount(amount: Balance, wrapped_amount: Balance) -> Balance {
+    if wrapped_amount >= amount {
+        0
+    } else {
+        amount - wrapped_amount + 1
+    }
+}
+
+impl Contract {
+    /// The distribution the reward due now leads to.
+    pub fn internal_distribution_step(&self) -> DistributionStep {
+        distribution_step(
+            self.get_near_reward_for_distribution().0,
+            self.queued_swap_amount,
+            self.swap_fraction_bps,
+            self.max_swap_chunk,
+        )
+    }
+
+    /// Releases the reward due now, pays out its NEAR share and takes the next swap chunk.
+    /// Queued chunks of earlier rewards are swapped even when no new reward is due.
+    /// Returns the NEAR to swap and the payout promise, if any.
+    pub fn internal_take_distribution(&mut self) -> (Balance, Option<Promise>) {
+        let step = self.internal_distribution_step();
+        require!(
+            step.released > 0 || step.swap_amount > 0,
+            "Nothing to distribute"
+        );
+        let payout_promise = if step.released > 0 {
+            self.internal_release_reward(step.released, step.near_payout)
+        } else {
+            None
+        };
+        self.queued_swap_amount = step.queued_swap_amount;
+        (step.swap_amount, payout_promise)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distribution_step() {
+        let step = distribution_step(1000, 0, 2500, 0);
+        assert_eq!(step.released, 1000);
+        assert_eq!(step.near_payout, 750);
+        assert_eq!(step.swap_amount, 250);
+        assert_eq!(step.queued_swap_amount, 0);
+
+        let step = distribution_step(1000, 0, MAX_BPS, 0);
+        assert_eq!(step.near_payout, 0);
+        assert_eq!(step.swap_amount, 1000);
+    }
+
+    #[test]
+    fn test_distribution_step_chunks() {
+        let step = distribution_step(1000, 100, 5000, 200);
+        assert_eq!(step.near_payout, 500);
+        assert_eq!(step.swap_amount, 200);
+        assert_eq!(step.queued_swap_amount, 400);
+
+        // Queued chunks are swapped without a new reward.
+        let step = distribution_step(0, 400, 5000, 200);
+        assert_eq!(step.released, 0);
+        assert_eq!(step.swap_amount, 200);
+        assert_eq!(step.queued_swap_amount, 200);
+    }
+
+    #[test]
+    fn test_wrap_amount() {
+        assert_eq!(wrap_amount(100, 150), 0);
+        assert_eq!(wrap_amount(100, 100), 0);
+        assert_eq!(wrap_amount(100, 40), 61);
+        assert_eq!(wrap_amount(100, 0), 101);
+    }
+}
@@ -0,0 +1,145 @@
+use super::*;
+
+const MAX_DISTRIBUTION_WINDOWS: usize = 14;
+const HOURS_PER_WEEK: u64 = 7 * 24;
+const ONE_HOUR: Duration = 60 * 60 * 1_000_000_000;
+/// 1970-01-01 was a Thursday, 3 days after the start of its week.
+const EPOCH_WEEKDAY: u64 = 3;
+
+/// A weekly recurring time span in UTC during which price triggered distributions run.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DistributionWindow {
+    /// 0 is Monday, 6 is Sunday.
+    pub weekday: u8,
+    pub start_hour: u8,
+    /// Length of the window, it can extend into the next days.
+    pub duration_hours: u8,
+}
+
+impl DistributionWindow {
+    fn assert_valid(&self) {
+        require!(
+            self.weekday < 7 && self.start_hour < 24,
+            "Invalid window start"
+        );
+        require!(
+            self.duration_hours > 0 && u64::from(self.duration_hours) <= HOURS_PER_WEEK,
+            "Invalid window duration"
+        );
+    }
+
+    fn contains(&self, timestamp: Timestamp) -> bool {
+        let hour_of_week = (timestamp / ONE_HOUR + EPOCH_WEEKDAY * 24) % HOURS_PER_WEEK;
+        let start = u64::from(self.weekday) * 24 + u64::from(self.start_hour);
+        (hour_of_week + HOURS_PER_WEEK - start) % HOURS_PER_WEEK < u64::from(self.duration_hours)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Limits price triggered distributions to the given weekly windows, an empty list
+    /// allows them at any time.
+    pub fn set_distribution_windows(&mut self, distribution_windows: Vec<DistributionWindow>) {
+        self.assert_owner();
+        require!(
+            distribution_windows.len() <= MAX_DISTRIBUTION_WINDOWS,
+            "Too many distribution windows"
+        );
+        for window in &distribution_windows {
+            window.assert_valid();
+        }
+        self.distribution_windows = distribution_windows;
+        Event::ConfigChanged {
+            field: "distribution_windows",
+        }
+        .emit();
+    }
+
+    pub fn get_distribution_windows(&self) -> Vec<DistributionWindow> {
+        self.distribution_windows.clone()
+    }
+
+    pub fn is_in_distribution_window(&self) -> bool {
+        self.internal_in_distribution_window(env::block_timestamp())
+    }
+}
+
+impl Contract {
+    fn internal_in_distribution_window(&self, timestamp: Timestamp) -> bool {
+        self.distribution_windows.is_empty()
+            || self
+                .distribution_windows
+                .iter()
+                .any(|window| window.contains(timestamp))
+    }
+
+    /// First time from `timestamp` on within the distribution windows, `None` if there is
+    /// none before `until`. Windows start on full hours.
+    pub fn internal_next_window_time(
+        &self,
+        timestamp: Timestamp,
+        until: Timestamp,
+    ) -> Option<Timestamp> {
+        let mut time = timestamp;
+        while time < until {
+            if self.internal_in_distribution_window(time) {
+                return Some(time);
+            }
+            time = (time / ONE_HOUR + 1) * ONE_HOUR;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(weekday: u8, start_hour: u8, duration_hours: u8) -> DistributionWindow {
+        DistributionWindow {
+            weekday,
+            start_hour,
+            duration_hours,
+        }
+    }
+
+    #[test]
+    fn test_epoch_is_thursday() {
+        let thursday = window(3, 0, 1);
+        assert!(thursday.contains(0));
+        assert!(thursday.contains(ONE_HOUR - 1));
+        assert!(!thursday.contains(ONE_HOUR));
+        assert!(thursday.contains(HOURS_PER_WEEK * ONE_HOUR));
+        assert!(!window(2, 23, 1).contains(0));
+    }
+
+    #[test]
+    fn test_window_into_next_week() {
+        // Sunday 22:00 until Monday 02:00.
+        let sunday_night = window(6, 22, 4);
+        let sunday = 3 * 24 * ONE_HOUR;
+        assert!(!sunday_night.contains(sunday + 21 * ONE_HOUR));
+        assert!(sunday_night.contains(sunday + 22 * ONE_HOUR));
+        assert!(sunday_night.contains(sunday + 25 * ONE_HOUR));
+        assert!(!sunday_night.contains(sunday + 26 * ONE_HOUR));
+    }
+
+    #[test]
+    fn test_whole_week() {
+        let always = window(0, 0, HOURS_PER_WEEK as u8);
+        assert!((0..HOURS_PER_WEEK).all(|hour| always.contains(hour * ONE_HOUR)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid window start")]
+    fn test_invalid_weekday() {
+        window(7, 0, 1).assert_valid();
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid window duration")]
+    fn test_empty_window() {
+        window(0, 0, 0).assert_valid();
+    }
+}
@@ -0,0 +1,260 @@
+use super::*;
+use near_sdk::json_types::{Base64VecU8, I128};
+
+const EVENT_STANDARD: &str = "staking-pool-owner";
+const EVENT_STANDARD_VERSION: &str = "1.0.0";
+
+/// NEP-297 events emitted by the contract.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum Event {
+    RewardWithdrawn {
+        amount: U128,
+        owner_fee: U128,
+    },
+    NothingToDistribute,
+    OracleReportRecorded {
+        reports: u8,
+        min_reports: u8,
+    },
+    SwapExecuted {
+        near_amount: U128,
+        min_amount_out: U128,
+        amount_out: U128,
+    },
+    SwapFailed {
+        near_amount: U128,
+        reason: &'static str,
+    },
+    SwapExpired {
+        near_amount: U128,
+        delay_blocks: u64,
+    },
+    SwapPathQuoted {
+        probe_amount: U128,
+        amount_out: U128,
+    },
+    SwapPathSwitched {
+        index: u32,
+    },
+    SlippageEscalated {
+        failures: u32,
+        escalation: SlippageEscalation,
+    },
+    DistributionCommitted {
+        hash: Base64VecU8,
+    },
+    DistributionRevealed {
+        amount: U128,
+        min_amount_out: U128,
+    },
+    SwapQuoteAccepted {
+        nonce: u64,
+        amount_in: U128,
+        min_amount_out: U128,
+    },
+    RewardTokensDistributed {
+        amount: U128,
+        /// `None` when the tokens create a new farm.
+        farm_id: Option<u64>,
+    },
+    RewardTokensHeldBack {
+        amount: U128,
+        reason: &'static str,
+    },
+    BuybackExecuted {
+        reward_amount: U128,
+    },
+    TokensBurned {
+        token_id: AccountId,
+        amount: U128,
+    },
+    WnearDistributed {
+        amount: U128,
+        farm_id: u64,
+    },
+    CouncilProposed {
+        id: u64,
+        method_name: String,
+    },
+    CouncilApproved {
+        id: u64,
+        account_id: AccountId,
+        approvals: u8,
+    },
+    CouncilExecuted {
+        id: u64,
+    },
+    ChangeProposed {
+        id: u64,
+        method_name: String,
+        executable_at: U64,
+    },
+    ChangeExecuted {
+        id: u64,
+    },
+    ChangeCancelled {
+        id: u64,
+    },
+    FarmEnded {
+        farm_id: u64,
+    },
+    FarmRotated {
+        farm_id: u64,
+    },
+    StakingPoolChangeStarted {
+        staking_pool_account_id: AccountId,
+    },
+    StakingPoolChanged {
+        old_staking_pool_account_id: AccountId,
+        staking_pool_account_id: AccountId,
+    },
+    FarmKickstarted {
+        token_id: AccountId,
+        amount: U128,
+        farm_id: u64,
+    },
+    TreasuryPaid {
+        receiver_id: AccountId,
+        amount: U128,
+    },
+    Restaked {
+        amount: U128,
+    },
+    RestakeReturned {
+        amount: U128,
+    },
+    NearPaidOut {
+        receiver_id: AccountId,
+        amount: U128,
+    },
+    KeeperRewarded {
+        keeper_id: AccountId,
+        amount: U128,
+    },
+    OwnerFeesClaimed {
+        amount: U128,
+    },
+    FtRescued {
+        token_id: AccountId,
+        receiver_id: AccountId,
+        amount: U128,
+    },
+    NearSwept {
+        receiver_id: AccountId,
+        amount: U128,
+    },
+    RefDepositReclaimed {
+        token_id: AccountId,
+        amount: U128,
+    },
+    BurrowDeposited {
+        amount: U128,
+    },
+    BurrowWithdrawn {
+        amount: U128,
+        interest: U128,
+    },
+    LiquidStakingCollectionSkipped {
+        reason: &'static str,
+    },
+    OutputTokenFarmed {
+        token_id: AccountId,
+        amount: U128,
+        farm_id: u64,
+    },
+    Donation {
+        donor_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+    },
+    DonationVestingStarted {
+        donor_id: AccountId,
+        amount: U128,
+        end_date: U64,
+    },
+    TokenDonation {
+        donor_id: AccountId,
+        token_id: AccountId,
+        amount: U128,
+    },
+    AccountingSnapshot {
+        rewards_received: U128,
+        near_donated: U128,
+        burrow_interest: U128,
+        available_rewards: U128,
+        wrapped_amount: U128,
+        rounding_adjustments: U128,
+        queued_swap_amount: U128,
+        vesting_amount: U128,
+        near_swapping: U128,
+        near_swapped: U128,
+        near_paid_out: U128,
+        wnear_distributed: U128,
+        owner_fees_accrued: U128,
+        owner_fees_claimed: U128,
+        keeper_rewards_paid: U128,
+        near_restaked: U128,
+        near_swept: U128,
+        burrow_deposited: U128,
+        reward_tokens_swapped: U128,
+        reward_tokens_distributed: U128,
+        held_reward_tokens: U128,
+    },
+    Reconciled {
+        wnear_balance: U128,
+        unexplained: I128,
+    },
+    StorageRegistered {
+        contract_id: AccountId,
+        account_id: AccountId,
+    },
+    StorageRegistrationFailed {
+        contract_id: AccountId,
+        account_id: AccountId,
+    },
+    RefStorageChecked {
+        registered: bool,
+        available: U128,
+    },
+    ConfigChanged {
+        field: &'static str,
+    },
+    OperatorAdded {
+        account_id: AccountId,
+    },
+    OperatorRemoved {
+        account_id: AccountId,
+    },
+    Paused,
+    Unpaused,
+    PoolActionProposed {
+        action: PoolAdminAction,
+        executable_at: U64,
+    },
+    PoolActionCancelled,
+    PoolActionExecuted {
+        action: PoolAdminAction,
+    },
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct EventLog<'a> {
+    standard: &'static str,
+    version: &'static str,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+impl Event {
+    pub fn emit(&self) {
+        let log = EventLog {
+            standard: EVENT_STANDARD,
+            version: EVENT_STANDARD_VERSION,
+            event: self,
+        };
+        log!("EVENT_JSON:{}", serde_json::to_string(&log).unwrap());
+    }
+}
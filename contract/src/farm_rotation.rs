@@ -0,0 +1,172 @@
+use super::*;
+
+const FARM_READ_GAS: Gas = Gas(10_000_000_000_000);
+const ON_ACTIVE_FARMS_GAS: Gas = Gas(10_000_000_000_000);
+/// Gas of the farm check `ping` starts on the side.
+pub const FARM_CHECK_GAS: Gas = Gas(FARM_READ_GAS.0 + ON_ACTIVE_FARMS_GAS.0);
+
+/// What happens when the farm topped up by reward token distributions ends.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum FarmRotation {
+    /// Keep topping up `farm_id`.
+    Disabled,
+    /// Switch to the first of these farm IDs, consuming it.
+    NextFarmIds(Vec<u64>),
+    /// Create a new farm with the next distribution and switch to it.
+    CreateFarm { name: String },
+}
+
+/// A farm of the staking pool, as returned by `get_active_farms`.
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StakingPoolFarm {
+    pub farm_id: u64,
+    pub token_id: AccountId,
+    pub end_date: U64,
+}
+
+/// A farm the last farm check found running, with the end date it reported.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone, Copy)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ValidatedFarm {
+    pub farm_id: u64,
+    pub end_date: U64,
+}
+
+#[ext_contract(ext_staking_farm)]
+pub trait StakingFarm {
+    fn get_active_farms(&self);
+}
+
+#[ext_contract(ext_self_farm_rotation)]
+pub trait ExtFarmRotation {
+    fn on_active_farms(&mut self, #[callback] farms: Vec<StakingPoolFarm>);
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn set_farm_rotation(&mut self, farm_rotation: FarmRotation) {
+        self.assert_owner();
+        if let FarmRotation::CreateFarm { name } = &farm_rotation {
+            require!(!name.is_empty(), "Missing farm name");
+        }
+        self.farm_rotation = farm_rotation;
+        self.create_farm = false;
+        Event::ConfigChanged {
+            field: "farm_rotation",
+        }
+        .emit();
+    }
+
+    /// Holds back reward tokens while `farm_id` isn't a running farm of the reward token,
+    /// instead of sending them to a missing or ended farm.
+    pub fn set_validate_farm(&mut self, validate_farm: bool) {
+        self.assert_owner();
+        self.validate_farm = validate_farm;
+        Event::ConfigChanged {
+            field: "validate_farm",
+        }
+        .emit();
+    }
+
+    /// Validates `farm_id` and rotates it if it has ended. Also done with every `ping`.
+    pub fn check_farm(&mut self) -> Promise {
+        require!(
+            self.validate_farm || self.farm_rotation != FarmRotation::Disabled,
+            "Farm validation and rotation are disabled"
+        );
+        self.internal_check_farm()
+    }
+
+    #[private]
+    pub fn on_active_farms(&mut self, #[callback] farms: Vec<StakingPoolFarm>) {
+        let timestamp = env::block_timestamp();
+        let farm_id = self.farm_id;
+        let reward_token_id = &self.reward_token_id;
+        let running_farms: Vec<ValidatedFarm> = farms
+            .iter()
+            .filter(|farm| &farm.token_id == reward_token_id && farm.end_date.0 > timestamp)
+            .map(|farm| ValidatedFarm {
+                farm_id: farm.farm_id,
+                end_date: farm.end_date,
+            })
+            .collect();
+        let running_farm = |farm_id: u64| {
+            running_farms
+                .iter()
+                .copied()
+                .find(|farm| farm.farm_id == farm_id)
+        };
+        if let Some(farm) = running_farm(farm_id) {
+            self.validated_farm = Some(farm);
+            return;
+        }
+        // Also emitted for a missing farm or a farm of another token.
+        Event::FarmEnded { farm_id }.emit();
+        let next_farm_id = match &mut self.farm_rotation {
+            FarmRotation::Disabled => None,
+            FarmRotation::NextFarmIds(farm_ids) if farm_ids.is_empty() => None,
+            FarmRotation::NextFarmIds(farm_ids) => Some(farm_ids.remove(0)),
+            // A newer running farm is the one created by the last distribution.
+            FarmRotation::CreateFarm { .. } => {
+                let created_farm_id = running_farms
+                    .iter()
+                    .map(|farm| farm.farm_id)
+                    .filter(|&running_farm_id| running_farm_id > farm_id)
+                    .max();
+                self.create_farm = created_farm_id.is_none();
+                if let Some(created_farm_id) = created_farm_id {
+                    self.internal_assign_created_farm_id(created_farm_id);
+                }
+                created_farm_id
+            }
+        };
+        self.validated_farm = next_farm_id.and_then(running_farm);
+        if let Some(next_farm_id) = next_farm_id {
+            self.farm_id = next_farm_id;
+            Event::FarmRotated {
+                farm_id: next_farm_id,
+            }
+            .emit();
+        }
+    }
+}
+
+impl Contract {
+    pub fn internal_check_farm(&self) -> Promise {
+        ext_staking_farm::get_active_farms(
+            self.staking_pool_account_id.clone(),
+            NO_DEPOSIT,
+            FARM_READ_GAS,
+        )
+        .then(ext_self_farm_rotation::on_active_farms(
+            env::current_account_id(),
+            NO_DEPOSIT,
+            ON_ACTIVE_FARMS_GAS,
+        ))
+    }
+
+    /// Whether `farm_id` is the farm found running by the last farm check and hasn't ended
+    /// since, counting the end date of top-ups after the check.
+    pub fn internal_farm_valid(&self) -> bool {
+        let validated_farm = match self.validated_farm {
+            Some(farm) if farm.farm_id == self.farm_id => farm,
+            _ => return false,
+        };
+        let end_date = self
+            .internal_farm_schedule(Some(self.farm_id))
+            .map_or(validated_farm.end_date.0, |schedule| {
+                std::cmp::max(validated_farm.end_date.0, schedule.end_date.0)
+            });
+        env::block_timestamp() < end_date
+    }
+
+    /// Name of the farm created when `farm_id` is `None`.
+    pub fn internal_new_farm_name(&self) -> Option<String> {
+        match &self.farm_rotation {
+            FarmRotation::CreateFarm { name } => Some(name.clone()),
+            _ => None,
+        }
+    }
+}
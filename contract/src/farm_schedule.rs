@@ -0,0 +1,213 @@
+use super::*;
+
+/// Reward tokens last added to the main farm, to estimate what's left of them at a top-up.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone, Copy)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FarmSchedule {
+    /// `None` for a created farm until rotation picks up its ID.
+    pub farm_id: Option<u64>,
+    /// Including what was left of the previous top-up.
+    pub amount: U128,
+    pub start_date: U64,
+    pub end_date: U64,
+}
+
+impl FarmSchedule {
+    fn remaining(&self, timestamp: Timestamp) -> Balance {
+        if timestamp >= self.end_date.0 {
+            0
+        } else if timestamp <= self.start_date.0 {
+            self.amount.0
+        } else {
+            u128_ratio(
+                self.amount.0,
+                u128::from(self.end_date.0 - timestamp),
+                u128::from(self.end_date.0 - self.start_date.0),
+            )
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FarmStatus {
+    pub farm_id: u64,
+    pub end_date: Option<U64>,
+    pub remaining_sec: DurationSec,
+    pub top_up_due: bool,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Starts the farm of the next reward token distribution at `start_date` (nanoseconds)
+    /// instead of right away. Its duration counts from there.
+    pub fn set_next_farm_start_date(&mut self, start_date: Option<U64>) {
+        self.assert_owner();
+        self.internal_set_next_farm_start_date(start_date.map(|start_date| start_date.0));
+    }
+
+    /// Caps the reward tokens per second of the farm after a distribution, counting what's left
+    /// of the previous top-up. Longer farms up to `max_farm_duration` are used to stay below it,
+    /// the rest is held back. 0 disables the cap.
+    pub fn set_max_reward_rate(&mut self, max_reward_rate: U128) {
+        self.assert_owner_or_timelock();
+        self.max_reward_rate = max_reward_rate.0;
+        Event::ConfigChanged {
+            field: "max_reward_rate",
+        }
+        .emit();
+    }
+
+    /// A top-up of the main farm is due once it has less than `farm_top_up_window_sec` left.
+    pub fn set_farm_top_up_window(&mut self, farm_top_up_window_sec: DurationSec) {
+        self.assert_owner_or_timelock();
+        self.farm_top_up_window = u64::from(farm_top_up_window_sec) * 10u64.pow(9);
+        Event::ConfigChanged {
+            field: "farm_top_up_window",
+        }
+        .emit();
+    }
+
+    /// Holds back reward tokens instead of topping up the main farm before its top-up is due,
+    /// so its end date is only pushed within `farm_top_up_window` of it.
+    pub fn set_require_top_up_due(&mut self, require_top_up_due: bool) {
+        self.assert_owner_or_timelock();
+        self.require_top_up_due = require_top_up_due;
+        Event::ConfigChanged {
+            field: "require_top_up_due",
+        }
+        .emit();
+    }
+
+    pub fn get_farm_schedule(&self) -> Option<FarmSchedule> {
+        self.farm_schedule
+    }
+
+    /// End of the main farm as last set by this contract. Without a tracked end date, e.g.
+    /// after the farm ID was changed, the top-up is due.
+    pub fn get_farm_status(&self) -> FarmStatus {
+        let timestamp = env::block_timestamp();
+        let end_date = self
+            .internal_farm_schedule(Some(self.farm_id))
+            .map(|schedule| schedule.end_date.0);
+        let remaining = end_date.map_or(0, |end_date| end_date.saturating_sub(timestamp));
+        FarmStatus {
+            farm_id: self.farm_id,
+            end_date: end_date.map(U64),
+            remaining_sec: (remaining / 10u64.pow(9)) as DurationSec,
+            top_up_due: remaining <= self.farm_top_up_window,
+        }
+    }
+}
+
+impl Contract {
+    pub fn internal_set_next_farm_start_date(&mut self, start_date: Option<Timestamp>) {
+        if let Some(start_date) = start_date {
+            let timestamp = env::block_timestamp();
+            require!(
+                start_date > timestamp && start_date - timestamp <= self.max_farm_duration,
+                "Farm start date is out of bounds"
+            );
+        }
+        self.next_farm_start_date = start_date;
+        Event::ConfigChanged {
+            field: "next_farm_start_date",
+        }
+        .emit();
+    }
+
+    /// Schedule of the last top-up of `farm_id`, if it was the last farm topped up.
+    pub fn internal_farm_schedule(&self, farm_id: Option<u64>) -> Option<FarmSchedule> {
+        self.farm_schedule
+            .filter(|schedule| farm_id.is_some() && schedule.farm_id == farm_id)
+    }
+
+    /// Whether the tracked end of `farm_id` is within `farm_top_up_window`, or untracked.
+    pub fn internal_farm_top_up_due(&self, farm_id: Option<u64>) -> bool {
+        self.internal_farm_schedule(farm_id)
+            .map_or(true, |schedule| {
+                schedule.end_date.0.saturating_sub(env::block_timestamp())
+                    <= self.farm_top_up_window
+            })
+    }
+
+    /// Extends `farm_duration` from `start_date` so a top-up doesn't end `farm_id` earlier
+    /// than tracked.
+    pub fn internal_top_up_duration(
+        &self,
+        farm_id: Option<u64>,
+        start_date: Timestamp,
+        farm_duration: Duration,
+    ) -> Duration {
+        self.internal_farm_schedule(farm_id)
+            .map_or(farm_duration, |schedule| {
+                std::cmp::max(
+                    farm_duration,
+                    schedule.end_date.0.saturating_sub(start_date),
+                )
+            })
+    }
+
+    /// Tracks `amount` reward tokens added to `farm_id` until `end_date`.
+    pub fn internal_record_farm_top_up(
+        &mut self,
+        farm_id: Option<u64>,
+        amount: Balance,
+        start_date: Timestamp,
+        end_date: Timestamp,
+    ) {
+        let remaining = self
+            .internal_farm_schedule(farm_id)
+            .map_or(0, |schedule| schedule.remaining(env::block_timestamp()));
+        self.farm_schedule = Some(FarmSchedule {
+            farm_id,
+            amount: (remaining + amount).into(),
+            start_date: start_date.into(),
+            end_date: end_date.into(),
+        });
+    }
+
+    /// Gives a farm created by a distribution the ID rotation found for it.
+    pub fn internal_assign_created_farm_id(&mut self, farm_id: u64) {
+        if let Some(schedule) = self.farm_schedule.as_mut() {
+            if schedule.farm_id.is_none() {
+                schedule.farm_id = Some(farm_id);
+            }
+        }
+    }
+
+    /// Applies `max_reward_rate` to `amount` reward tokens added to `farm_id` from `start_date`
+    /// for `farm_duration` and tracks the top-up. Returns the amount to farm and the duration.
+    pub fn internal_limit_reward_rate(
+        &mut self,
+        farm_id: Option<u64>,
+        amount: Balance,
+        start_date: Timestamp,
+        farm_duration: Duration,
+    ) -> (Balance, Duration) {
+        let remaining = self
+            .internal_farm_schedule(farm_id)
+            .map_or(0, |schedule| schedule.remaining(env::block_timestamp()));
+        let max_reward_rate = self.max_reward_rate;
+        let max_amount = |farm_duration: Duration| {
+            max_reward_rate
+                .saturating_mul(u128::from(farm_duration / 10u64.pow(9)))
+                .saturating_sub(remaining)
+        };
+        let mut farm_duration = farm_duration;
+        let mut amount = amount;
+        if max_reward_rate > 0 && amount > max_amount(farm_duration) {
+            let required_sec = (amount + remaining + max_reward_rate - 1) / max_reward_rate;
+            let max_sec = u128::from(self.max_farm_duration / 10u64.pow(9));
+            let stretched = std::cmp::min(required_sec, max_sec) as u64 * 10u64.pow(9);
+            farm_duration = std::cmp::max(farm_duration, stretched);
+            let excess = amount.saturating_sub(max_amount(farm_duration));
+            if excess > 0 {
+                self.internal_hold_back_reward_tokens(excess, "max_reward_rate");
+                amount -= excess;
+            }
+        }
+        self.internal_record_farm_top_up(farm_id, amount, start_date, start_date + farm_duration);
+        (amount, farm_duration)
+    }
+}
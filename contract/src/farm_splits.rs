@@ -0,0 +1,237 @@
+use super::*;
+
+/// Each split is funded with its own `ft_transfer_call` from `distribute_farm_splits`.
+const MAX_FARM_SPLITS: usize = 3;
+const ON_FARM_SPLIT_FUNDED_GAS: Gas = Gas(10_000_000_000_000);
+
+/// A farm receiving a weighted share of the reward tokens besides `farm_id`.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FarmSplitConfig {
+    /// Staking farm contract, e.g. another staking pool.
+    pub contract_id: AccountId,
+    pub farm_id: u64,
+    pub weight: u32,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FarmSplit {
+    pub config: FarmSplitConfig,
+    /// Reward tokens waiting for `distribute_farm_splits`.
+    #[serde(with = "u128_dec_format")]
+    pub pending: Balance,
+    #[serde(with = "u128_dec_format")]
+    pub distributed: Balance,
+}
+
+/// A farm and its weight, as listed by `get_farms`.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FarmWeight {
+    pub contract_id: AccountId,
+    pub farm_id: u64,
+    pub weight: u32,
+    pub pending: U128,
+    pub distributed: U128,
+}
+
+#[ext_contract(ext_self_farm_splits)]
+pub trait ExtFarmSplits {
+    fn on_farm_split_funded(
+        &mut self,
+        #[callback_result] used_amount: Result<U128, PromiseError>,
+        contract_id: AccountId,
+        farm_id: u64,
+        amount: U128,
+    );
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Splits the reward tokens between `farm_id`, weighted by `farm_weight`, and the given
+    /// farms. Pending tokens of removed farms go to `farm_id` with the next distribution.
+    pub fn set_farm_splits(&mut self, farm_weight: u32, farm_splits: Vec<FarmSplitConfig>) {
+        self.assert_owner_or_timelock();
+        require!(farm_splits.len() <= MAX_FARM_SPLITS, "Too many farm splits");
+        let total_weight = farm_splits.iter().fold(farm_weight as u64, |total, split| {
+            total + split.weight as u64
+        });
+        require!(total_weight > 0, "Missing farm weights");
+        for (index, split) in farm_splits.iter().enumerate() {
+            require!(
+                farm_splits[..index].iter().all(|other| {
+                    other.contract_id != split.contract_id || other.farm_id != split.farm_id
+                }),
+                "Duplicate farm split"
+            );
+        }
+        let mut previous_splits = std::mem::take(&mut self.farm_splits);
+        for config in farm_splits {
+            let previous = previous_splits.iter().position(|split| {
+                split.config.contract_id == config.contract_id
+                    && split.config.farm_id == config.farm_id
+            });
+            let (pending, distributed) = match previous {
+                Some(index) => {
+                    let split = previous_splits.remove(index);
+                    (split.pending, split.distributed)
+                }
+                None => (0, 0),
+            };
+            self.farm_splits.push(FarmSplit {
+                config,
+                pending,
+                distributed,
+            });
+        }
+        let removed_pending: Balance = previous_splits.iter().map(|split| split.pending).sum();
+        if removed_pending > 0 {
+            self.internal_hold_back_reward_tokens(removed_pending, "farm_split_removed");
+        }
+        self.farm_weight = farm_weight;
+        Event::ConfigChanged {
+            field: "farm_splits",
+        }
+        .emit();
+        if !self.farm_splits.is_empty() {
+            self.internal_register_storage();
+        }
+    }
+
+    /// Lists `farm_id` at the staking pool followed by the farm splits.
+    pub fn get_farms(&self) -> Vec<FarmWeight> {
+        let farm = FarmWeight {
+            contract_id: self.staking_pool_account_id.clone(),
+            farm_id: self.farm_id,
+            weight: self.farm_weight,
+            pending: U128(0),
+            distributed: self.reward_tokens_distributed.into(),
+        };
+        std::iter::once(farm)
+            .chain(self.farm_splits.iter().map(|split| FarmWeight {
+                contract_id: split.config.contract_id.clone(),
+                farm_id: split.config.farm_id,
+                weight: split.config.weight,
+                pending: split.pending.into(),
+                distributed: split.distributed.into(),
+            }))
+            .collect()
+    }
+
+    /// Sends the pending reward tokens of the farm splits to their farms.
+    pub fn distribute_farm_splits(&mut self) -> Promise {
+        self.assert_not_paused();
+        self.assert_setup_complete();
+        let end_date = U64::from(env::block_timestamp() + self.farm_duration);
+        let reward_token_id = self.reward_token_id.clone();
+        let add_farm_gas = self.gas.add_farm;
+        self.farm_splits
+            .iter_mut()
+            .filter(|split| split.pending > 0)
+            .map(|split| {
+                let amount = std::mem::take(&mut split.pending);
+                split.distributed += amount;
+                let FarmSplitConfig {
+                    contract_id,
+                    farm_id,
+                    ..
+                } = split.config.clone();
+                Event::RewardTokensDistributed {
+                    amount: amount.into(),
+                    farm_id: Some(farm_id),
+                }
+                .emit();
+                ext_fungible_token::ft_transfer_call(
+                    contract_id.clone(),
+                    amount.into(),
+                    Some(format!(
+                        "Enjoy reward of {} {}, friends",
+                        amount, reward_token_id
+                    )),
+                    serde_json::to_string(&FarmingDetails {
+                        name: None,
+                        start_date: None,
+                        end_date,
+                        farm_id: Some(farm_id),
+                    })
+                    .unwrap(),
+                    reward_token_id.clone(),
+                    ONE_YOCTO,
+                    add_farm_gas,
+                )
+                .then(ext_self_farm_splits::on_farm_split_funded(
+                    contract_id,
+                    farm_id,
+                    amount.into(),
+                    env::current_account_id(),
+                    NO_DEPOSIT,
+                    ON_FARM_SPLIT_FUNDED_GAS,
+                ))
+            })
+            .reduce(|promise, funding| promise.and(funding))
+            .expect("Nothing to distribute to the farm splits")
+    }
+
+    #[private]
+    pub fn on_farm_split_funded(
+        &mut self,
+        #[callback_result] used_amount: Result<U128, PromiseError>,
+        contract_id: AccountId,
+        farm_id: u64,
+        amount: U128,
+    ) {
+        let used_amount = used_amount.map(|amount| amount.0).unwrap_or(0);
+        let refunded_amount = amount.0 - used_amount;
+        if refunded_amount == 0 {
+            return;
+        }
+        let split = self.farm_splits.iter_mut().find(|split| {
+            split.config.contract_id == contract_id && split.config.farm_id == farm_id
+        });
+        match split {
+            Some(split) => {
+                split.distributed -= refunded_amount;
+                split.pending += refunded_amount;
+                Event::RewardTokensHeldBack {
+                    amount: refunded_amount.into(),
+                    reason: "farm_refunded",
+                }
+                .emit();
+            }
+            None => self.internal_hold_back_reward_tokens(refunded_amount, "farm_split_removed"),
+        }
+    }
+}
+
+impl Contract {
+    /// Moves the shares of the farm splits of `amount` to their pending amounts and returns
+    /// the share of `farm_id`.
+    pub fn internal_split_reward_tokens(&mut self, amount: Balance) -> Balance {
+        if self.farm_splits.is_empty() {
+            return amount;
+        }
+        let total_weight = self
+            .farm_splits
+            .iter()
+            .fold(self.farm_weight as u128, |total, split| {
+                total + split.config.weight as u128
+            });
+        let mut farm_amount = amount;
+        for split in self.farm_splits.iter_mut() {
+            let share = u128_ratio(amount, split.config.weight as u128, total_weight);
+            split.pending += share;
+            farm_amount -= share;
+        }
+        farm_amount
+    }
+
+    /// Reward tokens on the balance waiting for `distribute_farm_splits`.
+    pub fn internal_farm_splits_pending(&self) -> Balance {
+        self.farm_splits.iter().map(|split| split.pending).sum()
+    }
+
+    pub fn internal_farm_splits_distributed(&self) -> Balance {
+        self.farm_splits.iter().map(|split| split.distributed).sum()
+    }
+}
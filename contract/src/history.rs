@@ -0,0 +1,345 @@
+use super::*;
+
+/// Number of past distributions kept on chain. Older entries are overwritten.
+const MAX_DISTRIBUTION_HISTORY: u64 = 1_000;
+/// Number of staking pool account snapshots kept on chain.
+const MAX_POOL_SNAPSHOTS: u64 = 100;
+/// Number of failures kept on chain.
+const MAX_RECENT_ERRORS: u64 = 50;
+/// Number of donations kept on chain.
+const MAX_DONATION_HISTORY: u64 = 200;
+pub const MAX_DONATION_MEMO_LENGTH: usize = 256;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Distribution {
+    #[serde(with = "u64_dec_format")]
+    pub timestamp: Timestamp,
+    /// NEAR swapped in this round, 0 for leftover reward tokens swept from the balance.
+    #[serde(with = "u128_dec_format")]
+    pub near_amount: Balance,
+    /// Reward tokens sent to the farm.
+    #[serde(with = "u128_dec_format")]
+    pub reward_amount: Balance,
+    pub farm_id: u64,
+    /// Reward tokens received per 1 NEAR.
+    #[serde(with = "u128_dec_format")]
+    pub reward_per_near: Balance,
+    /// Reward tokens the swapped NEAR was worth at oracle prices, 0 without the oracle.
+    #[serde(with = "u128_dec_format")]
+    pub oracle_reward_amount: Balance,
+}
+
+/// Swapped NEAR and delivered reward tokens over a time window, compared to their oracle value.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PipelineEfficiency {
+    pub distributions: u64,
+    pub near_amount: U128,
+    pub reward_amount: U128,
+    pub oracle_reward_amount: U128,
+    /// Share of the oracle value that reached the farm, i.e. what's left after slippage
+    /// and pool fees. `None` without oracle priced distributions in the window.
+    pub efficiency_bps: Option<u32>,
+}
+
+/// The contract's account at the staking pool as seen by a ping.
+#[derive(BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PoolSnapshot {
+    #[serde(with = "u64_dec_format")]
+    pub timestamp: Timestamp,
+    #[serde(with = "u128_dec_format")]
+    pub staked_balance: Balance,
+    #[serde(with = "u128_dec_format")]
+    pub unstaked_balance: Balance,
+    pub can_withdraw: bool,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Donation {
+    #[serde(with = "u64_dec_format")]
+    pub timestamp: Timestamp,
+    pub donor_id: AccountId,
+    #[serde(with = "u128_dec_format")]
+    pub amount: Balance,
+    pub memo: Option<String>,
+}
+
+/// Arguments of `donate`, all optional. Read from the input by `donate` itself so calls
+/// without any input keep working.
+#[derive(Deserialize, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DonateArgs {
+    pub memo: Option<String>,
+    pub vesting_duration_sec: Option<DurationSec>,
+}
+
+/// A failure detected by a callback.
+#[derive(BorshDeserialize, BorshSerialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ErrorRecord {
+    #[serde(with = "u64_dec_format")]
+    pub timestamp: Timestamp,
+    pub stage: String,
+    pub reason: String,
+    /// NEAR or tokens involved.
+    #[serde(with = "u128_dec_format")]
+    pub amount: Balance,
+}
+
+/// Appends to a ring buffer of `capacity` entries, counting every entry ever recorded.
+fn ring_buffer_push<T: BorshSerialize + BorshDeserialize>(
+    buffer: &mut Vector<T>,
+    recorded: &mut u64,
+    storage_bytes: &mut u64,
+    capacity: u64,
+    item: &T,
+) {
+    let index = *recorded % capacity;
+    track_storage(storage_bytes, || {
+        if index < buffer.len() {
+            buffer.replace(index, item);
+        } else {
+            buffer.push(item);
+        }
+    });
+    *recorded += 1;
+}
+
+/// Returns entries by their sequence number, skipping those already overwritten.
+fn ring_buffer_page<T: BorshSerialize + BorshDeserialize>(
+    buffer: &Vector<T>,
+    recorded: u64,
+    capacity: u64,
+    from_index: u64,
+    limit: u64,
+) -> Vec<T> {
+    let from_index = std::cmp::max(from_index, recorded.saturating_sub(capacity));
+    let to_index = std::cmp::min(from_index.saturating_add(limit), recorded);
+    (from_index..to_index)
+        .map(|index| buffer.get(index % capacity).unwrap())
+        .collect()
+}
+
+impl Contract {
+    pub fn internal_record_distribution_history(
+        &mut self,
+        near_amount: Balance,
+        reward_amount: Balance,
+        oracle_reward_amount: Balance,
+    ) {
+        let distribution = Distribution {
+            timestamp: env::block_timestamp(),
+            near_amount,
+            reward_amount,
+            farm_id: self.farm_id,
+            reward_per_near: if near_amount > 0 {
+                u128_ratio(reward_amount, ONE_NEAR, near_amount)
+            } else {
+                0
+            },
+            oracle_reward_amount,
+        };
+        ring_buffer_push(
+            &mut self.distributions,
+            &mut self.distributions_recorded,
+            &mut self.distributions_storage_bytes,
+            MAX_DISTRIBUTION_HISTORY,
+            &distribution,
+        );
+    }
+
+    pub fn internal_last_distribution_timestamp(&self) -> Option<Timestamp> {
+        self.distributions_recorded.checked_sub(1).map(|index| {
+            self.distributions
+                .get(index % MAX_DISTRIBUTION_HISTORY)
+                .unwrap()
+                .timestamp
+        })
+    }
+
+    /// NEAR swapped by the retained distributions since `since`.
+    pub fn internal_near_swapped_since(&self, since: Timestamp) -> Balance {
+        let oldest_index = self
+            .distributions_recorded
+            .saturating_sub(MAX_DISTRIBUTION_HISTORY);
+        (oldest_index..self.distributions_recorded)
+            .rev()
+            .map(|index| {
+                self.distributions
+                    .get(index % MAX_DISTRIBUTION_HISTORY)
+                    .unwrap()
+            })
+            .take_while(|distribution| distribution.timestamp >= since)
+            .map(|distribution| distribution.near_amount)
+            .sum()
+    }
+
+    pub fn internal_record_pool_snapshot(&mut self, account: &StakingPoolAccount) {
+        let snapshot = PoolSnapshot {
+            timestamp: env::block_timestamp(),
+            staked_balance: account.staked_balance.0,
+            unstaked_balance: account.unstaked_balance.0,
+            can_withdraw: account.can_withdraw,
+        };
+        ring_buffer_push(
+            &mut self.pool_snapshots,
+            &mut self.pool_snapshots_recorded,
+            &mut self.pool_snapshots_storage_bytes,
+            MAX_POOL_SNAPSHOTS,
+            &snapshot,
+        );
+    }
+
+    pub fn internal_record_error(&mut self, stage: &str, reason: &str, amount: Balance) {
+        let error = ErrorRecord {
+            timestamp: env::block_timestamp(),
+            stage: stage.to_string(),
+            reason: reason.to_string(),
+            amount,
+        };
+        ring_buffer_push(
+            &mut self.recent_errors,
+            &mut self.errors_recorded,
+            &mut self.errors_storage_bytes,
+            MAX_RECENT_ERRORS,
+            &error,
+        );
+    }
+
+    pub fn internal_swap_failed(&mut self, near_amount: Balance, reason: &'static str) {
+        Event::SwapFailed {
+            near_amount: near_amount.into(),
+            reason,
+        }
+        .emit();
+        self.internal_record_error("swap", reason, near_amount);
+    }
+
+    pub fn internal_record_donation(
+        &mut self,
+        donor_id: AccountId,
+        amount: Balance,
+        memo: Option<String>,
+    ) {
+        let donation = Donation {
+            timestamp: env::block_timestamp(),
+            donor_id,
+            amount,
+            memo,
+        };
+        ring_buffer_push(
+            &mut self.donations,
+            &mut self.donations_recorded,
+            &mut self.donations_storage_bytes,
+            MAX_DONATION_HISTORY,
+            &donation,
+        );
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Returns past distributions by their sequence number, oldest first.
+    /// Only the last `MAX_DISTRIBUTION_HISTORY` entries are retained.
+    pub fn get_distributions(&self, from_index: u64, limit: u64) -> Vec<Distribution> {
+        ring_buffer_page(
+            &self.distributions,
+            self.distributions_recorded,
+            MAX_DISTRIBUTION_HISTORY,
+            from_index,
+            limit,
+        )
+    }
+
+    pub fn get_distributions_count(&self) -> u64 {
+        self.distributions_recorded
+    }
+
+    /// Sums the retained distributions of the last `window_sec` seconds. Swaps are only
+    /// counted when priced by the oracle, while swept reward tokens counts as swap output surplus.
+    pub fn get_pipeline_efficiency(&self, window_sec: u32) -> PipelineEfficiency {
+        let since = env::block_timestamp().saturating_sub(u64::from(window_sec) * 10u64.pow(9));
+        let oldest_index = self
+            .distributions_recorded
+            .saturating_sub(MAX_DISTRIBUTION_HISTORY);
+        let mut distributions = 0;
+        let mut near_amount = 0;
+        let mut reward_amount = 0;
+        let mut oracle_reward_amount = 0;
+        for index in (oldest_index..self.distributions_recorded).rev() {
+            let distribution = self
+                .distributions
+                .get(index % MAX_DISTRIBUTION_HISTORY)
+                .unwrap();
+            if distribution.timestamp < since {
+                break;
+            }
+            if distribution.near_amount > 0 && distribution.oracle_reward_amount == 0 {
+                continue;
+            }
+            distributions += 1;
+            near_amount += distribution.near_amount;
+            reward_amount += distribution.reward_amount;
+            oracle_reward_amount += distribution.oracle_reward_amount;
+        }
+        PipelineEfficiency {
+            distributions,
+            near_amount: near_amount.into(),
+            reward_amount: reward_amount.into(),
+            oracle_reward_amount: oracle_reward_amount.into(),
+            efficiency_bps: if oracle_reward_amount > 0 {
+                Some(u128_ratio(reward_amount, MAX_BPS as u128, oracle_reward_amount) as u32)
+            } else {
+                None
+            },
+        }
+    }
+
+    /// Returns staking pool account snapshots taken by pings, oldest first.
+    /// Only the last `MAX_POOL_SNAPSHOTS` entries are retained.
+    pub fn get_pool_snapshots(&self, from_index: u64, limit: u64) -> Vec<PoolSnapshot> {
+        ring_buffer_page(
+            &self.pool_snapshots,
+            self.pool_snapshots_recorded,
+            MAX_POOL_SNAPSHOTS,
+            from_index,
+            limit,
+        )
+    }
+
+    pub fn get_pool_snapshots_count(&self) -> u64 {
+        self.pool_snapshots_recorded
+    }
+
+    /// Returns the last `MAX_RECENT_ERRORS` failures, newest first.
+    pub fn get_recent_errors(&self) -> Vec<ErrorRecord> {
+        let mut errors = ring_buffer_page(
+            &self.recent_errors,
+            self.errors_recorded,
+            MAX_RECENT_ERRORS,
+            0,
+            MAX_RECENT_ERRORS,
+        );
+        errors.reverse();
+        errors
+    }
+
+    /// Returns NEAR donations by their sequence number, oldest first.
+    /// Only the last `MAX_DONATION_HISTORY` entries are retained.
+    pub fn get_donations(&self, from_index: u64, limit: u64) -> Vec<Donation> {
+        ring_buffer_page(
+            &self.donations,
+            self.donations_recorded,
+            MAX_DONATION_HISTORY,
+            from_index,
+            limit,
+        )
+    }
+
+    pub fn get_donations_count(&self) -> u64 {
+        self.donations_recorded
+    }
+}
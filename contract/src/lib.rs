@@ -1,34 +1,147 @@
+mod burrow;
+mod buyback;
+mod commitment;
+mod compounding;
+mod config;
+mod contract_ids;
+mod council;
+mod dex;
+mod direct_wnear;
+mod distribution_step;
+mod distribution_windows;
+mod events;
+mod farm_rotation;
+mod farm_schedule;
+mod farm_splits;
+mod history;
+mod liquid_staking;
+mod migration;
+mod network;
+mod operators;
+mod oracle;
+mod output_tokens;
+mod pipeline;
+mod pool_admin;
+mod pool_change;
+mod pre_swap_check;
+mod public_info;
+mod pyth;
+mod ref_deposits;
+mod ref_storage;
+mod release_curve;
+mod reports;
+mod rescue;
+mod setup;
+mod simulation;
+mod slippage;
+mod split_routes;
+mod swap_quote;
+mod timelock;
+mod token_decimals;
+mod token_receiver;
+mod treasury;
 mod utils;
+mod vesting;
 
+use crate::burrow::*;
+use crate::buyback::*;
+use crate::commitment::*;
+use crate::compounding::*;
+use crate::config::*;
+use crate::contract_ids::*;
+use crate::council::*;
+use crate::dex::*;
+use crate::direct_wnear::*;
+use crate::distribution_step::*;
+use crate::distribution_windows::*;
+use crate::events::*;
+use crate::farm_rotation::*;
+use crate::farm_schedule::*;
+use crate::farm_splits::*;
+use crate::history::*;
+use crate::liquid_staking::*;
+use crate::migration::*;
+use crate::network::*;
+use crate::oracle::*;
+use crate::output_tokens::*;
+use crate::pipeline::*;
+use crate::pool_admin::*;
+use crate::pool_change::*;
+use crate::pre_swap_check::*;
+use crate::public_info::*;
+use crate::pyth::*;
+use crate::ref_deposits::*;
+use crate::ref_storage::*;
+use crate::release_curve::*;
+use crate::reports::*;
+use crate::simulation::*;
+use crate::slippage::*;
+use crate::split_routes::*;
+use crate::swap_quote::*;
+use crate::timelock::*;
+use crate::token_decimals::*;
+use crate::treasury::*;
 use crate::utils::*;
+use crate::vesting::*;
 use std::collections::HashMap;
 use std::convert::TryFrom;
 
 use near_contract_standards::fungible_token::core_impl::ext_fungible_token;
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::{UnorderedSet, Vector};
 use near_sdk::json_types::{U128, U64};
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::{
     env, ext_contract, is_promise_success, log, near_bindgen, require, serde_json, AccountId,
-    Balance, Duration, Gas, PanicOnDefault, Promise, PromiseError, Timestamp, ONE_YOCTO,
+    Balance, BlockHeight, BorshStorageKey, Duration, Gas, PanicOnDefault, Promise, PromiseError,
+    PromiseOrValue, PublicKey, StorageUsage, Timestamp, ONE_NEAR, ONE_YOCTO,
 };
 
 const NO_DEPOSIT: Balance = 0;
 const STAKING_POOL_PING_GAS: Gas = Gas(50_000_000_000_000);
 const STAKING_POOL_READ_GAS: Gas = Gas(5_000_000_000_000);
-const ON_DISTRIBUTE_GAS: Gas = Gas(120_000_000_000_000);
+const ON_POOL_PAUSE_CHECKED_GAS: Gas = Gas(95_000_000_000_000);
+const ON_DISTRIBUTE_GAS: Gas = Gas(155_000_000_000_000);
 const WITHDRAW_GAS: Gas = Gas(25_000_000_000_000);
-const ON_WITHDRAW_GAS: Gas = Gas(60_000_000_000_000);
+const ON_WITHDRAW_GAS: Gas = Gas(95_000_000_000_000);
 const UNSTAKE_ALL_GAS: Gas = Gas(50_000_000_000_000);
 
 const SWAP_GAS: Gas = Gas(120_000_000_000_000);
-const ON_SWAP_GAS: Gas = Gas(100_000_000_000_000);
+const ON_SWAP_GAS: Gas = Gas(120_000_000_000_000);
 const FT_BALANCE_OF_GAS: Gas = Gas(10_000_000_000_000);
+/// Gas `on_swap` keeps for itself and the balance read, the rest goes to `on_swap_output`.
+const ON_SWAP_OUTPUT_READ_GAS: Gas = Gas(FT_BALANCE_OF_GAS.0 + 5_000_000_000_000);
 const FT_TRANSFER_CALL_ADD_FARM_GAS: Gas = Gas(80_000_000_000_000);
+const ON_REWARD_TOKENS_FARMED_GAS: Gas = Gas(10_000_000_000_000);
 const WRAP_NEAR_GAS: Gas = Gas(5_000_000_000_000);
+const REF_QUOTE_GAS: Gas = Gas(10_000_000_000_000);
+const ON_SWAP_PATH_QUOTE_GAS: Gas = Gas(15_000_000_000_000);
+const ON_PYTH_PRICES_GAS: Gas = Gas(250_000_000_000_000);
+
+/// Amount of wNEAR quoted through a newly set swap path.
+const SWAP_PATH_PROBE_AMOUNT: Balance = 10 * ONE_NEAR;
+
+const MAX_BPS: u32 = 10_000;
+
+/// Limits on configuration vectors, keeping the state small and its deserialization cheap.
+const MAX_SWAP_PATH_HOPS: usize = 4;
+const MAX_FALLBACK_SWAP_PATHS: usize = 3;
+const MAX_OPERATORS: u64 = 10;
+const MAX_INTERMEDIATE_TOKENS: usize = 10;
 
 const DEFAULT_FARM_DURATION: Duration = 7 * 24 * 60 * 60 * 1_000_000_000;
 const FULL_REWARDS_DURATION: u64 = 3 * 24 * 60 * 60 * 1_000_000_000;
+const ONE_DAY: Duration = 24 * 60 * 60 * 1_000_000_000;
+const DEFAULT_MIN_FARM_DURATION: Duration = ONE_DAY;
+const DEFAULT_MAX_FARM_DURATION: Duration = 28 * ONE_DAY;
+const DEFAULT_FARM_TOP_UP_WINDOW: Duration = ONE_DAY;
+const MAX_SCHEDULE_HORIZON_DAYS: u32 = 90;
+const DEFAULT_ORACLE_MAX_RECENCY_SEC: DurationSec = 90;
+const DEFAULT_ORACLE_MAX_STALENESS: Duration = 15_000_000_000;
+const MAX_ORACLE_RECENCY_SEC: DurationSec = 600;
+const MAX_ORACLE_STALENESS_SEC: DurationSec = 300;
+const DEFAULT_ACCOUNTING_SNAPSHOT_INTERVAL: Duration = ONE_DAY;
+const DEFAULT_MAX_QUOTE_AGE: Duration = 60_000_000_000;
 
 /// Represents an account structure readable by humans.
 #[derive(Deserialize)]
@@ -50,31 +163,91 @@ pub trait StakingPoolContract {
     fn ping(&mut self);
     /* Unstakes all staked balance */
     fn unstake_all(&mut self);
+    /* Unstakes the given amount */
+    fn unstake(&mut self, amount: U128);
+    /* Deposits the attached amount and stakes it */
+    fn deposit_and_stake(&mut self);
+    /* Returns the total staked balance of the pool */
+    fn get_total_staked_balance(&self);
     /* Returns the unstaked balance of the given account */
     fn get_account(&self, account_id: AccountId);
     /* Withdraws the non staked balance for given account */
     fn withdraw(&mut self, amount: U128);
+    /* Returns whether staking is paused */
+    fn is_staking_paused(&self) -> bool;
+}
+
+/// Interface for the REF exchange
+#[ext_contract(ext_ref_finance)]
+pub trait RefFinanceContract {
+    /* Returns the pool info */
+    fn get_pool(&self, pool_id: u64);
+    /* Returns the amount of token_out received for amount_in of token_in */
+    fn get_return(
+        &self,
+        pool_id: u64,
+        token_in: AccountId,
+        amount_in: U128,
+        token_out: AccountId,
+    ) -> U128;
 }
 
 #[ext_contract(ext_self)]
 pub trait ExtContract {
     /* Callback from checking unstaked balance */
-    fn on_get_account(&mut self, #[callback] account: StakingPoolAccount);
+    fn on_get_account(&mut self, #[callback] account: StakingPoolAccount, keeper_id: AccountId);
     /* Callback from staking rewards withdraw */
-    fn on_withdraw(&mut self, unstaked_amount: U128, unstake_all: bool);
+    fn on_withdraw(&mut self, unstaked_amount: U128, unstake_amount: U128, keeper_id: AccountId);
     /* Callback from REF buy */
     fn on_swap(
         &mut self,
         #[callback_result] transfer_amount: Result<U128, PromiseError>,
         min_amount_out: U128,
         reward: U128,
+        from_buffer: U128,
+        initiated_at: U64,
+        oracle_amount_out: Option<U128>,
+    );
+    /* Callback from reading the reward token balance after a swap */
+    fn on_swap_output(
+        &mut self,
+        #[callback_result] balance: Result<U128, PromiseError>,
+        near_amount: U128,
+        min_amount_out: U128,
+        initiated_at: U64,
+        oracle_amount_out: U128,
+    );
+    /* Callback from quoting a hop of a new swap path */
+    fn on_swap_path_quote(&mut self, #[callback] quote: DexQuote, hop_index: u32);
+    /* Callback from reward token balance */
+    fn on_reward_token_balance(&mut self, #[callback] reward_amount: U128);
+    /* Callback from adding reward tokens to the farm */
+    fn on_reward_tokens_farmed(
+        &mut self,
+        #[callback_result] used_amount: Result<U128, PromiseError>,
+        amount: U128,
+        farm_id: Option<u64>,
+    );
+    /* Callback from checking whether the staking pool is paused */
+    fn on_pool_pause_checked(
+        &mut self,
+        #[callback_result] paused: Result<bool, PromiseError>,
+        reward_amount: U128,
     );
-    /* Callback from USN token balance */
-    fn on_usn_balance(&mut self, #[callback] usn_amount: U128);
     /* Callback from wrap near token balance */
     fn on_wrap_near_balance(&mut self, #[callback] wnear_amount: U128);
 }
 
+/// What a price update does when there is no reward to distribute.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum ZeroRewardPolicy {
+    /// Fail the call.
+    Panic,
+    /// Succeed without doing anything.
+    Skip,
+}
+
 #[derive(Serialize)]
 #[serde(crate = "near_sdk::serde")]
 pub struct FtTransferCallArgs {
@@ -100,6 +273,27 @@ pub struct Action {
 #[serde(crate = "near_sdk::serde")]
 pub struct RefArgs {
     actions: Vec<Action>,
+    /// Account earning the referral share of the swap fees.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    referral_id: Option<AccountId>,
+}
+
+/// Instruction for a swap executor: swap the whole transfer into `token_out`
+/// and send at least `min_amount_out` back before resolving the transfer.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ExecutorArgs {
+    token_out: AccountId,
+    min_amount_out: U128,
+}
+
+#[derive(BorshStorageKey, BorshSerialize)]
+pub(crate) enum StorageKey {
+    Distributions,
+    Operators,
+    PoolSnapshots,
+    Donations,
+    RecentErrors,
 }
 
 #[near_bindgen]
@@ -108,7 +302,7 @@ pub struct RefArgs {
 pub struct Contract {
     staking_pool_account_id: AccountId,
     owner_id: AccountId,
-    usn_contract_id: AccountId,
+    reward_token_id: AccountId,
     #[serde(with = "u128_dec_format")]
     rewards_received: Balance,
     #[serde(with = "u128_dec_format")]
@@ -116,12 +310,14 @@ pub struct Contract {
     #[serde(with = "u64_dec_format")]
     last_reward_distribution: Timestamp,
     #[serde(with = "u64_dec_format")]
+    last_reward_distribution_height: BlockHeight,
+    #[serde(with = "u64_dec_format")]
     farm_duration: Duration,
     #[serde(with = "u64_dec_format")]
     full_rewards_duration: Duration,
     farm_id: u64,
     #[serde(with = "u128_dec_format")]
-    usn_distributed: Balance,
+    reward_tokens_distributed: Balance,
     oracle_contract_id: AccountId,
     ref_finance_contract_id: AccountId,
     wrap_near_contract_id: AccountId,
@@ -130,15 +326,296 @@ pub struct Contract {
     wrapped_amount: Balance,
     #[serde(with = "u128_dec_format")]
     max_near_reward: Balance,
+    /// Extra yoctoNEAR wrapped on top of the reward to cover rounding.
+    #[serde(with = "u128_dec_format")]
+    rounding_adjustments: Balance,
+    /// Part of each released reward that is swapped, the rest is paid out as NEAR.
+    swap_fraction_bps: u32,
+    near_payout_account_id: Option<AccountId>,
+    #[serde(with = "u128_dec_format")]
+    near_paid_out: Balance,
+    network: NetworkProfile,
+    /// Share of each withdrawn reward accrued to the owner.
+    owner_fee_bps: u32,
+    #[serde(with = "u128_dec_format")]
+    owner_fees_accrued: Balance,
+    #[serde(with = "u128_dec_format")]
+    owner_fees_claimed: Balance,
+    pending_pool_action: Option<PendingPoolAction>,
+    /// Farm the round as wNEAR when the swap promise fails.
+    fallback_to_wnear: bool,
+    wnear_farm_id: Option<u64>,
+    #[serde(with = "u128_dec_format")]
+    wnear_distributed: Balance,
+    /// Swap callbacks arriving later than this many blocks don't farm the output. 0 disables.
+    max_swap_delay_blocks: BlockHeight,
+    #[serde(with = "u64_dec_format")]
+    accounting_snapshot_interval: Duration,
+    #[serde(with = "u64_dec_format")]
+    last_accounting_snapshot: Timestamp,
+    #[serde(skip)]
+    distributions: Vector<Distribution>,
+    distributions_recorded: u64,
+    /// Set once `finalize_setup` verified the dependency contracts.
+    setup_complete: bool,
+    /// Unstaked balance at the staking pool as of the last ping, not yet withdrawn.
+    #[serde(with = "u128_dec_format")]
+    pending_at_pool: Balance,
+    /// Blocks the distribution pipeline while set.
+    paused: bool,
+    /// Share of each withdrawal paid to the account whose `ping` triggered it, up to a cap.
+    keeper_reward_bps: u32,
+    #[serde(with = "u128_dec_format")]
+    max_keeper_reward: Balance,
+    #[serde(with = "u128_dec_format")]
+    keeper_rewards_paid: Balance,
+    /// Oracle prices of the reward token below this band stop the distribution, prices above
+    /// it are capped when computing the minimum output.
+    reward_price_band: Option<PriceBand>,
+    /// When set, wNEAR is sent to this contract for swapping instead of REF.
+    swap_executor_id: Option<AccountId>,
+    /// Largest `recency_duration_sec` accepted from the oracle.
+    oracle_max_recency_sec: DurationSec,
+    /// Largest age of the oracle price data.
+    #[serde(with = "u64_dec_format")]
+    oracle_max_staleness: Duration,
+    extra_oracle_ids: Vec<AccountId>,
+    oracle_aggregation: OracleAggregation,
+    /// Fresh reports collected for `OracleAggregation::MedianOf`.
+    oracle_reports: Vec<OracleReport>,
+    /// Smallest share of the transfer REF has to consume for a swap to count as filled.
+    min_fill_bps: u32,
+    /// Smallest amount wrapped with `near_deposit`.
+    #[serde(with = "u128_dec_format")]
+    min_wrap_amount: Balance,
+    price_source: PriceSource,
+    #[serde(skip)]
+    pool_snapshots: Vector<PoolSnapshot>,
+    pool_snapshots_recorded: u64,
+    /// Reward tokens per 1 NEAR requested by the last successful swap.
+    #[serde(serialize_with = "option_u128_dec_format::serialize")]
+    last_swap_rate: Option<Balance>,
+    /// Largest change of the rate against `last_swap_rate` before swaps are refused. 0 disables.
+    max_price_deviation_bps: u32,
+    /// Lets the next swap through regardless of the deviation.
+    price_deviation_override: bool,
+    zero_reward_policy: ZeroRewardPolicy,
+    last_oracle_result: Option<OracleResult>,
+    /// Accounts allowed to run operational methods besides the owner.
+    #[serde(skip)]
+    operators: UnorderedSet<AccountId>,
+    /// Paths tried in order after a swap fails on slippage.
+    fallback_swap_paths: Vec<Vec<Action>>,
+    /// Path used by the next swap, 0 is `swap_path` and `i` is `fallback_swap_paths[i - 1]`.
+    swap_path_index: u32,
+    /// Largest amount swapped at once, the rest waits for the following distributions.
+    /// 0 disables.
+    #[serde(with = "u128_dec_format")]
+    max_swap_chunk: Balance,
+    /// Released reward waiting to be swapped in further chunks.
+    #[serde(with = "u128_dec_format")]
+    queued_swap_amount: Balance,
+    /// Bounds of a farm duration set for a single round.
+    #[serde(with = "u64_dec_format")]
+    min_farm_duration: Duration,
+    #[serde(with = "u64_dec_format")]
+    max_farm_duration: Duration,
+    /// Farm duration of the next reward token distribution instead of `farm_duration`.
+    next_farm_duration: Option<Duration>,
+    /// Ask the staking pool whether it's paused before adding reward tokens to the farm.
+    check_pool_pause: bool,
+    /// Reward tokens held back while the staking pool was paused, farmed next time.
+    #[serde(with = "u128_dec_format")]
+    held_reward_tokens: Balance,
+    /// Excess NEAR sent out by `sweep_excess_near`.
+    #[serde(with = "u128_dec_format")]
+    near_swept: Balance,
+    migration_progress: Option<MigrationProgress>,
+    /// Reward tokens donated with `ft_transfer_call`, not yet distributed.
+    #[serde(with = "u128_dec_format")]
+    pending_reward_donations: Balance,
+    /// Key of the off-chain router whose signed swap quotes are accepted.
+    quote_signer: Option<PublicKey>,
+    #[serde(with = "u64_dec_format")]
+    max_quote_age: Duration,
+    last_quote_nonce: u64,
+    distribution_mode: DistributionMode,
+    /// Mode of the next reward token distribution instead of `distribution_mode`.
+    next_distribution_mode: Option<DistributionMode>,
+    buyback: Option<BuybackConfig>,
+    /// Reward tokens swapped into the buyback token.
+    #[serde(with = "u128_dec_format")]
+    reward_tokens_bought_back: Balance,
+    /// Bought tokens burned, in the buyback token.
+    #[serde(with = "u128_dec_format")]
+    buyback_tokens_burned: Balance,
+    /// Part of `rewards_received` that was donated rather than withdrawn from the pool.
+    #[serde(with = "u128_dec_format")]
+    near_donated: Balance,
+    /// NEAR consumed by swaps.
+    #[serde(with = "u128_dec_format")]
+    near_swapped: Balance,
+    /// NEAR sent to swaps whose callback hasn't arrived yet.
+    #[serde(with = "u128_dec_format")]
+    near_swapping: Balance,
+    /// Storage taken by the collections, tracked as they are written.
+    distributions_storage_bytes: StorageUsage,
+    pool_snapshots_storage_bytes: StorageUsage,
+    operators_storage_bytes: StorageUsage,
+    /// Receives `treasury_share_bps` of each swap output instead of the farm.
+    treasury_account_id: Option<AccountId>,
+    treasury_share_bps: u32,
+    #[serde(with = "u128_dec_format")]
+    treasury_sent: Balance,
+    pyth_intermediate_feeds: Vec<PythFeed>,
+    /// Refuse swaps through tokens without an oracle price.
+    require_route_prices: bool,
+    /// Share of each withdrawn reward, after fees, staked back into the pool.
+    restake_bps: u32,
+    /// Restaked NEAR kept staked at the pool.
+    #[serde(with = "u128_dec_format")]
+    restaked: Balance,
+    /// NEAR restaked and not returned to the rewards, including released restaked NEAR that
+    /// isn't withdrawn yet.
+    #[serde(with = "u128_dec_format")]
+    near_restaked: Balance,
+    farm_rotation: FarmRotation,
+    /// The next reward token distribution creates a new farm instead of topping up `farm_id`.
+    create_farm: bool,
+    /// Price triggered distributions have to reveal a committed plan.
+    require_commitment: bool,
+    distribution_commitment: Option<DistributionCommitment>,
+    public_metadata: PublicMetadata,
+    /// Hold back reward tokens unless the last farm check found `farm_id` running.
+    validate_farm: bool,
+    /// Running farm of the reward token found by the last farm check.
+    validated_farm: Option<ValidatedFarm>,
+    /// Total stake of the staking pool as of the last ping.
+    #[serde(with = "u128_dec_format")]
+    pool_total_stake: Balance,
+    /// Weight of `farm_id` against the farm splits.
+    farm_weight: u32,
+    farm_splits: Vec<FarmSplit>,
+    /// Delay of changes to timelocked methods, 0 applies them immediately.
+    #[serde(with = "u64_dec_format")]
+    timelock_delay: Duration,
+    pending_changes: Vec<PendingChange>,
+    next_change_id: u64,
+    /// Approves admin calls instead of `owner_id` when set.
+    council: Option<Council>,
+    council_proposals: Vec<CouncilProposal>,
+    next_council_proposal_id: u64,
+    /// Gas of the distribution chain, see `GasConfig`.
+    gas: GasConfig,
+    /// Set while a swap is in flight, from `internal_swap` until its `on_swap`.
+    distribution_in_flight: bool,
+    pipeline_stage: PipelineStage,
+    #[serde(with = "u64_dec_format")]
+    pipeline_stage_since: Timestamp,
+    /// Swaps that went through and the reward tokens they returned.
+    swaps_executed: u64,
+    #[serde(with = "u128_dec_format")]
+    reward_tokens_swapped: Balance,
+    /// Tokens swap paths may go through between wNEAR and the reward token, any without it.
+    intermediate_tokens: Option<Vec<AccountId>>,
+    /// Staking pool to switch to once the current one is drained.
+    staking_pool_change: Option<StakingPoolChange>,
+    /// Contract whitelisting the staking pools this one may use.
+    staking_pool_whitelist_id: Option<AccountId>,
+    /// Smaller swaps are refused, the rewards accumulate until they reach it.
+    #[serde(with = "u128_dec_format")]
+    min_swap_amount: Balance,
+    /// Minimum time between price triggered distributions.
+    #[serde(with = "u64_dec_format")]
+    min_distribution_interval: Duration,
+    #[serde(with = "u64_dec_format")]
+    last_price_distribution: Timestamp,
+    release_curve: ReleaseCurve,
+    /// Weekly windows in which price triggered distributions run, any time without them.
+    distribution_windows: Vec<DistributionWindow>,
+    /// Exchange of each swap path by path index, REF for those without an entry.
+    swap_path_dexes: Vec<DexAdapter>,
+    /// Weighted routes each swap is split across, the swap path is used without them.
+    split_routes: Vec<SplitRoute>,
+    /// Whether the contract was found registered with REF, swaps on REF require it.
+    ref_storage_registered: bool,
+    /// Referral account put into REF swaps.
+    referral_id: Option<AccountId>,
+    /// How far below the oracle amount a pre-swap quote may be, no quote without it.
+    pre_swap_quote_tolerance_bps: Option<u32>,
+    slippage_policy: SlippagePolicy,
+    consecutive_slippage_failures: u32,
+    /// Slippage added to oracle priced swaps by `SlippageEscalation::WidenSlippage`.
+    extra_slippage_bps: u32,
+    /// Burrow contract idle wNEAR is supplied to.
+    burrow_contract_id: Option<AccountId>,
+    /// wNEAR principal supplied to Burrow, still part of `available_rewards`.
+    #[serde(with = "u128_dec_format")]
+    burrow_deposited: Balance,
+    /// Whether a withdrawal from Burrow is in flight.
+    burrow_pending: bool,
+    #[serde(with = "u128_dec_format")]
+    burrow_interest_earned: Balance,
+    /// Liquid staking token whose value growth is collected as rewards.
+    liquid_staking_source: Option<LiquidStakingSource>,
+    /// wNEAR farm the rewards go to without a swap, swaps are used without it.
+    direct_wnear_farm_id: Option<u64>,
+    /// Tokens taking a share of price triggered distributions besides the reward token.
+    output_tokens: Vec<OutputToken>,
+    /// Start of the farm of the next reward token distribution, it starts right away without.
+    #[serde(serialize_with = "option_u64_dec_format::serialize")]
+    next_farm_start_date: Option<Timestamp>,
+    /// Reward tokens per second the farm may distribute after a top-up, 0 disables the cap.
+    #[serde(with = "u128_dec_format")]
+    max_reward_rate: Balance,
+    /// Last top-up of the main farm, counted against `max_reward_rate`.
+    farm_schedule: Option<FarmSchedule>,
+    /// Time left on the main farm below which a top-up is due.
+    #[serde(with = "u64_dec_format")]
+    farm_top_up_window: Duration,
+    /// Hold back reward tokens until the main farm is within `farm_top_up_window` of its end.
+    require_top_up_due: bool,
+    /// Oracle asset IDs of tokens not priced under their contract ID.
+    oracle_assets: Vec<OracleAsset>,
+    /// Decimals of the swap path tokens, to check the decimals of oracle prices.
+    token_decimals: Vec<TokenDecimals>,
+    #[serde(skip)]
+    donations: Vector<Donation>,
+    donations_recorded: u64,
+    donations_storage_bytes: StorageUsage,
+    #[serde(with = "u128_dec_format")]
+    min_donation: Balance,
+    donations_enabled: bool,
+    donation_vesting: Option<DonationVesting>,
+    vesting_donations: Vec<VestingDonation>,
+    /// NEAR of vesting donations not yet added to `available_rewards`.
+    #[serde(with = "u128_dec_format")]
+    vesting_amount: Balance,
+    /// Time of the last successful withdraw from the staking pool, 0 before the first.
+    #[serde(with = "u64_dec_format")]
+    last_withdraw: Timestamp,
+    #[serde(skip)]
+    recent_errors: Vector<ErrorRecord>,
+    errors_recorded: u64,
+    errors_storage_bytes: StorageUsage,
+    /// Output token swaps that haven't farmed their output yet.
+    output_swaps_in_flight: u32,
+    /// Reward tokens of expired swaps left on the balance for `distribute_reward_tokens`.
+    #[serde(with = "u128_dec_format")]
+    expired_swap_output: Balance,
+    /// Reward tokens per 1 NEAR at the last oracle prices used for a swap, before slippage.
+    #[serde(serialize_with = "option_u128_dec_format::serialize")]
+    last_oracle_rate: Option<Balance>,
 }
 
 #[near_bindgen]
 impl Contract {
     #[init]
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         staking_pool_account_id: AccountId,
         owner_id: AccountId,
-        usn_contract_id: AccountId,
+        reward_token_id: AccountId,
         farm_id: u64,
         oracle_contract_id: AccountId,
         ref_finance_contract_id: AccountId,
@@ -146,10 +623,10 @@ impl Contract {
         swap_path: Vec<Action>,
         max_near_reward: U128,
     ) -> Self {
-        let this = Self {
+        let mut this: Self = ContractV1::from(ContractV0 {
             staking_pool_account_id,
             owner_id,
-            usn_contract_id,
+            usn_contract_id: reward_token_id,
             rewards_received: 0,
             available_rewards: 0,
             last_reward_distribution: 0,
@@ -163,91 +640,113 @@ impl Contract {
             swap_path,
             wrapped_amount: 0,
             max_near_reward: max_near_reward.0,
-        };
+        })
+        .into();
+        // Upgraded deployments are already running, fresh ones still need `finalize_setup`.
+        this.setup_complete = false;
+        this.ref_storage_registered = false;
+        Self::internal_write_state_version();
         this.assert_valid_swap_path();
+        this.internal_register_storage();
         this
     }
 
-    // #[private]
-    // #[init(ignore_state)]
-    // pub fn migrate(max_near_reward: U128) -> Self {
-    //     #[derive(BorshDeserialize)]
-    //     pub struct OldContract {
-    //         staking_pool_account_id: AccountId,
-    //         owner_id: AccountId,
-    //         usn_contract_id: AccountId,
-    //         rewards_received: Balance,
-    //         available_rewards: Balance,
-    //         last_reward_distribution: Timestamp,
-    //         farm_duration: Duration,
-    //         full_rewards_duration: Duration,
-    //         farm_id: u64,
-    //         usn_distributed: Balance,
-    //         oracle_contract_id: AccountId,
-    //         ref_finance_contract_id: AccountId,
-    //         wrap_near_contract_id: AccountId,
-    //         swap_path: Vec<Action>,
-    //         wrapped_amount: Balance,
-    //     }
-    //     let OldContract {
-    //         staking_pool_account_id,
-    //         owner_id,
-    //         usn_contract_id,
-    //         rewards_received,
-    //         available_rewards,
-    //         last_reward_distribution,
-    //         farm_duration,
-    //         full_rewards_duration,
-    //         farm_id,
-    //         usn_distributed,
-    //         oracle_contract_id,
-    //         ref_finance_contract_id,
-    //         wrap_near_contract_id,
-    //         swap_path,
-    //         wrapped_amount,
-    //     } = env::state_read().unwrap();
-    //     Self {
-    //         staking_pool_account_id,
-    //         owner_id,
-    //         usn_contract_id,
-    //         rewards_received,
-    //         available_rewards,
-    //         last_reward_distribution,
-    //         farm_duration,
-    //         full_rewards_duration,
-    //         farm_id,
-    //         usn_distributed,
-    //         oracle_contract_id,
-    //         ref_finance_contract_id,
-    //         wrap_near_contract_id,
-    //         swap_path,
-    //         wrapped_amount,
-    //         max_near_reward: max_near_reward.0,
-    //     }
-    // }
+    /// Initializes with the wNEAR, oracle and REF accounts of a known network.
+    /// Pool IDs differ between networks, so the swap path is still given explicitly.
+    #[init]
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_with_profile(
+        staking_pool_account_id: AccountId,
+        owner_id: AccountId,
+        network: NetworkProfile,
+        reward_token_id: AccountId,
+        farm_id: u64,
+        swap_path: Vec<Action>,
+        max_near_reward: U128,
+    ) -> Self {
+        let accounts = network.accounts();
+        let mut this = Self::new(
+            staking_pool_account_id,
+            owner_id,
+            reward_token_id,
+            farm_id,
+            accounts.oracle_contract_id,
+            accounts.ref_finance_contract_id,
+            accounts.wrap_near_contract_id,
+            swap_path,
+            max_near_reward,
+        );
+        this.network = network;
+        this
+    }
 
+    /// Switches the token the rewards are swapped into. Fallback paths, the buyback, the next
+    /// farm IDs and the farm splits belong to the previous token and are cleared, switching
+    /// back to farming.
     pub fn update_token(
         &mut self,
-        usn_contract_id: AccountId,
+        reward_token_id: AccountId,
         farm_id: u64,
         swap_path: Vec<Action>,
     ) {
-        self.assert_owner();
-        self.usn_contract_id = usn_contract_id;
+        self.assert_owner_or_timelock();
+        require!(
+            self.held_reward_tokens == 0
+                && self.pending_reward_donations == 0
+                && self.internal_farm_splits_pending() == 0,
+            "Distribute the reward tokens on the balance first"
+        );
+        self.reward_token_id = reward_token_id;
         self.farm_id = farm_id;
         self.swap_path = swap_path;
+        self.fallback_swap_paths.clear();
+        self.swap_path_index = 0;
+        self.swap_path_dexes.clear();
+        self.split_routes.clear();
+        self.buyback = None;
+        self.distribution_mode = DistributionMode::Farm;
+        self.next_distribution_mode = None;
+        if matches!(self.farm_rotation, FarmRotation::NextFarmIds(_)) {
+            self.farm_rotation = FarmRotation::Disabled;
+        }
+        self.create_farm = false;
+        self.validated_farm = None;
+        self.farm_splits.clear();
+        self.output_tokens.clear();
+        // Rates and bands are in units of the previous token, and the new pools need a check.
+        self.last_swap_rate = None;
+        self.last_oracle_rate = None;
+        self.reward_price_band = None;
+        self.setup_complete = false;
         self.assert_valid_swap_path();
+        Event::ConfigChanged {
+            field: "reward_token_id",
+        }
+        .emit();
+        self.internal_fetch_token_decimals();
+        self.internal_register_storage();
     }
 
     pub fn get_info(&self) -> &Self {
         self
     }
 
+    pub fn get_reward_token_id(&self) -> AccountId {
+        self.reward_token_id.clone()
+    }
+
+    /// Needs about 250 Tgas with the default gas config, the calls it starts take 240 Tgas.
     pub fn ping(&mut self) -> Promise {
+        self.assert_not_paused();
+        self.internal_unlock_vested_donations();
+        if self.validate_farm || self.farm_rotation != FarmRotation::Disabled {
+            self.internal_check_farm();
+        }
+        self.internal_read_pool_total_stake();
         ext_staking_pool::ping(
             self.staking_pool_account_id.clone(),
             NO_DEPOSIT,
-            STAKING_POOL_PING_GAS,
+            self.gas.staking_pool_ping,
         )
         .then(ext_staking_pool::get_account(
             env::current_account_id(),
@@ -256,16 +755,25 @@ impl Contract {
             STAKING_POOL_READ_GAS,
         ))
         .then(ext_self::on_get_account(
+            env::predecessor_account_id(),
             env::current_account_id(),
             NO_DEPOSIT,
-            ON_DISTRIBUTE_GAS,
+            self.gas.on_distribute,
         ))
     }
 
     #[private]
-    pub fn on_get_account(&mut self, #[callback] account: StakingPoolAccount) {
-        let unstake_all = account.staked_balance.0 > 0;
+    pub fn on_get_account(
+        &mut self,
+        #[callback] account: StakingPoolAccount,
+        keeper_id: AccountId,
+    ) {
+        // Restaked NEAR stays staked, its rewards are unstaked with the rest.
+        let unstake_amount = account.staked_balance.0.saturating_sub(self.restaked);
+        self.pending_at_pool = account.unstaked_balance.0;
+        self.internal_record_pool_snapshot(&account);
         if account.unstaked_balance.0 > 0 {
+            self.internal_set_pool_stage(PipelineStage::AwaitingWithdraw);
             if account.can_withdraw {
                 log!(
                     "Withdrawing from staking pool: {}",
@@ -275,90 +783,594 @@ impl Contract {
                     account.unstaked_balance,
                     self.staking_pool_account_id.clone(),
                     NO_DEPOSIT,
-                    WITHDRAW_GAS,
+                    self.gas.withdraw,
                 )
                 .then(ext_self::on_withdraw(
                     account.unstaked_balance,
-                    unstake_all,
+                    unstake_amount.into(),
+                    keeper_id,
                     env::current_account_id(),
                     NO_DEPOSIT,
-                    ON_WITHDRAW_GAS,
+                    self.gas.on_withdraw,
                 ))
                 .as_return();
             } else {
                 log!("Awaiting unstaking. Nothing to do. Can't withdraw yet");
             }
-        } else if unstake_all {
-            self.internal_unstake_all();
+        } else if unstake_amount > 0 {
+            self.internal_unstake_rewards(unstake_amount);
+        } else {
+            self.internal_set_pool_stage(PipelineStage::Idle);
         }
     }
 
-    fn internal_unstake_all(&mut self) {
-        log!("Unstaking all from staking pool",);
-        ext_staking_pool::unstake_all(
-            self.staking_pool_account_id.clone(),
-            NO_DEPOSIT,
-            UNSTAKE_ALL_GAS,
-        )
-        .as_return();
+    fn internal_unstake_rewards(&mut self, amount: Balance) {
+        self.internal_set_pool_stage(PipelineStage::AwaitingUnstake);
+        if self.restaked == 0 {
+            log!("Unstaking all from staking pool",);
+            ext_staking_pool::unstake_all(
+                self.staking_pool_account_id.clone(),
+                NO_DEPOSIT,
+                self.gas.unstake,
+            )
+            .as_return();
+        } else {
+            log!("Unstaking from staking pool: {}", amount);
+            ext_staking_pool::unstake(
+                amount.into(),
+                self.staking_pool_account_id.clone(),
+                NO_DEPOSIT,
+                self.gas.unstake,
+            )
+            .as_return();
+        }
     }
 
     #[private]
-    pub fn on_withdraw(&mut self, unstaked_amount: U128, unstake_all: bool) {
-        require!(is_promise_success(), "Withdraw failed");
-        log!(
-            "Withdraw success! Received unstaked rewards: {}",
-            unstaked_amount.0
-        );
-        self.rewards_received += unstaked_amount.0;
-        // TODO: Send some rewards to the owner.
-        self.available_rewards += unstaked_amount.0;
-        if unstake_all {
-            self.internal_unstake_all();
+    pub fn on_withdraw(
+        &mut self,
+        unstaked_amount: U128,
+        unstake_amount: U128,
+        keeper_id: AccountId,
+    ) {
+        if !is_promise_success() {
+            // Retried with the next ping, the unstaked NEAR is still at the staking pool.
+            self.internal_record_error("withdraw", "Withdraw failed", unstaked_amount.0);
+            return;
+        }
+        self.last_withdraw = env::block_timestamp();
+        self.pending_at_pool = self.pending_at_pool.saturating_sub(unstaked_amount.0);
+        self.internal_set_pool_stage(PipelineStage::Idle);
+        let returned = self.internal_return_released_restake(unstaked_amount.0);
+        if unstaked_amount.0 > returned {
+            self.internal_receive_rewards(unstaked_amount.0 - returned, keeper_id);
+        }
+        if unstake_amount.0 > 0 {
+            self.internal_unstake_rewards(unstake_amount.0);
         }
     }
 
     pub fn set_full_rewards_duration(&mut self, full_rewards_duration_sec: u32) {
-        self.assert_owner();
+        self.assert_owner_or_timelock();
         self.full_rewards_duration = u64::from(full_rewards_duration_sec) * 10u64.pow(9);
+        Event::ConfigChanged {
+            field: "full_rewards_duration",
+        }
+        .emit();
     }
 
     pub fn set_farm_duration(&mut self, farm_duration_sec: u32) {
-        self.assert_owner();
+        self.assert_owner_or_timelock();
         self.farm_duration = u64::from(farm_duration_sec) * 10u64.pow(9);
+        Event::ConfigChanged {
+            field: "farm_duration",
+        }
+        .emit();
     }
 
-    pub fn set_swap_path(&mut self, swap_path: Vec<Action>) {
+    pub fn set_farm_duration_bounds(
+        &mut self,
+        min_farm_duration_sec: u32,
+        max_farm_duration_sec: u32,
+    ) {
+        self.assert_owner_or_timelock();
+        require!(
+            min_farm_duration_sec > 0 && min_farm_duration_sec <= max_farm_duration_sec,
+            "Invalid farm duration bounds"
+        );
+        self.min_farm_duration = u64::from(min_farm_duration_sec) * 10u64.pow(9);
+        self.max_farm_duration = u64::from(max_farm_duration_sec) * 10u64.pow(9);
+        Event::ConfigChanged {
+            field: "farm_duration_bounds",
+        }
+        .emit();
+    }
+
+    /// Overrides `farm_duration` for the next reward token distribution only, e.g. for a boost.
+    pub fn set_next_farm_duration(&mut self, farm_duration_sec: Option<u32>) {
         self.assert_owner();
+        self.internal_set_next_farm_duration(farm_duration_sec);
+    }
+
+    /// Sets the swap path and quotes `SWAP_PATH_PROBE_AMOUNT` through it, reporting the
+    /// output in a `swap_path_quoted` event.
+    pub fn set_swap_path(&mut self, swap_path: Vec<Action>) -> Promise {
+        self.assert_owner_or_timelock();
         self.swap_path = swap_path;
+        self.swap_path_index = 0;
+        self.internal_reset_dcl_dex(0);
+        self.assert_valid_swap_path();
+        Event::ConfigChanged { field: "swap_path" }.emit();
+        self.internal_fetch_token_decimals();
+        self.internal_quote_swap_path_hop(0, SWAP_PATH_PROBE_AMOUNT)
+    }
+
+    #[private]
+    pub fn on_swap_path_quote(
+        &mut self,
+        #[callback] quote: DexQuote,
+        hop_index: u32,
+    ) -> PromiseOrValue<()> {
+        let amount_out = quote.amount();
+        let next_hop_index = hop_index + 1;
+        if (next_hop_index as usize) < self.swap_path.len() {
+            PromiseOrValue::Promise(self.internal_quote_swap_path_hop(next_hop_index, amount_out.0))
+        } else {
+            Event::SwapPathQuoted {
+                probe_amount: SWAP_PATH_PROBE_AMOUNT.into(),
+                amount_out,
+            }
+            .emit();
+            PromiseOrValue::Value(())
+        }
+    }
+
+    /// Sets the paths to fall back to, in order, when swaps on the current path fail on slippage.
+    /// They are swapped on REF until `set_swap_path_dex` says otherwise.
+    pub fn set_fallback_swap_paths(&mut self, fallback_swap_paths: Vec<Vec<Action>>) {
+        self.assert_owner_or_timelock();
+        require!(
+            fallback_swap_paths.len() <= MAX_FALLBACK_SWAP_PATHS,
+            "Too many fallback swap paths"
+        );
+        self.fallback_swap_paths = fallback_swap_paths;
+        self.swap_path_dexes.truncate(1);
+        self.swap_path_index = 0;
+        self.assert_valid_swap_path();
+        Event::ConfigChanged {
+            field: "fallback_swap_paths",
+        }
+        .emit();
+        self.internal_fetch_token_decimals();
+    }
+
+    /// Restricts the intermediate hops of swap paths to the given tokens, `None` lifts the
+    /// restriction. The current swap paths have to comply.
+    pub fn set_intermediate_tokens(&mut self, intermediate_tokens: Option<Vec<AccountId>>) {
+        self.assert_owner_or_timelock();
+        if let Some(intermediate_tokens) = &intermediate_tokens {
+            require!(
+                intermediate_tokens.len() <= MAX_INTERMEDIATE_TOKENS,
+                "Too many intermediate tokens"
+            );
+        }
+        self.intermediate_tokens = intermediate_tokens;
         self.assert_valid_swap_path();
+        Event::ConfigChanged {
+            field: "intermediate_tokens",
+        }
+        .emit();
+    }
+
+    pub fn get_current_swap_path(&self) -> Vec<Action> {
+        self.internal_current_swap_path().clone()
+    }
+
+    pub fn set_max_swap_chunk(&mut self, max_swap_chunk: U128) {
+        self.assert_owner_or_timelock();
+        self.max_swap_chunk = max_swap_chunk.0;
+        self.assert_valid_min_swap_amount();
+        Event::ConfigChanged {
+            field: "max_swap_chunk",
+        }
+        .emit();
+    }
+
+    pub fn set_check_pool_pause(&mut self, check_pool_pause: bool) {
+        self.assert_owner_or_timelock();
+        self.check_pool_pause = check_pool_pause;
+        Event::ConfigChanged {
+            field: "check_pool_pause",
+        }
+        .emit();
     }
 
     pub fn set_max_near_reward(&mut self, max_near_reward: U128) {
-        self.assert_owner();
+        self.assert_owner_or_timelock();
         self.max_near_reward = max_near_reward.0;
+        Event::ConfigChanged {
+            field: "max_near_reward",
+        }
+        .emit();
+    }
+
+    pub fn set_swap_fraction(
+        &mut self,
+        swap_fraction_bps: u32,
+        near_payout_account_id: Option<AccountId>,
+    ) {
+        self.assert_owner_or_timelock();
+        require!(swap_fraction_bps <= MAX_BPS, "Swap fraction is too large");
+        require!(
+            swap_fraction_bps == MAX_BPS || near_payout_account_id.is_some(),
+            "Missing NEAR payout account"
+        );
+        self.swap_fraction_bps = swap_fraction_bps;
+        self.near_payout_account_id = near_payout_account_id;
+        Event::ConfigChanged {
+            field: "swap_fraction_bps",
+        }
+        .emit();
+    }
+
+    pub fn set_owner_fee_bps(&mut self, owner_fee_bps: u32) {
+        self.assert_owner_or_timelock();
+        require!(
+            owner_fee_bps + self.keeper_reward_bps <= MAX_BPS,
+            "Owner fee is too large"
+        );
+        self.owner_fee_bps = owner_fee_bps;
+        Event::ConfigChanged {
+            field: "owner_fee_bps",
+        }
+        .emit();
+    }
+
+    /// Transfers all accrued owner fees to the owner.
+    pub fn claim_owner_fees(&mut self) -> Promise {
+        self.assert_owner();
+        let amount = self.owner_fees_accrued;
+        require!(amount > 0, "No fees to claim");
+        self.internal_maybe_snapshot_accounting();
+        Event::OwnerFeesClaimed {
+            amount: amount.into(),
+        }
+        .emit();
+        self.owner_fees_accrued = 0;
+        self.owner_fees_claimed += amount;
+        Promise::new(self.owner_id.clone()).transfer(amount)
+    }
+
+    pub fn get_owner_fees(&self) -> OwnerFees {
+        OwnerFees {
+            owner_fee_bps: self.owner_fee_bps,
+            accrued: self.owner_fees_accrued.into(),
+            claimed: self.owner_fees_claimed.into(),
+        }
+    }
+
+    pub fn set_fallback_to_wnear(&mut self, fallback_to_wnear: bool, wnear_farm_id: Option<u64>) {
+        self.assert_owner_or_timelock();
+        require!(
+            !fallback_to_wnear || wnear_farm_id.is_some(),
+            "Missing wNEAR farm ID"
+        );
+        self.fallback_to_wnear = fallback_to_wnear;
+        self.wnear_farm_id = wnear_farm_id;
+        Event::ConfigChanged {
+            field: "fallback_to_wnear",
+        }
+        .emit();
+        if self.wnear_farm_id.is_some() {
+            self.internal_register_storage();
+        }
+    }
+
+    pub fn set_max_swap_delay_blocks(&mut self, max_swap_delay_blocks: BlockHeight) {
+        self.assert_owner_or_timelock();
+        self.max_swap_delay_blocks = max_swap_delay_blocks;
+        Event::ConfigChanged {
+            field: "max_swap_delay_blocks",
+        }
+        .emit();
+    }
+
+    pub fn set_accounting_snapshot_interval(&mut self, accounting_snapshot_interval_sec: u32) {
+        self.assert_owner_or_timelock();
+        self.accounting_snapshot_interval =
+            u64::from(accounting_snapshot_interval_sec) * 10u64.pow(9);
+        Event::ConfigChanged {
+            field: "accounting_snapshot_interval",
+        }
+        .emit();
+    }
+
+    pub fn set_keeper_reward(&mut self, keeper_reward_bps: u32, max_keeper_reward: U128) {
+        self.assert_owner_or_timelock();
+        require!(
+            keeper_reward_bps + self.owner_fee_bps <= MAX_BPS,
+            "Keeper reward is too large"
+        );
+        self.keeper_reward_bps = keeper_reward_bps;
+        self.max_keeper_reward = max_keeper_reward.0;
+        Event::ConfigChanged {
+            field: "keeper_reward",
+        }
+        .emit();
+    }
+
+    pub fn set_reward_price_band(&mut self, reward_price_band: Option<PriceBand>) {
+        self.assert_owner_or_timelock();
+        if let Some(band) = &reward_price_band {
+            band.min.assert_valid();
+            band.max.assert_valid();
+            require!(
+                band.min.cmp_value(&band.max) != std::cmp::Ordering::Greater,
+                "Invalid price band"
+            );
+        }
+        self.reward_price_band = reward_price_band;
+        Event::ConfigChanged {
+            field: "reward_price_band",
+        }
+        .emit();
+    }
+
+    /// Delegates swaps to an approved executor contract, or back to REF with `None`.
+    pub fn set_swap_executor(&mut self, swap_executor_id: Option<AccountId>) {
+        self.assert_owner_or_timelock();
+        self.swap_executor_id = swap_executor_id;
+        Event::ConfigChanged {
+            field: "swap_executor_id",
+        }
+        .emit();
+    }
+
+    /// Lets `referral_id` earn REF's referral share of the swap fees, if REF accepts it.
+    pub fn set_referral_id(&mut self, referral_id: Option<AccountId>) {
+        self.assert_owner_or_timelock();
+        self.referral_id = referral_id;
+        Event::ConfigChanged {
+            field: "referral_id",
+        }
+        .emit();
+    }
+
+    pub fn set_oracle_limits(
+        &mut self,
+        max_recency_sec: DurationSec,
+        max_staleness_sec: DurationSec,
+    ) {
+        self.assert_owner_or_timelock();
+        require!(
+            max_recency_sec > 0 && max_recency_sec <= MAX_ORACLE_RECENCY_SEC,
+            "Invalid oracle recency limit"
+        );
+        require!(
+            max_staleness_sec > 0 && max_staleness_sec <= MAX_ORACLE_STALENESS_SEC,
+            "Invalid oracle staleness limit"
+        );
+        self.oracle_max_recency_sec = max_recency_sec;
+        self.oracle_max_staleness = u64::from(max_staleness_sec) * 10u64.pow(9);
+        Event::ConfigChanged {
+            field: "oracle_limits",
+        }
+        .emit();
+    }
+
+    pub fn set_min_fill_bps(&mut self, min_fill_bps: u32) {
+        self.assert_owner_or_timelock();
+        require!(
+            min_fill_bps > 0 && min_fill_bps <= MAX_BPS,
+            "Invalid minimum fill"
+        );
+        self.min_fill_bps = min_fill_bps;
+        Event::ConfigChanged {
+            field: "min_fill_bps",
+        }
+        .emit();
+    }
+
+    pub fn set_min_wrap_amount(&mut self, min_wrap_amount: U128) {
+        self.assert_owner_or_timelock();
+        self.min_wrap_amount = min_wrap_amount.0;
+        Event::ConfigChanged {
+            field: "min_wrap_amount",
+        }
+        .emit();
+    }
+
+    /// Refuses price triggered distributions swapping less than `min_swap_amount`, so dust
+    /// isn't eaten by gas and slippage. Has to fit in `max_swap_chunk`.
+    pub fn set_min_swap_amount(&mut self, min_swap_amount: U128) {
+        self.assert_owner_or_timelock();
+        self.min_swap_amount = min_swap_amount.0;
+        self.assert_valid_min_swap_amount();
+        Event::ConfigChanged {
+            field: "min_swap_amount",
+        }
+        .emit();
+    }
+
+    /// Spaces price triggered distributions at least `min_distribution_interval_sec` apart.
+    pub fn set_min_distribution_interval(&mut self, min_distribution_interval_sec: u32) {
+        self.assert_owner_or_timelock();
+        self.min_distribution_interval = u64::from(min_distribution_interval_sec) * 10u64.pow(9);
+        Event::ConfigChanged {
+            field: "min_distribution_interval",
+        }
+        .emit();
+    }
+
+    pub fn set_max_price_deviation_bps(&mut self, max_price_deviation_bps: u32) {
+        self.assert_owner_or_timelock();
+        self.max_price_deviation_bps = max_price_deviation_bps;
+        Event::ConfigChanged {
+            field: "max_price_deviation_bps",
+        }
+        .emit();
+    }
+
+    /// Allows the next swap to deviate from the last swap rate, e.g. after a real market move.
+    pub fn override_price_deviation(&mut self) {
+        self.assert_owner();
+        self.price_deviation_override = true;
+        Event::ConfigChanged {
+            field: "price_deviation_override",
+        }
+        .emit();
+    }
+
+    /// Releases the distribution lock in case `on_swap` failed without clearing it.
+    pub fn release_distribution_lock(&mut self) {
+        self.assert_owner();
+        require!(self.distribution_in_flight, "No distribution in flight");
+        self.distribution_in_flight = false;
+        Event::ConfigChanged {
+            field: "distribution_in_flight",
+        }
+        .emit();
+    }
+
+    pub fn set_require_route_prices(&mut self, require_route_prices: bool) {
+        self.assert_owner_or_timelock();
+        self.require_route_prices = require_route_prices;
+        Event::ConfigChanged {
+            field: "require_route_prices",
+        }
+        .emit();
+    }
+
+    pub fn set_zero_reward_policy(&mut self, zero_reward_policy: ZeroRewardPolicy) {
+        self.assert_owner();
+        self.zero_reward_policy = zero_reward_policy;
+        Event::ConfigChanged {
+            field: "zero_reward_policy",
+        }
+        .emit();
     }
 
     pub fn get_near_reward_for_distribution(&self) -> U128 {
-        let time_diff = env::block_timestamp() - self.last_reward_distribution;
-        let reward = if time_diff >= self.full_rewards_duration {
-            self.available_rewards
-        } else {
-            u128_ratio(
-                self.available_rewards,
-                time_diff as u128,
-                self.full_rewards_duration as u128,
-            )
-        };
-        std::cmp::min(reward, self.max_near_reward).into()
+        let time_diff = env::block_timestamp().saturating_sub(self.last_reward_distribution);
+        self.internal_reward_for(self.available_rewards, time_diff)
+            .into()
+    }
+
+    /// Expected distributions over the next `horizon_days`, assuming no new rewards arrive
+    /// and the oracle calls once a day, as early as the distribution windows and
+    /// `min_distribution_interval` allow.
+    pub fn get_schedule(&self, horizon_days: u32) -> Vec<ScheduledDistribution> {
+        let horizon_days = std::cmp::min(horizon_days, MAX_SCHEDULE_HORIZON_DAYS);
+        let mut available_rewards = self.available_rewards;
+        let mut queued_swap_amount = self.queued_swap_amount;
+        let mut last_distribution = self.last_reward_distribution;
+        let mut last_price_distribution = self.last_price_distribution;
+        let mut day_start = env::block_timestamp();
+        let mut schedule = vec![];
+        for _ in 0..horizon_days {
+            if available_rewards == 0 && queued_swap_amount == 0 {
+                break;
+            }
+            let day_end = day_start + ONE_DAY;
+            let earliest = std::cmp::max(
+                day_start,
+                last_price_distribution + self.min_distribution_interval,
+            );
+            day_start = day_end;
+            let timestamp = match self.internal_next_window_time(earliest, day_end) {
+                Some(timestamp) => timestamp,
+                None => continue,
+            };
+            let step = distribution_step(
+                self.internal_reward_for(
+                    available_rewards,
+                    timestamp.saturating_sub(last_distribution),
+                ),
+                queued_swap_amount,
+                self.swap_fraction_bps,
+                self.max_swap_chunk,
+            );
+            let pending_swap_amount = step.swap_amount + step.queued_swap_amount;
+            if (step.released == 0 && step.swap_amount == 0)
+                || (self.swap_fraction_bps > 0 && pending_swap_amount < self.min_swap_amount)
+            {
+                continue;
+            }
+            schedule.push(ScheduledDistribution {
+                timestamp: timestamp.into(),
+                near_amount: step.released.into(),
+                swap_amount: step.swap_amount.into(),
+            });
+            if step.released > 0 {
+                available_rewards -= step.released;
+                last_distribution = timestamp;
+            }
+            queued_swap_amount = step.queued_swap_amount;
+            last_price_distribution = timestamp;
+        }
+        schedule
     }
 
+    /// Adds the attached NEAR to the rewards. The donation is kept in the donor log with an
+    /// optional `memo`. A donation of at least `donation_vesting.min_amount` is released into
+    /// the rewards gradually, over `vesting_duration_sec` if that's longer.
+    /// Takes `DonateArgs` as JSON, an empty input or `{}` donates without either.
     #[payable]
     pub fn donate(&mut self) {
+        self.assert_not_paused();
+        let DonateArgs {
+            memo,
+            vesting_duration_sec,
+        } = env::input()
+            .filter(|input| !input.is_empty())
+            .map(|input| serde_json::from_slice(&input).expect("Invalid donate arguments"))
+            .unwrap_or_default();
+        if let Some(memo) = &memo {
+            require!(
+                memo.len() <= MAX_DONATION_MEMO_LENGTH,
+                "Donation memo is too long"
+            );
+        }
         let attached_deposit = env::attached_deposit();
-        log!("Thank for you {} NEAR", attached_deposit);
+        self.assert_donation_accepted(attached_deposit);
+        self.internal_maybe_snapshot_accounting();
+        let donor_id = env::predecessor_account_id();
+        Event::Donation {
+            donor_id: donor_id.clone(),
+            amount: attached_deposit.into(),
+            memo: memo.clone(),
+        }
+        .emit();
+        self.internal_record_donation(donor_id.clone(), attached_deposit, memo);
         self.rewards_received += attached_deposit;
-        self.available_rewards += attached_deposit;
+        self.near_donated += attached_deposit;
+        let vesting_duration_sec =
+            self.internal_donation_vesting_duration(attached_deposit, vesting_duration_sec);
+        if vesting_duration_sec > 0 {
+            self.internal_add_vesting_donation(donor_id, attached_deposit, vesting_duration_sec);
+        } else {
+            self.available_rewards += attached_deposit;
+        }
+    }
+
+    /// Donations below `min_donation` are refused, in NEAR or wNEAR.
+    pub fn set_min_donation(&mut self, min_donation: U128) {
+        self.assert_owner_or_timelock();
+        self.min_donation = min_donation.0;
+        Event::ConfigChanged {
+            field: "min_donation",
+        }
+        .emit();
+    }
+
+    /// Closes or reopens NEAR and token donations, e.g. while winding down the contract.
+    pub fn set_donations_enabled(&mut self, donations_enabled: bool) {
+        self.assert_owner_or_timelock();
+        self.donations_enabled = donations_enabled;
+        Event::ConfigChanged {
+            field: "donations_enabled",
+        }
+        .emit();
     }
 
     #[private]
@@ -367,47 +1379,231 @@ impl Contract {
         #[callback_result] transfer_amount: Result<U128, PromiseError>,
         min_amount_out: U128,
         reward: U128,
+        from_buffer: U128,
+        initiated_at: U64,
+        oracle_amount_out: Option<U128>,
     ) {
-        if let Ok(transfer_amount) = transfer_amount {
-            if transfer_amount.0 == reward.0 {
-                self.internal_distribute_usn(min_amount_out.0).as_return();
-                return;
+        self.distribution_in_flight = false;
+        self.internal_end_distribution_stage();
+        self.internal_maybe_snapshot_accounting();
+        self.near_swapping -= reward.0;
+        if let Ok(used_amount) = transfer_amount {
+            // The used amount is already converted, the rest returns to the wNEAR buffer.
+            let used_amount = used_amount.0;
+            let unused_amount = reward.0 - used_amount;
+            self.wrapped_amount += unused_amount;
+            self.available_rewards += unused_amount;
+            self.near_swapped += used_amount;
+            let min_fill = u128_ratio(reward.0, self.min_fill_bps as u128, MAX_BPS as u128);
+            if used_amount > 0 && used_amount >= min_fill {
+                // The swap returns the wNEAR it used, the output is read from the balance.
+                let oracle_reward_amount = oracle_amount_out
+                    .map(|oracle_amount_out| u128_ratio(oracle_amount_out.0, used_amount, reward.0))
+                    .unwrap_or(0);
+                ext_fungible_token::ft_balance_of(
+                    env::current_account_id(),
+                    self.reward_token_id.clone(),
+                    NO_DEPOSIT,
+                    FT_BALANCE_OF_GAS,
+                )
+                .then(ext_self::on_swap_output(
+                    used_amount.into(),
+                    u128_ratio(min_amount_out.0, used_amount, reward.0).into(),
+                    initiated_at,
+                    oracle_reward_amount.into(),
+                    env::current_account_id(),
+                    NO_DEPOSIT,
+                    self.gas.on_swap - ON_SWAP_OUTPUT_READ_GAS,
+                ))
+                .as_return();
             } else {
-                log!("Swap failed by slippage");
+                self.internal_swap_failed(reward.0, "slippage");
+                self.internal_on_slippage_failure();
             }
         } else {
-            log!("Swap failed by gas");
+            // The failed batch reverted `near_deposit` too, only the part taken from the
+            // buffer is wNEAR and the rest is NEAR again.
+            self.internal_revert_wrap_near(reward.0, from_buffer.0);
+            self.available_rewards += reward.0;
+            self.internal_swap_failed(reward.0, "gas");
+            let wnear_amount = std::cmp::min(reward.0, self.wrapped_amount);
+            if self.fallback_to_wnear && wnear_amount > 0 {
+                if let Some(wnear_farm_id) = self.wnear_farm_id {
+                    Event::WnearDistributed {
+                        amount: wnear_amount.into(),
+                        farm_id: wnear_farm_id,
+                    }
+                    .emit();
+                    self.wrapped_amount -= wnear_amount;
+                    self.available_rewards -= wnear_amount;
+                    self.wnear_distributed += wnear_amount;
+                    self.internal_farm_tokens(
+                        self.wrap_near_contract_id.clone(),
+                        Some(wnear_farm_id),
+                        wnear_amount,
+                        None,
+                        self.farm_duration,
+                    )
+                    .as_return();
+                }
+            }
         }
-        self.wrapped_amount += reward.0;
-        self.available_rewards += reward.0;
     }
 
+    /// Takes the swap output as the reward token balance less the reward tokens accounted for
+    /// otherwise. Without the balance the output stays for `distribute_reward_tokens`.
     #[private]
-    pub fn on_usn_balance(&mut self, #[callback] usn_amount: U128) {
-        if usn_amount.0 > 0 {
-            self.internal_distribute_usn(usn_amount.0).as_return();
+    pub fn on_swap_output(
+        &mut self,
+        #[callback_result] balance: Result<U128, PromiseError>,
+        near_amount: U128,
+        min_amount_out: U128,
+        initiated_at: U64,
+        oracle_amount_out: U128,
+    ) {
+        let accounted = self.held_reward_tokens
+            + self.internal_farm_splits_pending()
+            + self.pending_reward_donations
+            + self.expired_swap_output;
+        let amount_out = match balance {
+            Ok(balance) => balance.0.saturating_sub(accounted),
+            Err(_) => {
+                self.internal_record_error("swap", "output_unknown", near_amount.0);
+                return;
+            }
+        };
+        self.swaps_executed += 1;
+        self.reward_tokens_swapped += amount_out;
+        let delay = env::block_height() - initiated_at.0;
+        if self.max_swap_delay_blocks > 0 && delay > self.max_swap_delay_blocks {
+            // The swap went through at a possibly stale price. Keep the output on the
+            // balance for a manual `distribute_reward_tokens` instead of farming it now.
+            self.expired_swap_output += amount_out;
+            Event::SwapExpired {
+                near_amount,
+                delay_blocks: delay,
+            }
+            .emit();
+            return;
+        }
+        Event::SwapExecuted {
+            near_amount,
+            min_amount_out,
+            amount_out: amount_out.into(),
+        }
+        .emit();
+        self.internal_record_distribution_history(near_amount.0, amount_out, oracle_amount_out.0);
+        self.last_swap_rate = Some(u128_ratio(amount_out, ONE_NEAR, near_amount.0));
+        self.internal_on_swap_succeeded();
+        let farm_amount = self.internal_send_treasury_share(amount_out);
+        if farm_amount > 0 {
+            if let Some(promise) = self.internal_distribute_reward_tokens(farm_amount) {
+                self.internal_set_pipeline_stage(PipelineStage::Distributing);
+                promise.as_return();
+            }
+        }
+    }
+
+    #[private]
+    pub fn on_reward_token_balance(&mut self, #[callback] reward_amount: U128) {
+        self.internal_maybe_snapshot_accounting();
+        // Held back reward tokens are farmed on top of whatever gets distributed.
+        let reward_amount = reward_amount
+            .0
+            .saturating_sub(self.held_reward_tokens + self.internal_farm_splits_pending());
+        // Output of expired swaps is part of the balance and already in the history.
+        let expired_output =
+            std::cmp::min(std::mem::take(&mut self.expired_swap_output), reward_amount);
+        if reward_amount > 0 {
+            // Donations aren't swap output and are left out of the history.
+            let donations = std::cmp::min(
+                std::mem::take(&mut self.pending_reward_donations),
+                reward_amount,
+            );
+            if reward_amount > donations + expired_output {
+                self.internal_record_distribution_history(
+                    0,
+                    reward_amount - donations - expired_output,
+                    0,
+                );
+            }
+            if let Some(promise) = self.internal_distribute_reward_tokens(reward_amount) {
+                self.internal_set_pipeline_stage(PipelineStage::Distributing);
+                promise.as_return();
+            }
+        }
+    }
+
+    #[private]
+    pub fn on_reward_tokens_farmed(
+        &mut self,
+        #[callback_result] used_amount: Result<U128, PromiseError>,
+        amount: U128,
+        farm_id: Option<u64>,
+    ) {
+        self.internal_end_distribution_stage();
+        let used_amount = used_amount.map(|amount| amount.0).unwrap_or(0);
+        let refunded_amount = amount.0 - used_amount;
+        if refunded_amount == 0 {
+            return;
+        }
+        // Refunded tokens are retried with the next distribution.
+        self.reward_tokens_distributed -= refunded_amount;
+        self.internal_hold_back_reward_tokens(refunded_amount, "farm_refunded");
+        self.internal_record_error("farm", "farm_refunded", refunded_amount);
+        if let Some(schedule) = self.farm_schedule.as_mut() {
+            schedule.amount = schedule.amount.0.saturating_sub(refunded_amount).into();
+        }
+        if farm_id.is_none() && used_amount == 0 {
+            self.create_farm = true;
+        }
+    }
+
+    #[private]
+    pub fn on_pool_pause_checked(
+        &mut self,
+        #[callback_result] paused: Result<bool, PromiseError>,
+        reward_amount: U128,
+    ) {
+        // Pools that don't expose the pause state are treated as running.
+        if matches!(paused, Ok(true)) {
+            self.internal_end_distribution_stage();
+            self.internal_hold_back_reward_tokens(reward_amount.0, "pool_paused");
+            return;
+        }
+        match self.internal_farm_reward_tokens(reward_amount.0) {
+            Some(promise) => promise.as_return(),
+            None => self.internal_end_distribution_stage(),
         }
     }
 
+    /// The balance includes the rounding yoctoNEAR, which aren't part of the wNEAR buffer.
     #[private]
     pub fn on_wrap_near_balance(&mut self, #[callback] wnear_amount: U128) {
-        self.wrapped_amount = wnear_amount.0;
+        self.wrapped_amount = wnear_amount.0.saturating_sub(self.rounding_adjustments);
     }
 
-    pub fn distribute_usn(&mut self) -> Promise {
+    pub fn distribute_reward_tokens(&mut self) -> Promise {
+        self.assert_not_paused();
+        self.assert_setup_complete();
         ext_fungible_token::ft_balance_of(
             env::current_account_id(),
-            self.usn_contract_id.clone(),
+            self.reward_token_id.clone(),
             NO_DEPOSIT,
             FT_BALANCE_OF_GAS,
         )
-        .then(ext_self::on_usn_balance(
+        .then(ext_self::on_reward_token_balance(
             env::current_account_id(),
             NO_DEPOSIT,
-            ON_SWAP_GAS,
+            self.gas.on_swap,
         ))
     }
 
+    /// Deprecated alias of `distribute_reward_tokens` kept for existing keepers.
+    pub fn distribute_usn(&mut self) -> Promise {
+        self.distribute_reward_tokens()
+    }
+
     pub fn refresh_wrap_near_balance(&mut self) -> Promise {
         self.assert_owner();
         ext_fungible_token::ft_balance_of(
@@ -419,10 +1615,36 @@ impl Contract {
         .then(ext_self::on_wrap_near_balance(
             env::current_account_id(),
             NO_DEPOSIT,
-            ON_SWAP_GAS,
+            self.gas.on_swap,
         ))
     }
 
+    pub fn get_network_profile(&self) -> NetworkProfile {
+        self.network
+    }
+
+    pub fn pause(&mut self) {
+        self.assert_owner();
+        require!(!self.paused, "Already paused");
+        self.paused = true;
+        Event::Paused.emit();
+    }
+
+    pub fn unpause(&mut self) {
+        self.assert_owner();
+        require!(self.paused, "Not paused");
+        require!(
+            self.migration_progress.is_none(),
+            "Chunked migration is in progress"
+        );
+        self.paused = false;
+        Event::Unpaused.emit();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
     pub fn get_staking_pool(&self) -> AccountId {
         self.staking_pool_account_id.clone()
     }
@@ -430,146 +1652,838 @@ impl Contract {
 
 #[near_bindgen]
 impl OraclePriceReceiver for Contract {
-    #[allow(unused)]
-    fn oracle_on_call(&mut self, sender_id: AccountId, data: PriceData, msg: String) -> Promise {
-        assert_eq!(env::predecessor_account_id(), self.oracle_contract_id);
-
-        assert!(
-            data.recency_duration_sec <= 90,
-            "Recency duration in the oracle call is larger than allowed maximum"
+    fn oracle_on_call(
+        &mut self,
+        sender_id: AccountId,
+        data: PriceData,
+        msg: String,
+    ) -> PromiseOrValue<OracleResult> {
+        let oracle_id = env::predecessor_account_id();
+        require!(self.is_oracle(&oracle_id), "Not an oracle");
+        require!(
+            self.price_source == PriceSource::PushOracle,
+            "Push oracle is not the price source"
         );
+
+        // Rejections the oracle operator can act on are recorded instead of failing.
         let timestamp = env::block_timestamp();
-        assert!(
-            data.timestamp <= timestamp,
-            "Price data timestamp is in the future"
-        );
-        assert!(
-            timestamp - data.timestamp <= 15_000_000_000,
-            "Price data timestamp is too stale"
-        );
+        let rejection = if self.paused {
+            Some("Contract is paused")
+        } else if !self.setup_complete {
+            Some("Setup is not finalized")
+        } else if self.distribution_in_flight {
+            Some("A distribution is already in flight")
+        } else if self.burrow_pending {
+            Some("Rewards are being withdrawn from Burrow")
+        } else if self.direct_wnear_farm_id.is_some() {
+            Some("Rewards are farmed as wNEAR, use distribute_wnear")
+        } else if !self.is_in_distribution_window() {
+            Some("Outside of the distribution windows")
+        } else if timestamp < self.last_price_distribution + self.min_distribution_interval {
+            Some("The previous distribution is too recent")
+        } else if self.internal_pending_swap_amount() < self.min_swap_amount {
+            Some("Reward is below the minimum swap amount")
+        } else if data.recency_duration_sec > self.oracle_max_recency_sec {
+            Some("Recency duration in the oracle call is larger than allowed maximum")
+        } else if data.timestamp > timestamp {
+            Some("Price data timestamp is in the future")
+        } else if timestamp - data.timestamp > self.oracle_max_staleness {
+            Some("Price data timestamp is too stale")
+        } else {
+            None
+        };
+        if let Some(reason) = rejection {
+            return PromiseOrValue::Value(self.internal_record_oracle_result(
+                oracle_id,
+                false,
+                Some(reason),
+            ));
+        }
 
-        let reward = self.get_near_reward_for_distribution().0;
-        require!(reward > 0, "Nothing to distribute");
+        let mut quote = None;
+        let mut plan = None;
+        if !msg.is_empty() {
+            let msg: OracleCallMsg = serde_json::from_str(&msg).expect("Invalid message");
+            if let Some(farm_duration_sec) = msg.farm_duration_sec {
+                require!(
+                    self.is_operator(&sender_id),
+                    "Only operators can set the farm duration"
+                );
+                self.internal_set_next_farm_duration(Some(farm_duration_sec));
+            }
+            if let Some(farm_start_date) = msg.farm_start_date {
+                require!(
+                    self.is_operator(&sender_id),
+                    "Only operators can set the farm start date"
+                );
+                self.internal_set_next_farm_start_date(Some(farm_start_date.0));
+            }
+            if let Some(distribution_mode) = msg.distribution_mode {
+                require!(
+                    self.is_operator(&sender_id),
+                    "Only operators can set the distribution mode"
+                );
+                self.internal_set_next_distribution_mode(Some(distribution_mode));
+            }
+            quote = msg
+                .swap_quote
+                .map(|signed_quote| self.internal_verify_swap_quote(signed_quote));
+            plan = msg
+                .distribution_plan
+                .map(|plan| self.internal_reveal_distribution_plan(plan));
+        }
 
         let prices: HashMap<AccountId, Price> = data
             .prices
             .into_iter()
             .filter_map(|AssetOptionalPrice { asset_id, price }| {
-                let token_id =
-                    AccountId::try_from(asset_id).expect("Asset is not a valid token ID");
+                let token_id = self.internal_oracle_token_id(&asset_id)?;
                 price.map(|price| {
                     price.assert_valid();
                     (token_id, price)
                 })
             })
             .collect();
+        if !self.internal_prices_match_decimals(&prices) {
+            return PromiseOrValue::Value(self.internal_record_oracle_result(
+                oracle_id,
+                false,
+                Some("Price decimals don't match the token decimals"),
+            ));
+        }
 
-        self.available_rewards -= reward;
-        self.last_reward_distribution = env::block_timestamp();
-
-        let usn_price = prices
-            .get(&self.usn_contract_id)
-            .expect("Missing USN price");
-        let wnear_price = prices
+        let reward_price = *prices
+            .get(&self.reward_token_id)
+            .expect("Missing reward token price");
+        let wnear_price = *prices
             .get(&self.wrap_near_contract_id)
             .expect("Missing wNEAR price");
+        let (wnear_price, reward_price) = match self.internal_aggregate_prices(OracleReport {
+            oracle_id: oracle_id.clone(),
+            timestamp: data.timestamp,
+            wnear_price,
+            reward_price,
+        }) {
+            Some(prices) => prices,
+            None => {
+                return PromiseOrValue::Value(self.internal_record_oracle_result(
+                    oracle_id,
+                    true,
+                    Some("Waiting for more oracle reports"),
+                ))
+            }
+        };
+        if let Some(band) = &self.reward_price_band {
+            if band.is_below(&reward_price) {
+                return PromiseOrValue::Value(self.internal_record_oracle_result(
+                    oracle_id,
+                    false,
+                    Some("Reward token price is below the allowed band"),
+                ));
+            }
+        }
 
-        let wnear_extra = if wnear_price.decimals < usn_price.decimals {
-            10u128.pow((usn_price.decimals - wnear_price.decimals) as _)
-        } else {
-            1
+        match self.internal_distribute_with_prices(wnear_price, reward_price, prices, quote, plan) {
+            PromiseOrValue::Promise(promise) => {
+                self.internal_record_oracle_result(oracle_id, true, None);
+                PromiseOrValue::Promise(promise)
+            }
+            PromiseOrValue::Value(()) => PromiseOrValue::Value(self.internal_record_oracle_result(
+                oracle_id,
+                false,
+                Some("Nothing to distribute"),
+            )),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FarmingDetails {
+    /// Name of a new farm.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Start date of the farm, it starts right away without.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_date: Option<U64>,
+    /// End date of the farm.
+    pub end_date: U64,
+    /// Existing farm ID, a new farm is created without it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub farm_id: Option<u64>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OwnerFees {
+    pub owner_fee_bps: u32,
+    pub accrued: U128,
+    pub claimed: U128,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ScheduledDistribution {
+    pub timestamp: U64,
+    /// Reward released from `available_rewards`.
+    pub near_amount: U128,
+    /// NEAR swapped, including queued chunks of earlier rewards.
+    pub swap_amount: U128,
+}
+
+impl Contract {
+    /// Releases the reward due now and swaps it with the minimum output derived from
+    /// the given prices.
+    pub fn internal_distribute_with_prices(
+        &mut self,
+        wnear_price: Price,
+        reward_price: Price,
+        intermediate_prices: HashMap<AccountId, Price>,
+        quote: Option<SwapQuote>,
+        plan: Option<DistributionPlan>,
+    ) -> PromiseOrValue<()> {
+        self.internal_unlock_vested_donations();
+        require!(
+            self.direct_wnear_farm_id.is_none(),
+            "Rewards are farmed as wNEAR, use distribute_wnear"
+        );
+        if self.require_commitment {
+            require!(plan.is_some(), "Missing the committed distribution plan");
+        }
+        let near_reward = self.get_near_reward_for_distribution().0;
+        if self.zero_reward_policy == ZeroRewardPolicy::Skip
+            && near_reward == 0
+            && self.queued_swap_amount == 0
+        {
+            Event::NothingToDistribute.emit();
+            return PromiseOrValue::Value(());
+        }
+        // Without swaps the whole reward is paid out as NEAR.
+        require!(
+            self.swap_fraction_bps == 0
+                || self.internal_pending_swap_amount() >= self.min_swap_amount,
+            "Reward is below the minimum swap amount"
+        );
+        require!(
+            self.is_in_distribution_window(),
+            "Outside of the distribution windows"
+        );
+        let timestamp = env::block_timestamp();
+        require!(
+            timestamp >= self.last_price_distribution + self.min_distribution_interval,
+            "The previous distribution is too recent"
+        );
+        if self.burrow_deposited > 0 {
+            // Withdrawn just in time, the next price update distributes.
+            return PromiseOrValue::Promise(self.internal_withdraw_from_burrow());
+        }
+        self.last_price_distribution = timestamp;
+        let (reward, payout_promise) = self.internal_take_distribution();
+        if reward == 0 {
+            return PromiseOrValue::Promise(payout_promise.unwrap());
+        }
+
+        // A reward token priced above the band can't lower the minimum output below what
+        // the top of the band gives.
+        let reward_price = match &self.reward_price_band {
+            Some(band) => {
+                require!(
+                    !band.is_below(&reward_price),
+                    "Reward token price is below the allowed band"
+                );
+                band.cap(reward_price)
+            }
+            None => reward_price,
         };
 
-        let usn_extra = if usn_price.decimals < wnear_price.decimals {
-            10u128.pow((wnear_price.decimals - usn_price.decimals) as _)
-        } else {
-            1
+        let expected_amount_out = oracle_amount_out(reward, &wnear_price, &reward_price);
+        let oracle_min_amount_out = self.internal_apply_slippage(expected_amount_out);
+        self.assert_price_deviation(u128_ratio(oracle_min_amount_out, ONE_NEAR, reward));
+        self.last_oracle_rate = Some(u128_ratio(expected_amount_out, ONE_NEAR, reward));
+        // A signed quote picks the route and can only raise the minimums set by the oracle.
+        let quoted_min_amount = |amount: Balance| {
+            quote
+                .as_ref()
+                .map(|quote| u128_ratio(amount, reward, quote.amount_in.0))
+                .unwrap_or(0)
+        };
+        let min_amount_out = match &quote {
+            Some(quote) => std::cmp::max(
+                oracle_min_amount_out,
+                quoted_min_amount(quote.min_amount_out.0),
+            ),
+            None => oracle_min_amount_out,
+        };
+        // A committed plan caps the amount and can only raise the minimum output.
+        let min_amount_out = match &plan {
+            Some(plan) => {
+                require!(
+                    reward <= plan.amount.0,
+                    "Reward exceeds the committed amount"
+                );
+                std::cmp::max(
+                    min_amount_out,
+                    u128_ratio(plan.min_amount_out.0, reward, plan.amount.0),
+                )
+            }
+            None => min_amount_out,
         };
 
-        let oracle_amount_out = u128_ratio(
+        // Protect intermediate hops whose output token has a known price as well.
+        let mut token_prices = intermediate_prices;
+        token_prices.insert(self.wrap_near_contract_id.clone(), wnear_price);
+        token_prices.insert(self.reward_token_id.clone(), reward_price);
+        let swap_path = match &quote {
+            Some(quote) => &quote.swap_path,
+            None => self.internal_current_swap_path(),
+        };
+        let hop_amounts = route_oracle_amounts(reward, swap_path, &token_prices);
+        if self.require_route_prices {
+            require!(
+                hop_amounts.iter().all(Option::is_some),
+                "Missing oracle price of a token on the swap path"
+            );
+        }
+        let hop_min_amounts = swap_path[..swap_path.len() - 1]
+            .iter()
+            .zip(hop_amounts)
+            .map(|(action, hop_amount)| {
+                let oracle_min_amount = hop_amount
+                    .map(|hop_amount| self.internal_apply_slippage(hop_amount))
+                    .unwrap_or(0);
+                std::cmp::max(
+                    oracle_min_amount,
+                    quoted_min_amount(action.min_amount_out.0),
+                )
+            })
+            .collect::<Vec<Balance>>();
+
+        // The output tokens take their shares, the reward token swap is scaled to the rest.
+        let (reward, min_amount_out, hop_min_amounts, expected_amount_out) =
+            if self.output_tokens.is_empty() {
+                (reward, min_amount_out, hop_min_amounts, expected_amount_out)
+            } else {
+                let rest = self.internal_distribute_output_tokens(reward, &token_prices);
+                let scale = |amount: Balance| u128_ratio(amount, rest, reward);
+                (
+                    rest,
+                    scale(min_amount_out),
+                    hop_min_amounts.into_iter().map(scale).collect(),
+                    scale(expected_amount_out),
+                )
+            };
+        if quote.is_none() && self.internal_checks_pre_swap_quote() {
+            return PromiseOrValue::Promise(self.internal_start_pre_swap_quote(PendingSwap {
+                reward: reward.into(),
+                min_amount_out: min_amount_out.into(),
+                hop_min_amounts: hop_min_amounts.into_iter().map(U128).collect(),
+                expected_amount_out: expected_amount_out.into(),
+            }));
+        }
+        PromiseOrValue::Promise(self.internal_swap(
             reward,
-            wnear_price.multiplier * wnear_extra,
-            usn_price.multiplier * usn_extra,
+            min_amount_out,
+            quote.map(|quote| quote.swap_path),
+            hop_min_amounts,
+            Some(expected_amount_out),
+        ))
+    }
+
+    /// Whether `internal_wrap_near` accepts `amount` right now.
+    pub fn internal_can_wrap_near(&self, amount: Balance) -> bool {
+        let wrap_amount = wrap_amount(amount, self.wrapped_amount);
+        wrap_amount == 0 || (self.burrow_deposited == 0 && wrap_amount >= self.min_wrap_amount)
+    }
+
+    /// Takes `amount` out of the wNEAR buffer and wraps the part it doesn't cover. Returns
+    /// a promise on the wNEAR contract to add the transfer of `amount` to.
+    pub fn internal_wrap_near(&mut self, amount: Balance) -> Promise {
+        let promise = Promise::new(self.wrap_near_contract_id.clone());
+        let wrap_amount = wrap_amount(amount, self.wrapped_amount);
+        if wrap_amount == 0 {
+            // The wNEAR buffer covers the whole amount, no need to wrap.
+            self.wrapped_amount -= amount;
+            return promise;
+        }
+        // The NEAR to wrap could be backing the wNEAR supplied to Burrow.
+        require!(
+            self.burrow_deposited == 0,
+            "Withdraw the wNEAR from Burrow first"
         );
-        // Slippage 1%
-        let min_amount_out = U128(u128_ratio(oracle_amount_out, 99, 100));
-        let mut actions = self.swap_path.clone();
-        actions.last_mut().unwrap().min_amount_out = min_amount_out;
+        require!(
+            wrap_amount >= self.min_wrap_amount,
+            "Wrap amount is below the minimum, postponing the distribution"
+        );
+        self.wrapped_amount = 0;
+        self.rounding_adjustments += 1;
+        promise.function_call(
+            "near_deposit".to_string(),
+            b"{}".to_vec(),
+            wrap_amount,
+            self.gas.wrap_near,
+        )
+    }
+
+    /// Undoes `internal_wrap_near` of `amount` after its batch failed. `near_deposit` was
+    /// reverted as well, so only the part taken `from_buffer` is still wNEAR.
+    pub fn internal_revert_wrap_near(&mut self, amount: Balance, from_buffer: Balance) {
+        self.wrapped_amount += from_buffer;
+        if amount > from_buffer {
+            self.rounding_adjustments -= 1;
+        }
+    }
 
-        let wrap_amount = reward.saturating_sub(self.wrapped_amount) + 1;
-        self.wrapped_amount = self.wrapped_amount.saturating_sub(wrap_amount);
+    /// Takes `reward` out of `available_rewards` and pays out `payout` of it as NEAR.
+    pub fn internal_release_reward(&mut self, reward: Balance, payout: Balance) -> Option<Promise> {
+        self.internal_maybe_snapshot_accounting();
 
-        Promise::new(self.wrap_near_contract_id.clone())
-            .function_call(
-                "near_deposit".to_string(),
-                b"{}".to_vec(),
-                wrap_amount,
-                WRAP_NEAR_GAS,
-            )
+        self.available_rewards -= reward;
+        self.internal_record_distribution();
+
+        if payout == 0 {
+            return None;
+        }
+        let receiver_id = self
+            .near_payout_account_id
+            .clone()
+            .expect("Missing NEAR payout account");
+        Event::NearPaidOut {
+            receiver_id: receiver_id.clone(),
+            amount: payout.into(),
+        }
+        .emit();
+        self.near_paid_out += payout;
+        Some(Promise::new(receiver_id).transfer(payout))
+    }
+
+    /// NEAR the next price triggered distribution would queue for swapping, before chunking.
+    pub fn internal_pending_swap_amount(&self) -> Balance {
+        let step = self.internal_distribution_step();
+        step.swap_amount + step.queued_swap_amount
+    }
+
+    pub fn assert_valid_min_swap_amount(&self) {
+        require!(
+            self.max_swap_chunk == 0 || self.min_swap_amount <= self.max_swap_chunk,
+            "Minimum swap amount exceeds the max swap chunk"
+        );
+    }
+
+    /// Wraps `reward` NEAR and swaps it through the swap path on its exchange.
+    /// `swap_path` replaces the current swap path for this swap with a REF route, e.g. a quote.
+    /// Otherwise the split routes are used if there are any.
+    /// `hop_min_amounts` optionally protects the intermediate hops, 0 means unprotected.
+    /// `oracle_amount_out` is the output valued at oracle prices, if known.
+    pub fn internal_swap(
+        &mut self,
+        reward: Balance,
+        min_amount_out: Balance,
+        swap_path: Option<Vec<Action>>,
+        hop_min_amounts: Vec<Balance>,
+        oracle_amount_out: Option<Balance>,
+    ) -> Promise {
+        let min_amount_out = U128(min_amount_out);
+        let (receiver_id, msg) = if let Some(executor_id) = &self.swap_executor_id {
+            let args = ExecutorArgs {
+                token_out: self.reward_token_id.clone(),
+                min_amount_out,
+            };
+            (executor_id.clone(), serde_json::to_string(&args).unwrap())
+        } else if swap_path.is_none() && !self.split_routes.is_empty() {
+            self.assert_ref_storage_registered();
+            self.internal_split_swap_call(reward, min_amount_out.0)
+        } else {
+            let (mut actions, dex) = match swap_path {
+                Some(swap_path) => (swap_path, DexAdapter::Ref),
+                None => (
+                    self.internal_current_swap_path().clone(),
+                    self.internal_path_dex(self.swap_path_index).clone(),
+                ),
+            };
+            for (action, hop_min_amount) in actions.iter_mut().zip(hop_min_amounts) {
+                action.min_amount_out = U128(hop_min_amount);
+            }
+            actions.last_mut().unwrap().min_amount_out = min_amount_out;
+            if dex == DexAdapter::Ref {
+                self.assert_ref_storage_registered();
+            }
+            self.internal_dex_swap_call(&dex, actions)
+        };
+
+        require!(
+            !self.distribution_in_flight,
+            "A distribution is already in flight"
+        );
+        self.distribution_in_flight = true;
+        self.internal_set_pipeline_stage(PipelineStage::SwapInFlight);
+        self.near_swapping += reward;
+        let from_buffer = std::cmp::min(self.wrapped_amount, reward);
+        self.internal_wrap_near(reward)
             .function_call(
                 "ft_transfer_call".to_string(),
                 serde_json::to_vec(&FtTransferCallArgs {
-                    receiver_id: self.ref_finance_contract_id.clone(),
+                    receiver_id,
                     amount: U128(reward),
-                    msg: serde_json::to_string(&RefArgs { actions }).unwrap(),
+                    msg,
                 })
                 .unwrap(),
                 ONE_YOCTO,
-                SWAP_GAS,
+                self.gas.swap,
             )
             .then(ext_self::on_swap(
                 min_amount_out,
                 U128(reward),
+                U128(from_buffer),
+                env::block_height().into(),
+                oracle_amount_out.map(U128),
                 env::current_account_id(),
                 NO_DEPOSIT,
-                ON_SWAP_GAS,
+                self.gas.on_swap,
             ))
     }
-}
 
-#[derive(Serialize)]
-#[serde(crate = "near_sdk::serde")]
-pub struct FarmingDetails {
-    /// End date of the farm.
-    pub end_date: U64,
-    /// Existing farm ID.
-    pub farm_id: u64,
-}
+    /// Takes the owner fee and the keeper reward out of `amount` NEAR received as rewards
+    /// and makes the rest available for distribution.
+    pub fn internal_receive_rewards(&mut self, amount: Balance, keeper_id: AccountId) {
+        self.internal_maybe_snapshot_accounting();
+        self.rewards_received += amount;
+        let owner_fee = u128_ratio(amount, self.owner_fee_bps as u128, MAX_BPS as u128);
+        Event::RewardWithdrawn {
+            amount: amount.into(),
+            owner_fee: owner_fee.into(),
+        }
+        .emit();
+        self.owner_fees_accrued += owner_fee;
+        let keeper_reward = self.internal_reward_keeper(keeper_id, amount);
+        let reward = amount - owner_fee - keeper_reward;
+        let restaked = self.internal_restake(reward);
+        self.available_rewards += reward - restaked;
+    }
 
-impl Contract {
-    pub fn assert_valid_swap_path(&self) {
-        assert_eq!(
-            self.swap_path.first().unwrap().token_in,
-            self.wrap_near_contract_id
+    /// Pays the keeper its share of `withdrawn_amount` and returns the amount paid.
+    pub fn internal_reward_keeper(
+        &mut self,
+        keeper_id: AccountId,
+        withdrawn_amount: Balance,
+    ) -> Balance {
+        let reward = std::cmp::min(
+            u128_ratio(
+                withdrawn_amount,
+                self.keeper_reward_bps as u128,
+                MAX_BPS as u128,
+            ),
+            self.max_keeper_reward,
         );
-        assert_eq!(
-            self.swap_path.last().unwrap().token_out,
-            self.usn_contract_id
+        if reward > 0 {
+            Event::KeeperRewarded {
+                keeper_id: keeper_id.clone(),
+                amount: reward.into(),
+            }
+            .emit();
+            self.keeper_rewards_paid += reward;
+            Promise::new(keeper_id).transfer(reward);
+        }
+        reward
+    }
+
+    pub fn internal_reward_for(&self, available_rewards: Balance, time_diff: Duration) -> Balance {
+        let reward =
+            self.release_curve
+                .released(available_rewards, time_diff, self.full_rewards_duration);
+        std::cmp::min(reward, self.max_near_reward)
+    }
+
+    /// Moves the distribution checkpoint forward, refusing to go back in time or to
+    /// distribute twice within the same block.
+    pub fn internal_record_distribution(&mut self) {
+        let timestamp = env::block_timestamp();
+        let height = env::block_height();
+        require!(
+            height > self.last_reward_distribution_height
+                && timestamp >= self.last_reward_distribution,
+            "Distribution checkpoint is not monotonic"
         );
-        assert!(self
-            .swap_path
-            .iter()
-            .all(|action| action.min_amount_out.0 == 0));
+        self.last_reward_distribution = timestamp;
+        self.last_reward_distribution_height = height;
+    }
+
+    pub fn assert_valid_swap_path(&self) {
+        self.internal_assert_valid_path(&self.swap_path);
+        for path in &self.fallback_swap_paths {
+            self.internal_assert_valid_path(path);
+        }
+        self.assert_valid_split_routes();
+    }
+
+    fn internal_assert_valid_path(&self, path: &[Action]) {
+        assert_valid_path_hops(path);
+        assert_eq!(path.first().unwrap().token_in, self.wrap_near_contract_id);
+        assert_eq!(path.last().unwrap().token_out, self.reward_token_id);
+        assert!(path.iter().all(|action| action.min_amount_out.0 == 0));
+        self.assert_intermediate_tokens(path);
+    }
+
+    pub fn assert_intermediate_tokens(&self, path: &[Action]) {
+        if let Some(intermediate_tokens) = &self.intermediate_tokens {
+            require!(
+                path[..path.len() - 1]
+                    .iter()
+                    .all(|action| intermediate_tokens.contains(&action.token_out)),
+                "Swap path goes through a token that is not whitelisted"
+            );
+        }
+    }
+
+    pub fn internal_current_swap_path(&self) -> &Vec<Action> {
+        self.internal_swap_path_at(self.swap_path_index)
+    }
+
+    /// `swap_path` for 0, `fallback_swap_paths[index - 1]` otherwise.
+    pub fn internal_swap_path_at(&self, index: u32) -> &Vec<Action> {
+        match index {
+            0 => &self.swap_path,
+            index => &self.fallback_swap_paths[index as usize - 1],
+        }
+    }
+
+    /// Moves to the next configured path, wrapping around to `swap_path` after the last one.
+    fn internal_switch_to_next_swap_path(&mut self) {
+        if self.fallback_swap_paths.is_empty() {
+            return;
+        }
+        self.swap_path_index =
+            (self.swap_path_index + 1) % (self.fallback_swap_paths.len() as u32 + 1);
+        Event::SwapPathSwitched {
+            index: self.swap_path_index,
+        }
+        .emit();
+    }
+
+    /// Emits an `accounting_snapshot` event on the first mutation after each interval.
+    /// Called before the mutation, so the snapshot reflects the state at interval end.
+    pub fn internal_maybe_snapshot_accounting(&mut self) {
+        let timestamp = env::block_timestamp();
+        if timestamp < self.last_accounting_snapshot + self.accounting_snapshot_interval {
+            return;
+        }
+        self.last_accounting_snapshot = timestamp;
+        Event::AccountingSnapshot {
+            rewards_received: self.rewards_received.into(),
+            near_donated: self.near_donated.into(),
+            burrow_interest: self.burrow_interest_earned.into(),
+            available_rewards: self.available_rewards.into(),
+            wrapped_amount: self.wrapped_amount.into(),
+            rounding_adjustments: self.rounding_adjustments.into(),
+            queued_swap_amount: self.queued_swap_amount.into(),
+            vesting_amount: self.vesting_amount.into(),
+            near_swapping: self.near_swapping.into(),
+            near_swapped: self.near_swapped.into(),
+            near_paid_out: self.near_paid_out.into(),
+            wnear_distributed: self.wnear_distributed.into(),
+            owner_fees_accrued: self.owner_fees_accrued.into(),
+            owner_fees_claimed: self.owner_fees_claimed.into(),
+            keeper_rewards_paid: self.keeper_rewards_paid.into(),
+            near_restaked: self.near_restaked.into(),
+            near_swept: self.near_swept.into(),
+            burrow_deposited: self.burrow_deposited.into(),
+            reward_tokens_swapped: self.reward_tokens_swapped.into(),
+            reward_tokens_distributed: self.reward_tokens_distributed.into(),
+            held_reward_tokens: self.held_reward_tokens.into(),
+        }
+        .emit();
+    }
+
+    /// Quotes the hop at `hop_index` for `amount_in` and continues with the remaining hops.
+    fn internal_quote_swap_path_hop(&self, hop_index: u32, amount_in: Balance) -> Promise {
+        let remaining_hops = (self.swap_path.len() - hop_index as usize - 1) as u64;
+        self.internal_dex_quote_hop(0, hop_index as usize, amount_in, REF_QUOTE_GAS)
+            .then(ext_self::on_swap_path_quote(
+                hop_index,
+                env::current_account_id(),
+                NO_DEPOSIT,
+                ON_SWAP_PATH_QUOTE_GAS + (REF_QUOTE_GAS + ON_SWAP_PATH_QUOTE_GAS) * remaining_hops,
+            ))
+    }
+
+    /// Farms `reward_amount`, unless the staking pool is paused or the farm isn't validated
+    /// and the tokens are held back, or buys back and burns it in
+    /// `DistributionMode::BuybackAndBurn`. Returns `None` when the tokens are held back now
+    /// or all go to the farm splits.
+    pub fn internal_distribute_reward_tokens(&mut self, reward_amount: Balance) -> Option<Promise> {
+        if self.internal_take_distribution_mode() == DistributionMode::BuybackAndBurn {
+            return Some(self.internal_buyback(reward_amount));
+        }
+        let reward_amount = self.internal_split_reward_tokens(reward_amount);
+        if reward_amount == 0 {
+            return None;
+        }
+        if self.validate_farm && !self.internal_farm_valid() && !self.create_farm {
+            self.internal_hold_back_reward_tokens(reward_amount, "farm_not_validated");
+            return None;
+        }
+        if !self.check_pool_pause {
+            return self.internal_farm_reward_tokens(reward_amount);
+        }
+        let promise = ext_staking_pool::is_staking_paused(
+            self.staking_pool_account_id.clone(),
+            NO_DEPOSIT,
+            STAKING_POOL_READ_GAS,
+        )
+        .then(ext_self::on_pool_pause_checked(
+            reward_amount.into(),
+            env::current_account_id(),
+            NO_DEPOSIT,
+            self.gas.on_pool_pause_checked,
+        ));
+        Some(promise)
+    }
+
+    /// Keeps `amount` reward tokens on the balance, they are farmed with the next distribution.
+    pub fn internal_hold_back_reward_tokens(&mut self, amount: Balance, reason: &'static str) {
+        Event::RewardTokensHeldBack {
+            amount: amount.into(),
+            reason,
+        }
+        .emit();
+        self.held_reward_tokens += amount;
     }
 
-    pub fn internal_distribute_usn(&mut self, usn_amount: Balance) -> Promise {
-        self.usn_distributed += usn_amount;
+    /// Adds `reward_amount` and any held back reward tokens to the farm. Returns `None` when
+    /// everything is held back, as the top-up isn't due or by `max_reward_rate`.
+    pub fn internal_farm_reward_tokens(&mut self, reward_amount: Balance) -> Option<Promise> {
+        let reward_amount = reward_amount + std::mem::take(&mut self.held_reward_tokens);
+        let farm_id = if std::mem::take(&mut self.create_farm) {
+            None
+        } else {
+            Some(self.farm_id)
+        };
+        if self.require_top_up_due && !self.internal_farm_top_up_due(farm_id) {
+            self.internal_hold_back_reward_tokens(reward_amount, "farm_top_up_not_due");
+            return None;
+        }
+        // A start date that passed while waiting for the distribution is dropped.
+        let start_date = self
+            .next_farm_start_date
+            .take()
+            .filter(|start_date| *start_date > env::block_timestamp());
+        let start = start_date.unwrap_or_else(env::block_timestamp);
+        let farm_duration = self.next_farm_duration.take().unwrap_or(self.farm_duration);
+        let farm_duration = self.internal_top_up_duration(farm_id, start, farm_duration);
+        let (reward_amount, farm_duration) =
+            self.internal_limit_reward_rate(farm_id, reward_amount, start, farm_duration);
+        if reward_amount == 0 {
+            return None;
+        }
+        self.reward_tokens_distributed += reward_amount;
+        Event::RewardTokensDistributed {
+            amount: reward_amount.into(),
+            farm_id,
+        }
+        .emit();
+        let promise = self
+            .internal_farm_tokens(
+                self.reward_token_id.clone(),
+                farm_id,
+                reward_amount,
+                start_date,
+                farm_duration,
+            )
+            .then(ext_self::on_reward_tokens_farmed(
+                reward_amount.into(),
+                farm_id,
+                env::current_account_id(),
+                NO_DEPOSIT,
+                self.gas.on_reward_tokens_farmed,
+            ));
+        Some(promise)
+    }
+
+    pub fn internal_set_next_farm_duration(&mut self, farm_duration_sec: Option<u32>) {
+        let farm_duration = farm_duration_sec.map(|sec| u64::from(sec) * 10u64.pow(9));
+        if let Some(farm_duration) = farm_duration {
+            require!(
+                farm_duration >= self.min_farm_duration && farm_duration <= self.max_farm_duration,
+                "Farm duration is out of bounds"
+            );
+        }
+        self.next_farm_duration = farm_duration;
+        Event::ConfigChanged {
+            field: "next_farm_duration",
+        }
+        .emit();
+    }
+
+    /// Adds `amount` of `token_id` to the given farm of the staking pool for `farm_duration`
+    /// from `start_date` or now, or creates a new farm without `farm_id`.
+    pub fn internal_farm_tokens(
+        &self,
+        token_id: AccountId,
+        farm_id: Option<u64>,
+        amount: Balance,
+        start_date: Option<Timestamp>,
+        farm_duration: Duration,
+    ) -> Promise {
         ext_fungible_token::ft_transfer_call(
             self.staking_pool_account_id.clone(),
-            usn_amount.into(),
-            Some(format!("Enjoy reward of {} USN, friends", usn_amount)),
-            serde_json::to_string(&FarmingDetails {
-                end_date: U64::from(env::block_timestamp() + self.farm_duration),
-                farm_id: self.farm_id,
-            })
-            .unwrap(),
-            self.usn_contract_id.clone(),
+            amount.into(),
+            Some(format!("Enjoy reward of {} {}, friends", amount, token_id)),
+            self.internal_farming_msg(farm_id, start_date, farm_duration),
+            token_id,
             ONE_YOCTO,
-            FT_TRANSFER_CALL_ADD_FARM_GAS,
+            self.gas.add_farm,
         )
     }
 
+    /// `ft_transfer_call` message adding the transfer to `farm_id`, or to a new farm.
+    pub fn internal_farming_msg(
+        &self,
+        farm_id: Option<u64>,
+        start_date: Option<Timestamp>,
+        farm_duration: Duration,
+    ) -> String {
+        let start = start_date.unwrap_or_else(env::block_timestamp);
+        serde_json::to_string(&FarmingDetails {
+            name: farm_id.map_or_else(|| self.internal_new_farm_name(), |_| None),
+            start_date: start_date.map(U64),
+            end_date: U64::from(start + farm_duration),
+            farm_id,
+        })
+        .unwrap()
+    }
+
+    /// Circuit breaker against a manipulated oracle price.
+    pub fn assert_price_deviation(&mut self, rate: Balance) {
+        let last_rate = match self.last_swap_rate {
+            Some(last_rate) if self.max_price_deviation_bps > 0 => last_rate,
+            _ => return,
+        };
+        let deviation = u128_ratio(
+            std::cmp::max(rate, last_rate) - std::cmp::min(rate, last_rate),
+            MAX_BPS as u128,
+            last_rate,
+        );
+        if deviation > self.max_price_deviation_bps as u128 {
+            require!(
+                self.price_deviation_override,
+                "Price deviates too much from the last swap"
+            );
+            self.price_deviation_override = false;
+        }
+    }
+
+    pub fn assert_not_paused(&self) {
+        require!(!self.paused, "Contract is paused");
+    }
+
+    /// With a council, admin methods only accept calls executing its approved proposals.
     pub fn assert_owner(&self) {
+        if self.council.is_some() {
+            require!(
+                env::predecessor_account_id() == env::current_account_id(),
+                "Needs council approval"
+            );
+            return;
+        }
         assert_eq!(
             &self.owner_id,
             &env::predecessor_account_id(),
@@ -578,10 +2492,28 @@ impl Contract {
     }
 }
 
-uint::construct_uint!(
-    pub struct U256(4);
-);
+#[allow(clippy::manual_range_contains, clippy::assign_op_pattern)]
+mod u256 {
+    uint::construct_uint!(
+        pub struct U256(4);
+    );
+}
+pub(crate) use u256::U256;
 
 pub(crate) fn u128_ratio(a: u128, num: u128, denom: u128) -> Balance {
     (U256::from(a) * U256::from(num) / U256::from(denom)).as_u128()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_u128_ratio() {
+        assert_eq!(u128_ratio(1000, 1, 3), 333);
+        assert_eq!(u128_ratio(1000, 0, 3), 0);
+        // The product doesn't overflow u128.
+        assert_eq!(u128_ratio(u128::MAX, u128::MAX, u128::MAX), u128::MAX);
+        assert_eq!(u128_ratio(u128::MAX, 1, 2), u128::MAX / 2);
+    }
+}
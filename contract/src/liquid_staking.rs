@@ -0,0 +1,242 @@
+use super::*;
+use near_sdk::PromiseResult;
+
+const LST_READ_GAS: Gas = Gas(5_000_000_000_000);
+const NEAR_WITHDRAW_GAS: Gas = Gas(10_000_000_000_000);
+const ON_LST_UNWRAPPED_GAS: Gas = Gas(20_000_000_000_000);
+const ON_LST_OUTPUT_READ_GAS: Gas =
+    Gas(10_000_000_000_000 + NEAR_WITHDRAW_GAS.0 + ON_LST_UNWRAPPED_GAS.0);
+const ON_LST_SWAPPED_GAS: Gas = Gas(10_000_000_000_000 + LST_READ_GAS.0 + ON_LST_OUTPUT_READ_GAS.0);
+/// Without the swap itself, which takes `GasConfig::swap`.
+const ON_LST_READ_GAS: Gas = Gas(15_000_000_000_000 + ON_LST_SWAPPED_GAS.0);
+
+/// Rewards held as a liquid staking token, e.g. stNEAR or LiNEAR. The token's NEAR value
+/// above `principal` is swapped to wNEAR through `swap_path` and unwrapped.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LiquidStakingSource {
+    pub token_id: AccountId,
+    /// View returning the NEAR price of one token, `get_st_near_price` for stNEAR and
+    /// `ft_price` for LiNEAR.
+    pub price_method: String,
+    pub swap_path: Vec<Action>,
+    /// NEAR value of the tokens that are not rewards.
+    pub principal: U128,
+}
+
+#[ext_contract(ext_self_liquid_staking)]
+pub trait ExtLiquidStaking {
+    fn on_liquid_staking_read(&mut self, keeper_id: AccountId);
+    fn on_liquid_staking_swapped(
+        &mut self,
+        #[callback_result] used_amount: Result<U128, PromiseError>,
+        wnear_before: U128,
+        keeper_id: AccountId,
+    );
+    fn on_liquid_staking_output_read(
+        &mut self,
+        #[callback_result] wnear_after: Result<U128, PromiseError>,
+        wnear_before: U128,
+        keeper_id: AccountId,
+    );
+    fn on_liquid_staking_unwrapped(&mut self, amount: U128, keeper_id: AccountId);
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn set_liquid_staking_source(&mut self, source: Option<LiquidStakingSource>) {
+        self.assert_owner_or_timelock();
+        if let Some(source) = &source {
+            let swap_path = &source.swap_path;
+            assert_valid_path_hops(swap_path);
+            require!(
+                swap_path.first().unwrap().token_in == source.token_id
+                    && swap_path.last().unwrap().token_out == self.wrap_near_contract_id,
+                "Swap path has to lead from the liquid staking token to wNEAR"
+            );
+            require!(
+                swap_path.iter().all(|action| action.min_amount_out.0 == 0),
+                "Swap path can't set minimum amounts"
+            );
+        }
+        self.liquid_staking_source = source;
+        Event::ConfigChanged {
+            field: "liquid_staking_source",
+        }
+        .emit();
+    }
+
+    pub fn get_liquid_staking_source(&self) -> Option<LiquidStakingSource> {
+        self.liquid_staking_source.clone()
+    }
+
+    /// Swaps the growth of the liquid staking token's value into NEAR rewards, which are
+    /// distributed like the ones of the staking pool. Holds the distribution lock.
+    pub fn collect_liquid_staking_rewards(&mut self) -> Promise {
+        self.assert_not_paused();
+        let source = self
+            .liquid_staking_source
+            .clone()
+            .expect("No liquid staking source");
+        // The output is measured by the growth of the wNEAR balance, so nothing else may
+        // move it until then.
+        require!(
+            !self.distribution_in_flight
+                && self.output_swaps_in_flight == 0
+                && !self.burrow_pending,
+            "A distribution or Burrow withdrawal is in flight"
+        );
+        self.distribution_in_flight = true;
+        ext_fungible_token::ft_balance_of(
+            env::current_account_id(),
+            source.token_id.clone(),
+            NO_DEPOSIT,
+            LST_READ_GAS,
+        )
+        .and(Promise::new(source.token_id).function_call(
+            source.price_method,
+            b"{}".to_vec(),
+            NO_DEPOSIT,
+            LST_READ_GAS,
+        ))
+        .and(ext_fungible_token::ft_balance_of(
+            env::current_account_id(),
+            self.wrap_near_contract_id.clone(),
+            NO_DEPOSIT,
+            LST_READ_GAS,
+        ))
+        .then(ext_self_liquid_staking::on_liquid_staking_read(
+            env::predecessor_account_id(),
+            env::current_account_id(),
+            NO_DEPOSIT,
+            ON_LST_READ_GAS + self.gas.swap,
+        ))
+    }
+
+    #[private]
+    pub fn on_liquid_staking_read(&mut self, keeper_id: AccountId) -> PromiseOrValue<()> {
+        let read = |index: u64| match env::promise_result(index) {
+            PromiseResult::Successful(value) => serde_json::from_slice::<U128>(&value).ok(),
+            _ => None,
+        };
+        let (balance, price, wnear_before) = match (read(0), read(1), read(2)) {
+            (Some(balance), Some(price), Some(wnear_before)) if price.0 > 0 => {
+                (balance.0, price.0, wnear_before)
+            }
+            _ => return self.internal_end_liquid_staking_collection("read_failed"),
+        };
+        let source = self.liquid_staking_source.clone().unwrap();
+        let value = u128_ratio(balance, price, ONE_NEAR);
+        let reward = value.saturating_sub(source.principal.0);
+        let amount = std::cmp::min(u128_ratio(reward, ONE_NEAR, price), balance);
+        if amount == 0 {
+            return self.internal_end_liquid_staking_collection("no_rewards");
+        }
+        self.assert_ref_storage_registered();
+        // The token contract's price serves as the oracle price.
+        let mut actions = source.swap_path;
+        actions.last_mut().unwrap().min_amount_out = self.internal_apply_slippage(reward).into();
+        let (receiver_id, msg) = self.internal_dex_swap_call(&DexAdapter::Ref, actions);
+        let promise = ext_fungible_token::ft_transfer_call(
+            receiver_id,
+            amount.into(),
+            None,
+            msg,
+            source.token_id,
+            ONE_YOCTO,
+            self.gas.swap,
+        )
+        .then(ext_self_liquid_staking::on_liquid_staking_swapped(
+            wnear_before,
+            keeper_id,
+            env::current_account_id(),
+            NO_DEPOSIT,
+            ON_LST_SWAPPED_GAS,
+        ));
+        PromiseOrValue::Promise(promise)
+    }
+
+    #[private]
+    pub fn on_liquid_staking_swapped(
+        &mut self,
+        #[callback_result] used_amount: Result<U128, PromiseError>,
+        wnear_before: U128,
+        keeper_id: AccountId,
+    ) -> PromiseOrValue<()> {
+        if used_amount.map_or(true, |used_amount| used_amount.0 == 0) {
+            return self.internal_end_liquid_staking_collection("swap_failed");
+        }
+        let promise = ext_fungible_token::ft_balance_of(
+            env::current_account_id(),
+            self.wrap_near_contract_id.clone(),
+            NO_DEPOSIT,
+            LST_READ_GAS,
+        )
+        .then(ext_self_liquid_staking::on_liquid_staking_output_read(
+            wnear_before,
+            keeper_id,
+            env::current_account_id(),
+            NO_DEPOSIT,
+            ON_LST_OUTPUT_READ_GAS,
+        ));
+        PromiseOrValue::Promise(promise)
+    }
+
+    /// The distribution lock keeps other swaps, reclaims and wNEAR donations from changing
+    /// the wNEAR balance since the first reading, and no output token swap or Burrow
+    /// withdrawal was in flight when it was taken.
+    #[private]
+    pub fn on_liquid_staking_output_read(
+        &mut self,
+        #[callback_result] wnear_after: Result<U128, PromiseError>,
+        wnear_before: U128,
+        keeper_id: AccountId,
+    ) -> PromiseOrValue<()> {
+        let amount = match wnear_after {
+            Ok(wnear_after) if wnear_after.0 > wnear_before.0 => wnear_after.0 - wnear_before.0,
+            _ => return self.internal_end_liquid_staking_collection("output_read_failed"),
+        };
+        let promise = Promise::new(self.wrap_near_contract_id.clone())
+            .function_call(
+                "near_withdraw".to_string(),
+                serde_json::to_vec(&NearWithdrawArgs {
+                    amount: amount.into(),
+                })
+                .unwrap(),
+                ONE_YOCTO,
+                NEAR_WITHDRAW_GAS,
+            )
+            .then(ext_self_liquid_staking::on_liquid_staking_unwrapped(
+                amount.into(),
+                keeper_id,
+                env::current_account_id(),
+                NO_DEPOSIT,
+                ON_LST_UNWRAPPED_GAS,
+            ));
+        PromiseOrValue::Promise(promise)
+    }
+
+    #[private]
+    pub fn on_liquid_staking_unwrapped(&mut self, amount: U128, keeper_id: AccountId) {
+        self.distribution_in_flight = false;
+        if is_promise_success() {
+            self.internal_receive_rewards(amount.0, keeper_id);
+        } else {
+            // Kept in the wNEAR buffer, without the owner fee and keeper reward paid in NEAR.
+            self.wrapped_amount += amount.0;
+            self.available_rewards += amount.0;
+            self.rewards_received += amount.0;
+        }
+    }
+}
+
+impl Contract {
+    fn internal_end_liquid_staking_collection(
+        &mut self,
+        reason: &'static str,
+    ) -> PromiseOrValue<()> {
+        self.distribution_in_flight = false;
+        Event::LiquidStakingCollectionSkipped { reason }.emit();
+        PromiseOrValue::Value(())
+    }
+}
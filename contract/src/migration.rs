@@ -0,0 +1,540 @@
+use super::*;
+
+const STATE_VERSION_KEY: &[u8] = b"VERSION";
+/// Bumped with a new `VersionedContract` variant whenever fields of `Contract` change.
+const CURRENT_STATE_VERSION: u32 = 2;
+/// Gas kept by `upgrade` for itself, the rest goes to `migrate`.
+#[cfg(target_arch = "wasm32")]
+const UPGRADE_RESERVED_GAS: Gas = Gas(20_000_000_000_000);
+/// Least gas `migrate` is called with, below it the upgrade is refused.
+#[cfg(target_arch = "wasm32")]
+const MIN_MIGRATE_GAS: Gas = Gas(50_000_000_000_000);
+
+/// State layout of the first mainnet deployment, which didn't record a version.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ContractV0 {
+    pub staking_pool_account_id: AccountId,
+    pub owner_id: AccountId,
+    pub usn_contract_id: AccountId,
+    pub rewards_received: Balance,
+    pub available_rewards: Balance,
+    pub last_reward_distribution: Timestamp,
+    pub farm_duration: Duration,
+    pub full_rewards_duration: Duration,
+    pub farm_id: u64,
+    pub usn_distributed: Balance,
+    pub oracle_contract_id: AccountId,
+    pub ref_finance_contract_id: AccountId,
+    pub wrap_near_contract_id: AccountId,
+    pub swap_path: Vec<Action>,
+    pub wrapped_amount: Balance,
+    pub max_near_reward: Balance,
+}
+
+/// State layout tagged as version 1, up to `setup_complete`.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ContractV1 {
+    pub staking_pool_account_id: AccountId,
+    pub owner_id: AccountId,
+    pub usn_contract_id: AccountId,
+    pub rewards_received: Balance,
+    pub available_rewards: Balance,
+    pub last_reward_distribution: Timestamp,
+    pub last_reward_distribution_height: BlockHeight,
+    pub farm_duration: Duration,
+    pub full_rewards_duration: Duration,
+    pub farm_id: u64,
+    pub usn_distributed: Balance,
+    pub oracle_contract_id: AccountId,
+    pub ref_finance_contract_id: AccountId,
+    pub wrap_near_contract_id: AccountId,
+    pub swap_path: Vec<Action>,
+    pub wrapped_amount: Balance,
+    pub max_near_reward: Balance,
+    pub rounding_adjustments: Balance,
+    pub swap_fraction_bps: u32,
+    pub near_payout_account_id: Option<AccountId>,
+    pub near_paid_out: Balance,
+    pub network: NetworkProfile,
+    pub owner_fee_bps: u32,
+    pub owner_fees_accrued: Balance,
+    pub owner_fees_claimed: Balance,
+    pub pending_pool_action: Option<PendingPoolAction>,
+    pub fallback_to_wnear: bool,
+    pub wnear_farm_id: Option<u64>,
+    pub wnear_distributed: Balance,
+    pub max_swap_delay_blocks: BlockHeight,
+    pub accounting_snapshot_interval: Duration,
+    pub last_accounting_snapshot: Timestamp,
+    pub distributions: Vector<Distribution>,
+    pub distributions_recorded: u64,
+    pub setup_complete: bool,
+}
+
+/// Layout of distributions recorded before `oracle_reward_amount` was added.
+#[derive(BorshDeserialize)]
+pub struct DistributionV0 {
+    pub timestamp: Timestamp,
+    pub near_amount: Balance,
+    pub usn_amount: Balance,
+    pub farm_id: u64,
+    pub usn_per_near: Balance,
+}
+
+/// Storage reshapes of collections too large to convert within a single `migrate`.
+/// They run in chunks with `migrate_step` while the contract is paused. `migrate` starts
+/// the ones its state version needs.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum ChunkedMigration {
+    /// Rewrites distributions recorded in `DistributionV0` layout, started by `migrate` from
+    /// version 1.
+    DistributionsV0,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MigrationProgress {
+    pub migration: ChunkedMigration,
+    /// Index of the next item to convert.
+    pub next_index: u64,
+}
+
+/// Every state layout the contract has been deployed with.
+/// Older variants are converted into the current one by `migrate`.
+#[allow(clippy::large_enum_variant)]
+pub enum VersionedContract {
+    V0(ContractV0),
+    V1(ContractV1),
+    Current(Contract),
+}
+
+impl VersionedContract {
+    pub fn state_read() -> Self {
+        let version = env::storage_read(STATE_VERSION_KEY)
+            .map(|bytes| u32::try_from_slice(&bytes).expect("Invalid state version"))
+            .unwrap_or(0);
+        match version {
+            0 => Self::V0(env::state_read().expect("Contract is not initialized")),
+            1 => Self::V1(env::state_read().expect("Contract is not initialized")),
+            CURRENT_STATE_VERSION => {
+                Self::Current(env::state_read().expect("Contract is not initialized"))
+            }
+            _ => env::panic_str("Unknown state version"),
+        }
+    }
+
+    pub fn into_current(self) -> Contract {
+        match self {
+            Self::V0(contract) => ContractV1::from(contract).into(),
+            Self::V1(contract) => contract.into(),
+            Self::Current(contract) => contract,
+        }
+    }
+}
+
+impl From<ContractV0> for ContractV1 {
+    fn from(contract: ContractV0) -> Self {
+        let ContractV0 {
+            staking_pool_account_id,
+            owner_id,
+            usn_contract_id,
+            rewards_received,
+            available_rewards,
+            last_reward_distribution,
+            farm_duration,
+            full_rewards_duration,
+            farm_id,
+            usn_distributed,
+            oracle_contract_id,
+            ref_finance_contract_id,
+            wrap_near_contract_id,
+            swap_path,
+            wrapped_amount,
+            max_near_reward,
+        } = contract;
+        Self {
+            staking_pool_account_id,
+            owner_id,
+            usn_contract_id,
+            rewards_received,
+            available_rewards,
+            last_reward_distribution,
+            last_reward_distribution_height: 0,
+            farm_duration,
+            full_rewards_duration,
+            farm_id,
+            usn_distributed,
+            oracle_contract_id,
+            ref_finance_contract_id,
+            wrap_near_contract_id,
+            swap_path,
+            wrapped_amount,
+            max_near_reward,
+            rounding_adjustments: 0,
+            swap_fraction_bps: MAX_BPS,
+            near_payout_account_id: None,
+            near_paid_out: 0,
+            network: NetworkProfile::Custom,
+            owner_fee_bps: 0,
+            owner_fees_accrued: 0,
+            owner_fees_claimed: 0,
+            pending_pool_action: None,
+            fallback_to_wnear: false,
+            wnear_farm_id: None,
+            wnear_distributed: 0,
+            max_swap_delay_blocks: 0,
+            accounting_snapshot_interval: DEFAULT_ACCOUNTING_SNAPSHOT_INTERVAL,
+            last_accounting_snapshot: 0,
+            distributions: Vector::new(StorageKey::Distributions),
+            distributions_recorded: 0,
+            setup_complete: true,
+        }
+    }
+}
+
+impl From<ContractV1> for Contract {
+    fn from(contract: ContractV1) -> Self {
+        let ContractV1 {
+            staking_pool_account_id,
+            owner_id,
+            usn_contract_id,
+            rewards_received,
+            available_rewards,
+            last_reward_distribution,
+            last_reward_distribution_height,
+            farm_duration,
+            full_rewards_duration,
+            farm_id,
+            usn_distributed,
+            oracle_contract_id,
+            ref_finance_contract_id,
+            wrap_near_contract_id,
+            swap_path,
+            wrapped_amount,
+            max_near_reward,
+            rounding_adjustments,
+            swap_fraction_bps,
+            near_payout_account_id,
+            near_paid_out,
+            network,
+            owner_fee_bps,
+            owner_fees_accrued,
+            owner_fees_claimed,
+            pending_pool_action,
+            fallback_to_wnear,
+            wnear_farm_id,
+            wnear_distributed,
+            max_swap_delay_blocks,
+            accounting_snapshot_interval,
+            last_accounting_snapshot,
+            distributions,
+            distributions_recorded,
+            setup_complete,
+        } = contract;
+        // Everything received and not held or sent elsewhere was swapped.
+        let near_swapped = rewards_received.saturating_sub(
+            available_rewards
+                + near_paid_out
+                + wnear_distributed
+                + owner_fees_accrued
+                + owner_fees_claimed,
+        );
+        Self {
+            staking_pool_account_id,
+            owner_id,
+            reward_token_id: usn_contract_id,
+            rewards_received,
+            available_rewards,
+            last_reward_distribution,
+            last_reward_distribution_height,
+            farm_duration,
+            full_rewards_duration,
+            farm_id,
+            reward_tokens_distributed: usn_distributed,
+            oracle_contract_id,
+            ref_finance_contract_id,
+            wrap_near_contract_id,
+            swap_path,
+            wrapped_amount,
+            max_near_reward,
+            rounding_adjustments,
+            swap_fraction_bps,
+            near_payout_account_id,
+            near_paid_out,
+            network,
+            owner_fee_bps,
+            owner_fees_accrued,
+            owner_fees_claimed,
+            pending_pool_action,
+            fallback_to_wnear,
+            wnear_farm_id,
+            wnear_distributed,
+            max_swap_delay_blocks,
+            accounting_snapshot_interval,
+            last_accounting_snapshot,
+            distributions,
+            distributions_recorded,
+            setup_complete,
+            pending_at_pool: 0,
+            paused: false,
+            keeper_reward_bps: 0,
+            max_keeper_reward: 0,
+            keeper_rewards_paid: 0,
+            reward_price_band: None,
+            swap_executor_id: None,
+            oracle_max_recency_sec: DEFAULT_ORACLE_MAX_RECENCY_SEC,
+            oracle_max_staleness: DEFAULT_ORACLE_MAX_STALENESS,
+            extra_oracle_ids: vec![],
+            oracle_aggregation: OracleAggregation::AnyOf,
+            oracle_reports: vec![],
+            min_fill_bps: MAX_BPS,
+            min_wrap_amount: 0,
+            price_source: PriceSource::PushOracle,
+            pool_snapshots: Vector::new(StorageKey::PoolSnapshots),
+            pool_snapshots_recorded: 0,
+            last_swap_rate: None,
+            max_price_deviation_bps: 0,
+            price_deviation_override: false,
+            zero_reward_policy: ZeroRewardPolicy::Panic,
+            last_oracle_result: None,
+            operators: UnorderedSet::new(StorageKey::Operators),
+            fallback_swap_paths: vec![],
+            swap_path_index: 0,
+            max_swap_chunk: 0,
+            queued_swap_amount: 0,
+            min_farm_duration: DEFAULT_MIN_FARM_DURATION,
+            max_farm_duration: DEFAULT_MAX_FARM_DURATION,
+            next_farm_duration: None,
+            check_pool_pause: false,
+            held_reward_tokens: 0,
+            near_swept: 0,
+            migration_progress: None,
+            pending_reward_donations: 0,
+            quote_signer: None,
+            max_quote_age: DEFAULT_MAX_QUOTE_AGE,
+            last_quote_nonce: 0,
+            distribution_mode: DistributionMode::Farm,
+            next_distribution_mode: None,
+            buyback: None,
+            reward_tokens_bought_back: 0,
+            buyback_tokens_burned: 0,
+            near_donated: 0,
+            near_swapped,
+            near_swapping: 0,
+            distributions_storage_bytes: 0,
+            pool_snapshots_storage_bytes: 0,
+            operators_storage_bytes: 0,
+            treasury_account_id: None,
+            treasury_share_bps: 0,
+            treasury_sent: 0,
+            pyth_intermediate_feeds: vec![],
+            require_route_prices: false,
+            restake_bps: 0,
+            restaked: 0,
+            near_restaked: 0,
+            farm_rotation: FarmRotation::Disabled,
+            create_farm: false,
+            require_commitment: false,
+            distribution_commitment: None,
+            public_metadata: PublicMetadata::default(),
+            pool_total_stake: 0,
+            validate_farm: false,
+            validated_farm: None,
+            farm_weight: 1,
+            farm_splits: vec![],
+            timelock_delay: 0,
+            pending_changes: vec![],
+            next_change_id: 0,
+            council: None,
+            council_proposals: vec![],
+            next_council_proposal_id: 0,
+            gas: GasConfig::default(),
+            distribution_in_flight: false,
+            pipeline_stage: PipelineStage::Idle,
+            pipeline_stage_since: 0,
+            swaps_executed: 0,
+            reward_tokens_swapped: 0,
+            intermediate_tokens: None,
+            staking_pool_change: None,
+            staking_pool_whitelist_id: None,
+            min_swap_amount: 0,
+            min_distribution_interval: 0,
+            last_price_distribution: 0,
+            release_curve: ReleaseCurve::Linear,
+            distribution_windows: vec![],
+            swap_path_dexes: vec![],
+            split_routes: vec![],
+            // Registered on init.
+            ref_storage_registered: true,
+            referral_id: None,
+            pre_swap_quote_tolerance_bps: None,
+            slippage_policy: SlippagePolicy::default(),
+            consecutive_slippage_failures: 0,
+            extra_slippage_bps: 0,
+            burrow_contract_id: None,
+            burrow_deposited: 0,
+            burrow_pending: false,
+            burrow_interest_earned: 0,
+            liquid_staking_source: None,
+            direct_wnear_farm_id: None,
+            output_tokens: vec![],
+            next_farm_start_date: None,
+            max_reward_rate: 0,
+            farm_schedule: None,
+            farm_top_up_window: DEFAULT_FARM_TOP_UP_WINDOW,
+            require_top_up_due: false,
+            oracle_assets: vec![],
+            token_decimals: vec![],
+            donations: Vector::new(StorageKey::Donations),
+            donations_recorded: 0,
+            donations_storage_bytes: 0,
+            min_donation: 0,
+            donations_enabled: true,
+            donation_vesting: None,
+            vesting_donations: vec![],
+            vesting_amount: 0,
+            last_withdraw: 0,
+            recent_errors: Vector::new(StorageKey::RecentErrors),
+            errors_recorded: 0,
+            errors_storage_bytes: 0,
+            output_swaps_in_flight: 0,
+            expired_swap_output: 0,
+            last_oracle_rate: None,
+        }
+    }
+}
+
+impl Contract {
+    /// Converts up to `limit` items starting at `from_index` and returns the number of
+    /// items the migration covers in total.
+    fn internal_migrate_chunk(
+        &mut self,
+        migration: ChunkedMigration,
+        from_index: u64,
+        limit: u64,
+    ) -> u64 {
+        match migration {
+            ChunkedMigration::DistributionsV0 => {
+                let total = self.distributions.len();
+                let prefix = StorageKey::Distributions.try_to_vec().unwrap();
+                let to_index = std::cmp::min(from_index.saturating_add(limit), total);
+                for index in from_index..to_index {
+                    let key = [prefix.as_slice(), &index.to_le_bytes()].concat();
+                    let raw = env::storage_read(&key).expect("Missing distribution");
+                    // Entries in the current layout have trailing bytes and are skipped.
+                    // Written directly, `Vector::replace` would deserialize the old entry.
+                    if let Ok(old) = DistributionV0::try_from_slice(&raw) {
+                        let distribution = Distribution {
+                            timestamp: old.timestamp,
+                            near_amount: old.near_amount,
+                            reward_amount: old.usn_amount,
+                            farm_id: old.farm_id,
+                            reward_per_near: old.usn_per_near,
+                            oracle_reward_amount: 0,
+                        };
+                        track_storage(&mut self.distributions_storage_bytes, || {
+                            env::storage_write(&key, &distribution.try_to_vec().unwrap())
+                        });
+                    }
+                }
+                total
+            }
+        }
+    }
+
+    pub fn internal_write_state_version() {
+        env::storage_write(
+            STATE_VERSION_KEY,
+            &CURRENT_STATE_VERSION.try_to_vec().unwrap(),
+        );
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let state = VersionedContract::state_read();
+        let reshape_distributions = matches!(state, VersionedContract::V1(_));
+        let mut contract = state.into_current();
+        if reshape_distributions && !contract.distributions.is_empty() {
+            // Version 1 recorded distributions in `DistributionV0` layout, too many to rewrite
+            // here. The contract stays paused until `migrate_step` is done with them.
+            contract.paused = true;
+            contract.migration_progress = Some(MigrationProgress {
+                migration: ChunkedMigration::DistributionsV0,
+                next_index: 0,
+            });
+        }
+        Self::internal_write_state_version();
+        contract
+    }
+
+    /// Starts a chunked migration. The contract has to stay paused until it's finished.
+    pub fn start_chunked_migration(&mut self, migration: ChunkedMigration) {
+        self.assert_owner();
+        require!(self.paused, "Pause the contract before migrating");
+        require!(
+            self.migration_progress.is_none(),
+            "Chunked migration is in progress"
+        );
+        self.migration_progress = Some(MigrationProgress {
+            migration,
+            next_index: 0,
+        });
+        Event::ConfigChanged {
+            field: "migration_progress",
+        }
+        .emit();
+    }
+
+    /// Converts up to `limit` items of the running migration. Returns `true` once done.
+    pub fn migrate_step(&mut self, limit: u64) -> bool {
+        self.assert_owner();
+        let progress = self
+            .migration_progress
+            .clone()
+            .expect("No chunked migration in progress");
+        let total = self.internal_migrate_chunk(progress.migration, progress.next_index, limit);
+        let next_index = std::cmp::min(progress.next_index.saturating_add(limit), total);
+        let done = next_index >= total;
+        self.migration_progress = if done {
+            None
+        } else {
+            Some(MigrationProgress {
+                next_index,
+                ..progress
+            })
+        };
+        done
+    }
+
+    pub fn get_migration_progress(&self) -> Option<MigrationProgress> {
+        self.migration_progress.clone()
+    }
+}
+
+/// Deploys the code given as raw input and runs `migrate` on it. Owner only, and timelocked
+/// like the setters it could bypass. Lets an owning contract, e.g. the factory, push code updates.
+#[cfg(target_arch = "wasm32")]
+#[no_mangle]
+pub extern "C" fn upgrade() {
+    env::setup_panic_hook();
+    VersionedContract::state_read()
+        .into_current()
+        .assert_owner_or_timelock();
+    let code = env::input().expect("Missing code");
+    let migrate_gas = Gas(env::prepaid_gas()
+        .0
+        .saturating_sub(env::used_gas().0)
+        .saturating_sub(UPGRADE_RESERVED_GAS.0));
+    require!(
+        migrate_gas >= MIN_MIGRATE_GAS,
+        "Not enough gas left for migrate"
+    );
+    Promise::new(env::current_account_id())
+        .deploy_contract(code)
+        .function_call("migrate".to_string(), vec![], NO_DEPOSIT, migrate_gas);
+}
@@ -0,0 +1,36 @@
+use super::*;
+
+/// Known deployments of the contracts this one depends on.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum NetworkProfile {
+    Mainnet,
+    Testnet,
+    /// Accounts were provided explicitly at init.
+    Custom,
+}
+
+pub struct ProfileAccounts {
+    pub oracle_contract_id: AccountId,
+    pub ref_finance_contract_id: AccountId,
+    pub wrap_near_contract_id: AccountId,
+}
+
+impl NetworkProfile {
+    pub fn accounts(&self) -> ProfileAccounts {
+        let (oracle, ref_finance, wrap_near) = match self {
+            NetworkProfile::Mainnet => ("priceoracle.near", "v2.ref-finance.near", "wrap.near"),
+            NetworkProfile::Testnet => (
+                "priceoracle.testnet",
+                "ref-finance-101.testnet",
+                "wrap.testnet",
+            ),
+            NetworkProfile::Custom => env::panic_str("Custom profile has no preset accounts"),
+        };
+        ProfileAccounts {
+            oracle_contract_id: oracle.parse().unwrap(),
+            ref_finance_contract_id: ref_finance.parse().unwrap(),
+            wrap_near_contract_id: wrap_near.parse().unwrap(),
+        }
+    }
+}
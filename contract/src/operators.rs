@@ -0,0 +1,187 @@
+use super::*;
+
+const ON_RETRY_SWAP_QUOTE_GAS: Gas = Gas(15_000_000_000_000);
+
+#[ext_contract(ext_self_operators)]
+pub trait ExtOperators {
+    fn on_retry_swap_quote(
+        &mut self,
+        #[callback] quote: DexQuote,
+        hop_index: u32,
+        path_index: u32,
+        amount: U128,
+    ) -> PromiseOrValue<()>;
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn add_operator(&mut self, account_id: AccountId) {
+        self.assert_owner_or_timelock();
+        require!(self.operators.len() < MAX_OPERATORS, "Too many operators");
+        let operators = &mut self.operators;
+        let inserted = track_storage(&mut self.operators_storage_bytes, || {
+            operators.insert(&account_id)
+        });
+        require!(inserted, "Already an operator");
+        Event::OperatorAdded { account_id }.emit();
+    }
+
+    pub fn remove_operator(&mut self, account_id: AccountId) {
+        self.assert_owner();
+        let operators = &mut self.operators;
+        let removed = track_storage(&mut self.operators_storage_bytes, || {
+            operators.remove(&account_id)
+        });
+        require!(removed, "Not an operator");
+        Event::OperatorRemoved { account_id }.emit();
+    }
+
+    pub fn get_operators(&self) -> Vec<AccountId> {
+        self.operators.to_vec()
+    }
+
+    /// Distributes the reward due now without waiting for the oracle, using the given
+    /// minimum output of the swap. Bound like price triggered distributions: only within
+    /// the distribution windows, `min_distribution_interval` after the previous one, and
+    /// swapping at most `max_swap_chunk`, which `min_amount_out` applies to. It can't go below
+    /// the rate of the last oracle prices or swap minus the slippage.
+    pub fn force_distribution(&mut self, min_amount_out: U128) -> Promise {
+        self.assert_operator();
+        self.assert_not_paused();
+        self.assert_setup_complete();
+        require!(min_amount_out.0 > 0, "Minimum amount out must be positive");
+        require!(
+            self.is_in_distribution_window(),
+            "Outside of the distribution windows"
+        );
+        let timestamp = env::block_timestamp();
+        require!(
+            timestamp >= self.last_price_distribution + self.min_distribution_interval,
+            "The previous distribution is too recent"
+        );
+        require!(
+            self.swap_fraction_bps == 0
+                || self.internal_pending_swap_amount() >= self.min_swap_amount,
+            "Reward is below the minimum swap amount"
+        );
+        self.last_price_distribution = timestamp;
+        let (reward, payout_promise) = self.internal_take_distribution();
+        if reward == 0 {
+            return payout_promise.unwrap();
+        }
+        require!(
+            min_amount_out.0 >= self.internal_reference_min_amount_out(reward),
+            "Minimum amount out is below the last rate"
+        );
+        self.internal_swap(reward, min_amount_out.0, None, vec![], None)
+    }
+
+    /// Swaps the wNEAR left over by failed swaps right away instead of waiting for the
+    /// oracle. The minimum output follows the rate of the last oracle prices or swap minus the
+    /// slippage. A REF quote of the current swap path can only raise it, if that path is
+    /// what the swap goes through.
+    pub fn retry_swap(&mut self) -> Promise {
+        self.assert_operator();
+        self.assert_not_paused();
+        self.assert_setup_complete();
+        let amount = std::cmp::min(self.wrapped_amount, self.available_rewards);
+        require!(amount > 0, "No wNEAR to swap");
+        if self.internal_swaps_current_path() {
+            self.internal_quote_retry_swap_hop(0, amount, amount)
+        } else {
+            self.internal_retry_swap(amount, 0)
+        }
+    }
+
+    #[private]
+    pub fn on_retry_swap_quote(
+        &mut self,
+        #[callback] quote: DexQuote,
+        hop_index: u32,
+        path_index: u32,
+        amount: U128,
+    ) -> PromiseOrValue<()> {
+        require!(
+            path_index == self.swap_path_index && self.internal_swaps_current_path(),
+            "Swap path has changed"
+        );
+        let amount_out = quote.amount();
+        let next_hop_index = hop_index + 1;
+        if (next_hop_index as usize) < self.internal_current_swap_path().len() {
+            return PromiseOrValue::Promise(self.internal_quote_retry_swap_hop(
+                next_hop_index,
+                amount_out.0,
+                amount.0,
+            ));
+        }
+        self.assert_not_paused();
+        let quoted_min_amount_out = self.internal_apply_slippage(amount_out.0);
+        PromiseOrValue::Promise(self.internal_retry_swap(amount.0, quoted_min_amount_out))
+    }
+}
+
+impl Contract {
+    /// Quotes the hop at `hop_index` of the current swap path for `amount_in`, continuing
+    /// with the remaining hops and finally swapping `amount`.
+    fn internal_quote_retry_swap_hop(
+        &self,
+        hop_index: u32,
+        amount_in: Balance,
+        amount: Balance,
+    ) -> Promise {
+        let remaining_hops =
+            (self.internal_current_swap_path().len() - hop_index as usize - 1) as u64;
+        self.internal_dex_quote_hop(
+            self.swap_path_index,
+            hop_index as usize,
+            amount_in,
+            REF_QUOTE_GAS,
+        )
+        .then(ext_self_operators::on_retry_swap_quote(
+            hop_index,
+            self.swap_path_index,
+            amount.into(),
+            env::current_account_id(),
+            NO_DEPOSIT,
+            ON_RETRY_SWAP_QUOTE_GAS
+                + self.gas.swap
+                + self.gas.on_swap
+                + (REF_QUOTE_GAS + ON_RETRY_SWAP_QUOTE_GAS) * remaining_hops,
+        ))
+    }
+
+    /// Swaps `amount` of the wNEAR buffer at the last rate, or `quoted_min_amount_out` if higher.
+    fn internal_retry_swap(&mut self, amount: Balance, quoted_min_amount_out: Balance) -> Promise {
+        require!(
+            self.wrapped_amount >= amount && self.available_rewards >= amount,
+            "wNEAR to swap has changed"
+        );
+        let min_amount_out = std::cmp::max(
+            self.internal_reference_min_amount_out(amount),
+            quoted_min_amount_out,
+        );
+        self.assert_price_deviation(u128_ratio(min_amount_out, ONE_NEAR, amount));
+        self.available_rewards -= amount;
+        self.internal_swap(amount, min_amount_out, None, vec![], None)
+    }
+
+    /// The owner, or the council members when there is a council.
+    pub fn is_owner(&self, account_id: &AccountId) -> bool {
+        match &self.council {
+            Some(_) => self.is_council_member(account_id),
+            None => account_id == &self.owner_id,
+        }
+    }
+
+    /// Owners as of `is_owner` are implicitly operators.
+    pub fn is_operator(&self, account_id: &AccountId) -> bool {
+        self.is_owner(account_id) || self.operators.contains(account_id)
+    }
+
+    pub fn assert_operator(&self) {
+        require!(
+            self.is_operator(&env::predecessor_account_id()),
+            "Not an operator"
+        );
+    }
+}
@@ -0,0 +1,196 @@
+use super::*;
+
+pub const MAX_ORACLES: usize = 5;
+const MAX_ORACLE_ASSETS: usize = 8;
+
+/// How prices from several oracles are combined before a swap.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum OracleAggregation {
+    /// Any registered oracle can trigger a distribution with its own prices.
+    AnyOf,
+    /// A distribution uses the median of fresh reports and needs at least `min_reports`.
+    MedianOf { min_reports: u8 },
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OracleReport {
+    pub oracle_id: AccountId,
+    #[serde(with = "u64_dec_format")]
+    pub timestamp: Timestamp,
+    pub wnear_price: Price,
+    pub reward_price: Price,
+}
+
+/// Outcome of the last `oracle_on_call`, for the oracle operator.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OracleResult {
+    #[serde(with = "u64_dec_format")]
+    pub timestamp: Timestamp,
+    pub oracle_id: AccountId,
+    /// Whether the prices were used.
+    pub accepted: bool,
+    /// Why the prices were rejected or didn't lead to a swap.
+    pub reason: Option<String>,
+}
+
+/// Asset ID an oracle reports the price of `token_id` under, e.g. an alias or a bridged variant.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OracleAsset {
+    pub token_id: AccountId,
+    pub asset_id: AssetId,
+}
+
+/// Optional instructions passed by the account requesting the oracle call.
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OracleCallMsg {
+    /// Farm duration of this round, only accepted from operators.
+    pub farm_duration_sec: Option<u32>,
+    /// Farm start date of this round, only accepted from operators.
+    pub farm_start_date: Option<U64>,
+    /// Distribution mode of this round, only accepted from operators.
+    pub distribution_mode: Option<DistributionMode>,
+    /// Route and minimum output signed by the quote signer, used for this round's swap.
+    pub swap_quote: Option<SignedSwapQuote>,
+    /// Plan committed with `commit_distribution` in an earlier block.
+    pub distribution_plan: Option<DistributionPlan>,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Registers oracles besides `oracle_contract_id` and how their prices are combined.
+    pub fn set_oracles(
+        &mut self,
+        extra_oracle_ids: Vec<AccountId>,
+        aggregation: OracleAggregation,
+    ) {
+        self.assert_owner_or_timelock();
+        require!(extra_oracle_ids.len() < MAX_ORACLES, "Too many oracles");
+        if let OracleAggregation::MedianOf { min_reports } = aggregation {
+            require!(
+                min_reports > 0 && min_reports as usize <= extra_oracle_ids.len() + 1,
+                "Invalid number of required oracle reports"
+            );
+        }
+        self.extra_oracle_ids = extra_oracle_ids;
+        self.oracle_aggregation = aggregation;
+        self.oracle_reports.clear();
+        Event::ConfigChanged { field: "oracles" }.emit();
+    }
+
+    /// Maps tokens to the asset IDs of their oracle prices. Tokens without a mapping are
+    /// priced under their contract ID.
+    pub fn set_oracle_assets(&mut self, oracle_assets: Vec<OracleAsset>) {
+        self.assert_owner_or_timelock();
+        require!(
+            oracle_assets.len() <= MAX_ORACLE_ASSETS,
+            "Too many oracle assets"
+        );
+        for (i, oracle_asset) in oracle_assets.iter().enumerate() {
+            require!(!oracle_asset.asset_id.is_empty(), "Missing asset ID");
+            require!(
+                oracle_assets[..i]
+                    .iter()
+                    .all(|other| other.token_id != oracle_asset.token_id
+                        && other.asset_id != oracle_asset.asset_id),
+                "Duplicate oracle asset"
+            );
+        }
+        self.oracle_assets = oracle_assets;
+        self.oracle_reports.clear();
+        Event::ConfigChanged {
+            field: "oracle_assets",
+        }
+        .emit();
+    }
+
+    pub fn get_oracle_assets(&self) -> Vec<OracleAsset> {
+        self.oracle_assets.clone()
+    }
+
+    pub fn get_oracle_reports(&self) -> Vec<OracleReport> {
+        self.oracle_reports.clone()
+    }
+
+    pub fn get_last_oracle_result(&self) -> Option<OracleResult> {
+        self.last_oracle_result.clone()
+    }
+}
+
+impl Contract {
+    /// Token priced under `asset_id`. The contract ID of a mapped token isn't used as an
+    /// asset ID, neither are assets that aren't valid account IDs.
+    pub fn internal_oracle_token_id(&self, asset_id: &str) -> Option<AccountId> {
+        if let Some(oracle_asset) = self
+            .oracle_assets
+            .iter()
+            .find(|oracle_asset| oracle_asset.asset_id == asset_id)
+        {
+            return Some(oracle_asset.token_id.clone());
+        }
+        AccountId::try_from(asset_id.to_string())
+            .ok()
+            .filter(|token_id| {
+                self.oracle_assets
+                    .iter()
+                    .all(|oracle_asset| &oracle_asset.token_id != token_id)
+            })
+    }
+
+    pub fn internal_record_oracle_result(
+        &mut self,
+        oracle_id: AccountId,
+        accepted: bool,
+        reason: Option<&str>,
+    ) -> OracleResult {
+        if let Some(reason) = reason {
+            log!("Oracle call not swapped: {}", reason);
+        }
+        let result = OracleResult {
+            timestamp: env::block_timestamp(),
+            oracle_id,
+            accepted,
+            reason: reason.map(|reason| reason.to_string()),
+        };
+        self.last_oracle_result = Some(result.clone());
+        result
+    }
+
+    pub fn is_oracle(&self, account_id: &AccountId) -> bool {
+        account_id == &self.oracle_contract_id || self.extra_oracle_ids.contains(account_id)
+    }
+
+    /// Returns the wNEAR and reward token prices to use, or `None` while more reports are needed.
+    pub fn internal_aggregate_prices(&mut self, report: OracleReport) -> Option<(Price, Price)> {
+        let min_reports = match self.oracle_aggregation {
+            OracleAggregation::AnyOf => return Some((report.wnear_price, report.reward_price)),
+            OracleAggregation::MedianOf { min_reports } => min_reports as usize,
+        };
+        let timestamp = env::block_timestamp();
+        let max_staleness = self.oracle_max_staleness;
+        self.oracle_reports.retain(|r| {
+            r.oracle_id != report.oracle_id
+                && timestamp.saturating_sub(r.timestamp) <= max_staleness
+        });
+        self.oracle_reports.push(report);
+        let reports = self.oracle_reports.len();
+        if reports < min_reports {
+            Event::OracleReportRecorded {
+                reports: reports as u8,
+                min_reports: min_reports as u8,
+            }
+            .emit();
+            return None;
+        }
+        let median = |price: fn(&OracleReport) -> Price| {
+            let mut prices: Vec<Price> = self.oracle_reports.iter().map(price).collect();
+            prices.sort_by(|a, b| a.cmp_value(b));
+            prices[prices.len() / 2]
+        };
+        Some((median(|r| r.wnear_price), median(|r| r.reward_price)))
+    }
+}
@@ -0,0 +1,301 @@
+use super::*;
+
+const MAX_OUTPUT_TOKENS: usize = 2;
+const OUTPUT_BALANCE_GAS: Gas = Gas(5_000_000_000_000);
+const ON_OUTPUT_BALANCE_GAS: Gas = Gas(10_000_000_000_000);
+const ON_OUTPUT_SWAPPED_GAS: Gas = Gas(10_000_000_000_000);
+const ON_OUTPUT_BALANCE_READ_GAS: Gas = Gas(10_000_000_000_000);
+
+/// A token taking `weight_bps` of each price triggered distribution besides the reward token,
+/// swapped into from wNEAR through `swap_path`. wNEAR itself is farmed with
+/// `set_direct_wnear_farm`.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OutputToken {
+    pub token_id: AccountId,
+    pub swap_path: Vec<Action>,
+    pub farm_id: u64,
+    pub weight_bps: u32,
+}
+
+#[ext_contract(ext_self_output_tokens)]
+pub trait ExtOutputTokens {
+    fn on_output_balance_read(
+        &mut self,
+        #[callback_result] balance: Result<U128, PromiseError>,
+        amount: U128,
+        actions: Vec<Action>,
+        farm_id: u64,
+    );
+    fn on_output_swapped(
+        &mut self,
+        #[callback_result] used_amount: Result<U128, PromiseError>,
+        amount: U128,
+        from_buffer: U128,
+        token_id: AccountId,
+        farm_id: u64,
+        balance_before: U128,
+    );
+    fn on_output_balance(
+        &mut self,
+        #[callback_result] balance: Result<U128, PromiseError>,
+        token_id: AccountId,
+        farm_id: u64,
+        balance_before: U128,
+    );
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Splits price triggered distributions into the given tokens by weight, the reward
+    /// token takes the rest. An empty list distributes only the reward token. Changing the
+    /// reward token or the swap path clears the list.
+    pub fn set_output_tokens(&mut self, output_tokens: Vec<OutputToken>) -> Promise {
+        self.assert_owner_or_timelock();
+        require!(
+            self.output_swaps_in_flight == 0,
+            "Output token swaps are in flight"
+        );
+        require!(
+            output_tokens.len() <= MAX_OUTPUT_TOKENS,
+            "Too many output tokens"
+        );
+        require!(
+            output_tokens.iter().all(|output| output.weight_bps > 0)
+                && output_tokens
+                    .iter()
+                    .map(|output| output.weight_bps)
+                    .sum::<u32>()
+                    < MAX_BPS,
+            "Output token weights must be positive and leave a share to the reward token"
+        );
+        for output in &output_tokens {
+            require!(
+                output.token_id != self.reward_token_id,
+                "The reward token takes the rest of the distribution"
+            );
+            require!(
+                output.token_id != self.wrap_near_contract_id,
+                "wNEAR is farmed with set_direct_wnear_farm"
+            );
+            let swap_path = &output.swap_path;
+            assert_valid_path_hops(swap_path);
+            require!(
+                swap_path.first().unwrap().token_in == self.wrap_near_contract_id
+                    && swap_path.last().unwrap().token_out == output.token_id,
+                "Swap path has to lead from wNEAR to the output token"
+            );
+            require!(
+                swap_path.iter().all(|action| action.min_amount_out.0 == 0),
+                "Swap path can't set minimum amounts"
+            );
+            // Pyth only prices the tokens it has a feed for.
+            if matches!(self.price_source, PriceSource::Pyth(_)) {
+                require!(
+                    self.pyth_intermediate_feeds
+                        .iter()
+                        .any(|feed| feed.token_id == output.token_id),
+                    "Output token has no Pyth feed"
+                );
+            }
+        }
+        self.output_tokens = output_tokens;
+        Event::ConfigChanged {
+            field: "output_tokens",
+        }
+        .emit();
+        self.internal_fetch_token_decimals();
+        self.internal_register_storage()
+    }
+
+    pub fn get_output_tokens(&self) -> Vec<OutputToken> {
+        self.output_tokens.clone()
+    }
+
+    /// Swaps with the output token balance from before, to farm only what the swap added.
+    #[private]
+    pub fn on_output_balance_read(
+        &mut self,
+        #[callback_result] balance: Result<U128, PromiseError>,
+        amount: U128,
+        actions: Vec<Action>,
+        farm_id: u64,
+    ) -> PromiseOrValue<()> {
+        let balance = match balance {
+            Ok(balance) if self.internal_can_wrap_near(amount.0) => balance,
+            _ => {
+                // Nothing was wrapped yet, the NEAR goes back to the rewards.
+                self.output_swaps_in_flight -= 1;
+                self.near_swapping -= amount.0;
+                self.available_rewards += amount.0;
+                self.internal_swap_failed(amount.0, "output_token_balance_unknown");
+                return PromiseOrValue::Value(());
+            }
+        };
+        let token_id = actions.last().unwrap().token_out.clone();
+        let (receiver_id, msg) = self.internal_dex_swap_call(&DexAdapter::Ref, actions);
+        let from_buffer = std::cmp::min(self.wrapped_amount, amount.0);
+        let promise = self
+            .internal_wrap_near(amount.0)
+            .function_call(
+                "ft_transfer_call".to_string(),
+                serde_json::to_vec(&FtTransferCallArgs {
+                    receiver_id,
+                    amount,
+                    msg,
+                })
+                .unwrap(),
+                ONE_YOCTO,
+                self.gas.swap,
+            )
+            .then(ext_self_output_tokens::on_output_swapped(
+                amount,
+                from_buffer.into(),
+                token_id,
+                farm_id,
+                balance,
+                env::current_account_id(),
+                NO_DEPOSIT,
+                ON_OUTPUT_SWAPPED_GAS
+                    + OUTPUT_BALANCE_GAS
+                    + ON_OUTPUT_BALANCE_GAS
+                    + self.gas.add_farm,
+            ));
+        PromiseOrValue::Promise(promise)
+    }
+
+    #[private]
+    pub fn on_output_swapped(
+        &mut self,
+        #[callback_result] used_amount: Result<U128, PromiseError>,
+        amount: U128,
+        from_buffer: U128,
+        token_id: AccountId,
+        farm_id: u64,
+        balance_before: U128,
+    ) -> PromiseOrValue<()> {
+        self.near_swapping -= amount.0;
+        let used_amount = match used_amount {
+            Ok(used_amount) => used_amount.0,
+            Err(_) => {
+                // The failed batch reverted `near_deposit` too.
+                self.internal_revert_wrap_near(amount.0, from_buffer.0);
+                self.available_rewards += amount.0;
+                self.output_swaps_in_flight -= 1;
+                self.internal_swap_failed(amount.0, "output_token_gas");
+                return PromiseOrValue::Value(());
+            }
+        };
+        // Unswapped wNEAR returns to the buffer.
+        let unused_amount = amount.0 - used_amount;
+        self.wrapped_amount += unused_amount;
+        self.available_rewards += unused_amount;
+        if used_amount == 0 {
+            self.output_swaps_in_flight -= 1;
+            self.internal_swap_failed(amount.0, "output_token_slippage");
+            return PromiseOrValue::Value(());
+        }
+        self.near_swapped += used_amount;
+        let promise = ext_fungible_token::ft_balance_of(
+            env::current_account_id(),
+            token_id.clone(),
+            NO_DEPOSIT,
+            OUTPUT_BALANCE_GAS,
+        )
+        .then(ext_self_output_tokens::on_output_balance(
+            token_id,
+            farm_id,
+            balance_before,
+            env::current_account_id(),
+            NO_DEPOSIT,
+            ON_OUTPUT_BALANCE_GAS + self.gas.add_farm,
+        ));
+        PromiseOrValue::Promise(promise)
+    }
+
+    /// Farms what the balance grew by since the swap started. Tokens the farm refunds stay on
+    /// the balance and are left out of later farmings.
+    #[private]
+    pub fn on_output_balance(
+        &mut self,
+        #[callback_result] balance: Result<U128, PromiseError>,
+        token_id: AccountId,
+        farm_id: u64,
+        balance_before: U128,
+    ) {
+        self.output_swaps_in_flight -= 1;
+        let amount = match balance {
+            Ok(balance) => balance.0.saturating_sub(balance_before.0),
+            Err(_) => {
+                self.internal_record_error("output_tokens", "balance_read_failed", 0);
+                return;
+            }
+        };
+        if amount == 0 {
+            return;
+        }
+        Event::OutputTokenFarmed {
+            token_id: token_id.clone(),
+            amount: amount.into(),
+            farm_id,
+        }
+        .emit();
+        self.internal_farm_tokens(token_id, Some(farm_id), amount, None, self.farm_duration)
+            .as_return();
+    }
+}
+
+impl Contract {
+    /// Swaps and farms the shares of `reward` going to the output tokens, at the oracle
+    /// `prices` with the usual slippage. Returns the rest, for the reward token. The next
+    /// output swaps wait until these called back.
+    pub fn internal_distribute_output_tokens(
+        &mut self,
+        reward: Balance,
+        prices: &HashMap<AccountId, Price>,
+    ) -> Balance {
+        require!(
+            self.output_swaps_in_flight == 0,
+            "Output token swaps are in flight"
+        );
+        let mut rest = reward;
+        for output in self.output_tokens.clone() {
+            let amount = u128_ratio(reward, output.weight_bps as u128, MAX_BPS as u128);
+            if amount == 0 {
+                continue;
+            }
+            rest -= amount;
+            let price_in = prices.get(&self.wrap_near_contract_id).unwrap();
+            let price_out = prices
+                .get(&output.token_id)
+                .expect("Missing oracle price of an output token");
+            let min_amount_out =
+                self.internal_apply_slippage(oracle_amount_out(amount, price_in, price_out));
+            let mut actions = output.swap_path;
+            actions.last_mut().unwrap().min_amount_out = min_amount_out.into();
+            self.near_swapping += amount;
+            self.output_swaps_in_flight += 1;
+            ext_fungible_token::ft_balance_of(
+                env::current_account_id(),
+                output.token_id,
+                NO_DEPOSIT,
+                OUTPUT_BALANCE_GAS,
+            )
+            .then(ext_self_output_tokens::on_output_balance_read(
+                amount.into(),
+                actions,
+                output.farm_id,
+                env::current_account_id(),
+                NO_DEPOSIT,
+                ON_OUTPUT_BALANCE_READ_GAS
+                    + self.gas.wrap_near
+                    + self.gas.swap
+                    + ON_OUTPUT_SWAPPED_GAS
+                    + OUTPUT_BALANCE_GAS
+                    + ON_OUTPUT_BALANCE_GAS
+                    + self.gas.add_farm,
+            ));
+        }
+        rest
+    }
+}
@@ -0,0 +1,73 @@
+use super::*;
+
+/// Stage of the reward pipeline, from the staking pool to the farm.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum PipelineStage {
+    /// Nothing is pending at the staking pool or in flight.
+    Idle,
+    /// Rewards are being unstaked at the staking pool.
+    AwaitingUnstake,
+    /// Unstaked rewards wait for the unstaking period to end or are being withdrawn.
+    AwaitingWithdraw,
+    /// NEAR is being swapped into the reward token.
+    SwapInFlight,
+    /// Reward tokens are being added to the farm or bought back.
+    Distributing,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PipelineState {
+    pub stage: PipelineStage,
+    /// When the pipeline entered `stage`, in nanoseconds.
+    pub since: U64,
+    /// NEAR unstaked at the staking pool as of the last ping.
+    pub pending_at_pool: U128,
+    pub near_swapping: U128,
+    pub held_reward_tokens: U128,
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_pipeline_state(&self) -> PipelineState {
+        PipelineState {
+            stage: self.pipeline_stage,
+            since: self.pipeline_stage_since.into(),
+            pending_at_pool: self.pending_at_pool.into(),
+            near_swapping: self.near_swapping.into(),
+            held_reward_tokens: self.held_reward_tokens.into(),
+        }
+    }
+}
+
+impl Contract {
+    pub fn internal_set_pipeline_stage(&mut self, stage: PipelineStage) {
+        if self.pipeline_stage != stage {
+            self.pipeline_stage = stage;
+            self.pipeline_stage_since = env::block_timestamp();
+        }
+    }
+
+    /// Records a staking pool stage, unless a swap or distribution is still under way.
+    pub fn internal_set_pool_stage(&mut self, stage: PipelineStage) {
+        if !matches!(
+            self.pipeline_stage,
+            PipelineStage::SwapInFlight | PipelineStage::Distributing
+        ) {
+            self.internal_set_pipeline_stage(stage);
+        }
+    }
+
+    /// Returns to the staking pool stage once a swap or distribution is over.
+    pub fn internal_end_distribution_stage(&mut self) {
+        let stage = if self.distribution_in_flight {
+            PipelineStage::SwapInFlight
+        } else if self.pending_at_pool > 0 {
+            PipelineStage::AwaitingWithdraw
+        } else {
+            PipelineStage::Idle
+        };
+        self.internal_set_pipeline_stage(stage);
+    }
+}
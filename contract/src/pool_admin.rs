@@ -0,0 +1,105 @@
+use super::*;
+use near_sdk::PublicKey;
+
+const POOL_ADMIN_TIMELOCK: Duration = 24 * 60 * 60 * 1_000_000_000;
+const POOL_ADMIN_GAS: Gas = Gas(20_000_000_000_000);
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RewardFeeFraction {
+    pub numerator: u32,
+    pub denominator: u32,
+}
+
+/// Staking pool administration that this contract performs as the pool owner.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub enum PoolAdminAction {
+    UpdateRewardFeeFraction(RewardFeeFraction),
+    UpdateStakingKey(PublicKey),
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingPoolAction {
+    pub action: PoolAdminAction,
+    #[serde(with = "u64_dec_format")]
+    pub executable_at: Timestamp,
+}
+
+#[ext_contract(ext_staking_pool_owner)]
+pub trait StakingPoolOwnerContract {
+    fn update_reward_fee_fraction(&mut self, reward_fee_fraction: RewardFeeFraction);
+    fn update_staking_key(&mut self, stake_public_key: PublicKey);
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Schedules an administrative call to the staking pool, replacing any pending one.
+    pub fn propose_pool_action(&mut self, action: PoolAdminAction) {
+        self.assert_owner();
+        if let PoolAdminAction::UpdateRewardFeeFraction(fraction) = &action {
+            require!(
+                fraction.denominator != 0 && fraction.numerator <= fraction.denominator,
+                "Invalid reward fee fraction"
+            );
+        }
+        let executable_at = env::block_timestamp() + POOL_ADMIN_TIMELOCK;
+        Event::PoolActionProposed {
+            action: action.clone(),
+            executable_at: executable_at.into(),
+        }
+        .emit();
+        self.pending_pool_action = Some(PendingPoolAction {
+            action,
+            executable_at,
+        });
+    }
+
+    pub fn cancel_pool_action(&mut self) {
+        self.assert_owner();
+        require!(
+            self.pending_pool_action.take().is_some(),
+            "No pending pool action"
+        );
+        Event::PoolActionCancelled.emit();
+    }
+
+    pub fn execute_pool_action(&mut self) -> Promise {
+        self.assert_owner();
+        let pending = self
+            .pending_pool_action
+            .take()
+            .expect("No pending pool action");
+        require!(
+            env::block_timestamp() >= pending.executable_at,
+            "Pool action is still timelocked"
+        );
+        Event::PoolActionExecuted {
+            action: pending.action.clone(),
+        }
+        .emit();
+        match pending.action {
+            PoolAdminAction::UpdateRewardFeeFraction(reward_fee_fraction) => {
+                ext_staking_pool_owner::update_reward_fee_fraction(
+                    reward_fee_fraction,
+                    self.staking_pool_account_id.clone(),
+                    NO_DEPOSIT,
+                    POOL_ADMIN_GAS,
+                )
+            }
+            PoolAdminAction::UpdateStakingKey(stake_public_key) => {
+                ext_staking_pool_owner::update_staking_key(
+                    stake_public_key,
+                    self.staking_pool_account_id.clone(),
+                    NO_DEPOSIT,
+                    POOL_ADMIN_GAS,
+                )
+            }
+        }
+    }
+
+    pub fn get_pending_pool_action(&self) -> Option<PendingPoolAction> {
+        self.pending_pool_action.clone()
+    }
+}
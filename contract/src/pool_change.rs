@@ -0,0 +1,183 @@
+use super::*;
+
+const ON_STAKING_POOL_DRAINED_GAS: Gas = Gas(40_000_000_000_000);
+const WHITELIST_READ_GAS: Gas = Gas(5_000_000_000_000);
+const ON_POOL_WHITELIST_CHECKED_GAS: Gas = Gas(70_000_000_000_000);
+
+/// A switch to another staking pool, applied once the current one is drained.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StakingPoolChange {
+    pub staking_pool_account_id: AccountId,
+    /// Farm of the reward token at the new pool, a new farm is created without it.
+    pub farm_id: Option<u64>,
+    #[serde(with = "u64_dec_format")]
+    pub started_at: Timestamp,
+}
+
+/// Interface of the staking pool whitelist, e.g. the one of the NEAR Foundation.
+#[ext_contract(ext_staking_pool_whitelist)]
+pub trait StakingPoolWhitelist {
+    fn is_whitelisted(&self, staking_pool_account_id: AccountId) -> bool;
+}
+
+#[ext_contract(ext_self_pool_change)]
+pub trait ExtPoolChange {
+    fn on_staking_pool_drained(&mut self, #[callback] account: StakingPoolAccount);
+    fn on_pool_whitelist_checked(
+        &mut self,
+        #[callback] whitelisted: bool,
+        staking_pool_account_id: AccountId,
+        farm_id: Option<u64>,
+    );
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Staking pools have to be whitelisted by this contract to be used, `None` accepts any.
+    pub fn set_staking_pool_whitelist(&mut self, staking_pool_whitelist_id: Option<AccountId>) {
+        self.assert_owner_or_timelock();
+        self.staking_pool_whitelist_id = staking_pool_whitelist_id;
+        Event::ConfigChanged {
+            field: "staking_pool_whitelist_id",
+        }
+        .emit();
+    }
+
+    /// Starts moving to another staking pool by unstaking everything from the current one.
+    /// Pings keep withdrawing from it until `finish_staking_pool_change` finds it empty.
+    /// With a staking pool whitelist, the new pool has to be whitelisted.
+    pub fn start_staking_pool_change(
+        &mut self,
+        staking_pool_account_id: AccountId,
+        farm_id: Option<u64>,
+    ) -> Promise {
+        self.assert_owner_or_timelock();
+        match self.staking_pool_whitelist_id.clone() {
+            Some(whitelist_id) => ext_staking_pool_whitelist::is_whitelisted(
+                staking_pool_account_id.clone(),
+                whitelist_id,
+                NO_DEPOSIT,
+                WHITELIST_READ_GAS,
+            )
+            .then(ext_self_pool_change::on_pool_whitelist_checked(
+                staking_pool_account_id,
+                farm_id,
+                env::current_account_id(),
+                NO_DEPOSIT,
+                ON_POOL_WHITELIST_CHECKED_GAS,
+            )),
+            None => self.internal_start_staking_pool_change(staking_pool_account_id, farm_id),
+        }
+    }
+
+    #[private]
+    pub fn on_pool_whitelist_checked(
+        &mut self,
+        #[callback] whitelisted: bool,
+        staking_pool_account_id: AccountId,
+        farm_id: Option<u64>,
+    ) -> Promise {
+        require!(whitelisted, "Staking pool is not whitelisted");
+        self.internal_start_staking_pool_change(staking_pool_account_id, farm_id)
+    }
+
+    pub fn cancel_staking_pool_change(&mut self) {
+        self.assert_owner();
+        require!(
+            self.staking_pool_change.take().is_some(),
+            "No staking pool change in progress"
+        );
+        Event::ConfigChanged {
+            field: "staking_pool_change",
+        }
+        .emit();
+    }
+
+    /// Switches to the new staking pool if nothing is left at the current one.
+    pub fn finish_staking_pool_change(&mut self) -> Promise {
+        self.assert_owner();
+        require!(
+            self.staking_pool_change.is_some(),
+            "No staking pool change in progress"
+        );
+        ext_staking_pool::get_account(
+            env::current_account_id(),
+            self.staking_pool_account_id.clone(),
+            NO_DEPOSIT,
+            STAKING_POOL_READ_GAS,
+        )
+        .then(ext_self_pool_change::on_staking_pool_drained(
+            env::current_account_id(),
+            NO_DEPOSIT,
+            ON_STAKING_POOL_DRAINED_GAS,
+        ))
+    }
+
+    #[private]
+    pub fn on_staking_pool_drained(&mut self, #[callback] account: StakingPoolAccount) {
+        require!(
+            account.staked_balance.0 == 0 && account.unstaked_balance.0 == 0,
+            "The staking pool isn't drained yet, ping until everything is withdrawn"
+        );
+        let change = self
+            .staking_pool_change
+            .take()
+            .expect("No staking pool change in progress");
+        Event::StakingPoolChanged {
+            old_staking_pool_account_id: self.staking_pool_account_id.clone(),
+            staking_pool_account_id: change.staking_pool_account_id.clone(),
+        }
+        .emit();
+        self.staking_pool_account_id = change.staking_pool_account_id;
+        match change.farm_id {
+            Some(farm_id) => self.farm_id = farm_id,
+            None => self.create_farm = true,
+        }
+        // State kept about the previous pool doesn't apply to the new one.
+        self.pending_at_pool = 0;
+        self.pool_total_stake = 0;
+        self.pending_pool_action = None;
+        self.validated_farm = None;
+        if matches!(self.farm_rotation, FarmRotation::NextFarmIds(_)) {
+            self.farm_rotation = FarmRotation::Disabled;
+        }
+        self.internal_register_storage().as_return();
+    }
+
+    pub fn get_staking_pool_change(&self) -> Option<StakingPoolChange> {
+        self.staking_pool_change.clone()
+    }
+}
+
+impl Contract {
+    fn internal_start_staking_pool_change(
+        &mut self,
+        staking_pool_account_id: AccountId,
+        farm_id: Option<u64>,
+    ) -> Promise {
+        require!(
+            self.staking_pool_change.is_none(),
+            "A staking pool change is already in progress"
+        );
+        require!(
+            staking_pool_account_id != self.staking_pool_account_id,
+            "Already using this staking pool"
+        );
+        require!(self.restaked == 0, "Release the restaked NEAR first");
+        Event::StakingPoolChangeStarted {
+            staking_pool_account_id: staking_pool_account_id.clone(),
+        }
+        .emit();
+        self.staking_pool_change = Some(StakingPoolChange {
+            staking_pool_account_id,
+            farm_id,
+            started_at: env::block_timestamp(),
+        });
+        ext_staking_pool::unstake_all(
+            self.staking_pool_account_id.clone(),
+            NO_DEPOSIT,
+            self.gas.unstake,
+        )
+    }
+}
@@ -0,0 +1,146 @@
+use super::*;
+
+const ON_PRE_SWAP_QUOTE_GAS: Gas = Gas(15_000_000_000_000);
+
+#[ext_contract(ext_self_pre_swap_check)]
+pub trait ExtPreSwapCheck {
+    fn on_pre_swap_quote(
+        &mut self,
+        #[callback_result] quote: Result<DexQuote, PromiseError>,
+        hop_index: u32,
+        path_index: u32,
+        swap: PendingSwap,
+    );
+}
+
+/// A swap waiting for the quote of its path.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingSwap {
+    pub reward: U128,
+    pub min_amount_out: U128,
+    pub hop_min_amounts: Vec<U128>,
+    pub expected_amount_out: U128,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Quotes the swap path before price triggered swaps and skips the swap if the quote is
+    /// more than `tolerance_bps` below the oracle amount. `None` swaps without quoting.
+    pub fn set_pre_swap_quote_tolerance(&mut self, tolerance_bps: Option<u32>) {
+        self.assert_owner_or_timelock();
+        if let Some(tolerance_bps) = tolerance_bps {
+            require!(tolerance_bps <= MAX_BPS, "Tolerance is too large");
+        }
+        self.pre_swap_quote_tolerance_bps = tolerance_bps;
+        Event::ConfigChanged {
+            field: "pre_swap_quote_tolerance_bps",
+        }
+        .emit();
+    }
+
+    #[private]
+    pub fn on_pre_swap_quote(
+        &mut self,
+        #[callback_result] quote: Result<DexQuote, PromiseError>,
+        hop_index: u32,
+        path_index: u32,
+        swap: PendingSwap,
+    ) -> PromiseOrValue<()> {
+        let amount_out = match quote {
+            // The path changed since the quote started.
+            Ok(_) if path_index != self.swap_path_index => {
+                return self.internal_abort_pre_swap(swap.reward.0, "swap_path_changed")
+            }
+            Ok(quote) => quote.amount().0,
+            Err(_) => return self.internal_abort_pre_swap(swap.reward.0, "pre_swap_quote_failed"),
+        };
+        let next_hop_index = hop_index + 1;
+        if (next_hop_index as usize) < self.internal_current_swap_path().len() {
+            return PromiseOrValue::Promise(self.internal_quote_pre_swap_hop(
+                next_hop_index,
+                amount_out,
+                swap,
+            ));
+        }
+        let tolerance_bps = self.pre_swap_quote_tolerance_bps.unwrap_or(MAX_BPS);
+        let min_quote = u128_ratio(
+            swap.expected_amount_out.0,
+            (MAX_BPS - tolerance_bps) as u128,
+            MAX_BPS as u128,
+        );
+        if amount_out < min_quote {
+            return self.internal_abort_pre_swap(swap.reward.0, "pre_swap_quote_too_low");
+        }
+        self.distribution_in_flight = false;
+        PromiseOrValue::Promise(self.internal_swap(
+            swap.reward.0,
+            swap.min_amount_out.0,
+            None,
+            swap.hop_min_amounts.iter().map(|amount| amount.0).collect(),
+            Some(swap.expected_amount_out.0),
+        ))
+    }
+}
+
+impl Contract {
+    /// Whether price triggered swaps through the current path are quoted first.
+    pub fn internal_checks_pre_swap_quote(&self) -> bool {
+        self.pre_swap_quote_tolerance_bps.is_some() && self.internal_swaps_current_path()
+    }
+
+    /// Whether swaps go through the current swap path, not the executor or the split routes.
+    pub fn internal_swaps_current_path(&self) -> bool {
+        self.swap_executor_id.is_none() && self.split_routes.is_empty()
+    }
+
+    /// Holds the distribution lock while quoting `swap` hop by hop.
+    pub fn internal_start_pre_swap_quote(&mut self, swap: PendingSwap) -> Promise {
+        require!(
+            !self.distribution_in_flight,
+            "A distribution is already in flight"
+        );
+        self.distribution_in_flight = true;
+        self.internal_quote_pre_swap_hop(0, swap.reward.0, swap)
+    }
+
+    fn internal_quote_pre_swap_hop(
+        &self,
+        hop_index: u32,
+        amount_in: Balance,
+        swap: PendingSwap,
+    ) -> Promise {
+        let remaining_hops =
+            (self.internal_current_swap_path().len() - hop_index as usize - 1) as u64;
+        self.internal_dex_quote_hop(
+            self.swap_path_index,
+            hop_index as usize,
+            amount_in,
+            REF_QUOTE_GAS,
+        )
+        .then(ext_self_pre_swap_check::on_pre_swap_quote(
+            hop_index,
+            self.swap_path_index,
+            swap,
+            env::current_account_id(),
+            NO_DEPOSIT,
+            ON_PRE_SWAP_QUOTE_GAS
+                + self.gas.wrap_near
+                + self.gas.swap
+                + self.gas.on_swap
+                + (REF_QUOTE_GAS + ON_PRE_SWAP_QUOTE_GAS) * remaining_hops,
+        ))
+    }
+
+    /// Puts the reward back into the swap queue for the next distribution.
+    fn internal_abort_pre_swap(
+        &mut self,
+        reward: Balance,
+        reason: &'static str,
+    ) -> PromiseOrValue<()> {
+        self.distribution_in_flight = false;
+        self.queued_swap_amount += reward;
+        self.internal_swap_failed(reward, reason);
+        PromiseOrValue::Value(())
+    }
+}
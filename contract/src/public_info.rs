@@ -0,0 +1,125 @@
+use super::*;
+
+const MAX_LABELS: usize = 10;
+const MAX_LINKS: usize = 10;
+const MAX_METADATA_LENGTH: usize = 256;
+/// Distributions of this window are annualized into the APR estimate.
+const APR_WINDOW: Duration = 30 * ONE_DAY;
+const ONE_YEAR: Duration = 365 * ONE_DAY;
+const POOL_TOTAL_STAKE_READ_GAS: Gas = Gas(5_000_000_000_000);
+const ON_POOL_TOTAL_STAKE_GAS: Gas = Gas(5_000_000_000_000);
+/// Gas of the total stake read `ping` starts on the side.
+pub const POOL_TOTAL_STAKE_GAS: Gas = Gas(POOL_TOTAL_STAKE_READ_GAS.0 + ON_POOL_TOTAL_STAKE_GAS.0);
+
+/// Validator details set by the owner for wallets.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PublicMetadata {
+    pub name: Option<String>,
+    /// Short tags, e.g. "farm" or "community".
+    pub labels: Vec<String>,
+    /// Named links, e.g. "website" or "telegram".
+    pub links: HashMap<String, String>,
+}
+
+/// What delegators see in a wallet. Fields are only added, never renamed or removed.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PublicInfo {
+    pub metadata: PublicMetadata,
+    pub staking_pool_account_id: AccountId,
+    /// Token farmed for delegators and its farm.
+    pub farm_token_id: AccountId,
+    pub farm_id: u64,
+    pub distribution_mode: DistributionMode,
+    /// Annualized NEAR value farmed over the last 30 days relative to the pool stake.
+    /// `None` until the pool stake is known.
+    pub apr_estimate_bps: Option<u32>,
+    /// Shares of the pool rewards taken before farming.
+    pub owner_fee_bps: u32,
+    pub keeper_reward_bps: u32,
+    pub restake_bps: u32,
+    /// Share of the swapped reward tokens sent to the treasury instead of the farm.
+    pub treasury_share_bps: u32,
+}
+
+#[ext_contract(ext_self_public_info)]
+pub trait ExtPublicInfo {
+    fn on_pool_total_stake(&mut self, #[callback] total_staked_balance: U128);
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn set_public_metadata(&mut self, metadata: PublicMetadata) {
+        self.assert_owner();
+        require!(
+            metadata.labels.len() <= MAX_LABELS && metadata.links.len() <= MAX_LINKS,
+            "Too many labels or links"
+        );
+        let fields = metadata.name.iter().chain(&metadata.labels).chain(
+            metadata
+                .links
+                .iter()
+                .flat_map(|(name, url)| vec![name, url]),
+        );
+        for field in fields {
+            require!(field.len() <= MAX_METADATA_LENGTH, "Metadata is too long");
+        }
+        self.public_metadata = metadata;
+        Event::ConfigChanged {
+            field: "public_metadata",
+        }
+        .emit();
+    }
+
+    pub fn get_public_info(&self) -> PublicInfo {
+        PublicInfo {
+            metadata: self.public_metadata.clone(),
+            staking_pool_account_id: self.staking_pool_account_id.clone(),
+            farm_token_id: self.reward_token_id.clone(),
+            farm_id: self.farm_id,
+            distribution_mode: self.distribution_mode,
+            apr_estimate_bps: self.internal_apr_estimate_bps(),
+            owner_fee_bps: self.owner_fee_bps,
+            keeper_reward_bps: self.keeper_reward_bps,
+            restake_bps: self.restake_bps,
+            treasury_share_bps: self.treasury_share_bps,
+        }
+    }
+
+    #[private]
+    pub fn on_pool_total_stake(&mut self, #[callback] total_staked_balance: U128) {
+        self.pool_total_stake = total_staked_balance.0;
+    }
+}
+
+impl Contract {
+    /// Refreshes the total stake of the pool used by the APR estimate.
+    pub fn internal_read_pool_total_stake(&self) -> Promise {
+        ext_staking_pool::get_total_staked_balance(
+            self.staking_pool_account_id.clone(),
+            NO_DEPOSIT,
+            POOL_TOTAL_STAKE_READ_GAS,
+        )
+        .then(ext_self_public_info::on_pool_total_stake(
+            env::current_account_id(),
+            NO_DEPOSIT,
+            ON_POOL_TOTAL_STAKE_GAS,
+        ))
+    }
+
+    pub fn internal_apr_estimate_bps(&self) -> Option<u32> {
+        if self.pool_total_stake == 0 {
+            return None;
+        }
+        let since = env::block_timestamp().saturating_sub(APR_WINDOW);
+        let near_farmed = u128_ratio(
+            self.internal_near_swapped_since(since),
+            (MAX_BPS - self.treasury_share_bps) as u128,
+            MAX_BPS as u128,
+        );
+        let yearly_near_farmed = u128_ratio(near_farmed, ONE_YEAR as u128, APR_WINDOW as u128);
+        let apr_bps = u128_ratio(yearly_near_farmed, MAX_BPS as u128, self.pool_total_stake);
+        Some(std::cmp::min(apr_bps, u32::MAX as u128) as u32)
+    }
+}
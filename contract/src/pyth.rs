@@ -0,0 +1,191 @@
+use super::*;
+use near_sdk::json_types::I64;
+use near_sdk::PromiseResult;
+
+const PYTH_READ_GAS: Gas = Gas(10_000_000_000_000);
+
+/// Where the prices for sizing a swap come from.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum PriceSource {
+    /// Prices are pushed by registered oracles through `oracle_on_call`.
+    PushOracle,
+    /// Prices are pulled from the Pyth contract with `pull_pyth_prices`.
+    Pyth(PythConfig),
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PythConfig {
+    pub pyth_contract_id: AccountId,
+    /// Hex encoded Pyth price feed IDs.
+    pub wnear_price_id: String,
+    pub reward_price_id: String,
+    pub wnear_decimals: u8,
+    pub reward_decimals: u8,
+}
+
+/// Pyth feed of a token on the swap path besides wNEAR and the reward token.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PythFeed {
+    pub token_id: AccountId,
+    /// Hex encoded Pyth price feed ID.
+    pub price_id: String,
+    pub decimals: u8,
+}
+
+/// USD price of a whole token as reported by Pyth: `price * 10^expo`.
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PythPrice {
+    pub price: I64,
+    pub expo: i32,
+    /// Unix timestamp in seconds.
+    pub publish_time: i64,
+}
+
+impl PythPrice {
+    /// Converts into the price of the smallest token unit used by the push oracle.
+    pub fn into_price(self, token_decimals: u8, max_staleness: Duration) -> Price {
+        require!(self.price.0 > 0 && self.expo <= 0, "Invalid Pyth price");
+        let publish_time =
+            u64::try_from(self.publish_time).expect("Invalid Pyth publish time") * 10u64.pow(9);
+        require!(
+            env::block_timestamp().saturating_sub(publish_time) <= max_staleness,
+            "Pyth price is too stale"
+        );
+        let price = Price {
+            multiplier: self.price.0 as u128,
+            decimals: u8::try_from(-self.expo).expect("Invalid Pyth exponent") + token_decimals,
+        };
+        price.assert_valid();
+        price
+    }
+}
+
+#[ext_contract(ext_pyth)]
+pub trait PythContract {
+    fn get_price(&self, price_identifier: String) -> Option<PythPrice>;
+}
+
+#[ext_contract(ext_self_pyth)]
+pub trait ExtPyth {
+    fn on_pyth_prices(
+        &mut self,
+        quote: Option<SwapQuote>,
+        plan: Option<DistributionPlan>,
+    ) -> PromiseOrValue<()>;
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn set_price_source(&mut self, price_source: PriceSource) {
+        self.assert_owner_or_timelock();
+        self.price_source = price_source;
+        Event::ConfigChanged {
+            field: "price_source",
+        }
+        .emit();
+    }
+
+    /// Sets the Pyth feeds of intermediate tokens, pulled together with wNEAR and the
+    /// reward token to protect every hop of the swap.
+    pub fn set_pyth_intermediate_feeds(&mut self, pyth_intermediate_feeds: Vec<PythFeed>) {
+        self.assert_owner();
+        require!(
+            pyth_intermediate_feeds.len() < MAX_SWAP_PATH_HOPS,
+            "Too many intermediate feeds"
+        );
+        self.pyth_intermediate_feeds = pyth_intermediate_feeds;
+        Event::ConfigChanged {
+            field: "pyth_intermediate_feeds",
+        }
+        .emit();
+    }
+
+    /// Pulls wNEAR and reward token prices from Pyth and distributes the reward due now.
+    /// A signed `swap_quote` sets the route and minimum output of the swap, a
+    /// `distribution_plan` reveals the committed plan.
+    pub fn pull_pyth_prices(
+        &mut self,
+        swap_quote: Option<SignedSwapQuote>,
+        distribution_plan: Option<DistributionPlan>,
+    ) -> Promise {
+        self.assert_not_paused();
+        self.assert_setup_complete();
+        let quote = swap_quote.map(|signed_quote| self.internal_verify_swap_quote(signed_quote));
+        let plan = distribution_plan.map(|plan| self.internal_reveal_distribution_plan(plan));
+        let config = match &self.price_source {
+            PriceSource::Pyth(config) => config,
+            _ => env::panic_str("Pyth is not the price source"),
+        };
+        let price_ids = std::iter::once(&config.wnear_price_id)
+            .chain(std::iter::once(&config.reward_price_id))
+            .chain(
+                self.pyth_intermediate_feeds
+                    .iter()
+                    .map(|feed| &feed.price_id),
+            );
+        price_ids
+            .map(|price_id| {
+                ext_pyth::get_price(
+                    price_id.clone(),
+                    config.pyth_contract_id.clone(),
+                    NO_DEPOSIT,
+                    PYTH_READ_GAS,
+                )
+            })
+            .reduce(|promise, read| promise.and(read))
+            .unwrap()
+            .then(ext_self_pyth::on_pyth_prices(
+                quote,
+                plan,
+                env::current_account_id(),
+                NO_DEPOSIT,
+                self.gas.on_pyth_prices,
+            ))
+    }
+
+    /// Receives the prices of wNEAR, the reward token and then the intermediate feeds.
+    #[private]
+    pub fn on_pyth_prices(
+        &mut self,
+        quote: Option<SwapQuote>,
+        plan: Option<DistributionPlan>,
+    ) -> PromiseOrValue<()> {
+        self.assert_not_paused();
+        let config = match &self.price_source {
+            PriceSource::Pyth(config) => config.clone(),
+            _ => env::panic_str("Pyth is not the price source"),
+        };
+        require!(
+            env::promise_results_count() == 2 + self.pyth_intermediate_feeds.len() as u64,
+            "Pyth feeds changed while pulling prices"
+        );
+        let max_staleness = self.oracle_max_staleness;
+        let price = |index: u64, decimals: u8| match env::promise_result(index) {
+            PromiseResult::Successful(value) => serde_json::from_slice::<Option<PythPrice>>(&value)
+                .expect("Invalid Pyth price")
+                .map(|price| price.into_price(decimals, max_staleness)),
+            _ => env::panic_str("Failed to read Pyth price"),
+        };
+        let wnear_price = price(0, config.wnear_decimals).expect("Missing wNEAR price");
+        let reward_price = price(1, config.reward_decimals).expect("Missing reward token price");
+        let intermediate_prices = self
+            .pyth_intermediate_feeds
+            .iter()
+            .enumerate()
+            .filter_map(|(index, feed)| {
+                price(index as u64 + 2, feed.decimals).map(|price| (feed.token_id.clone(), price))
+            })
+            .collect();
+        self.internal_distribute_with_prices(
+            wnear_price,
+            reward_price,
+            intermediate_prices,
+            quote,
+            plan,
+        )
+    }
+}
@@ -0,0 +1,160 @@
+use super::*;
+
+const REF_READ_GAS: Gas = Gas(5_000_000_000_000);
+const BALANCE_READ_GAS: Gas = Gas(5_000_000_000_000);
+/// Covers REF's `ft_transfer` and its callback.
+const REF_WITHDRAW_GAS: Gas = Gas(45_000_000_000_000);
+const ON_REF_DEPOSIT_WITHDRAWN_GAS: Gas = Gas(10_000_000_000_000);
+const ON_REF_BALANCE_READ_GAS: Gas = Gas(5_000_000_000_000
+    + REF_WITHDRAW_GAS.0
+    + BALANCE_READ_GAS.0
+    + ON_REF_DEPOSIT_WITHDRAWN_GAS.0);
+/// Tokens withdrawn per call, the others are left for the next one.
+const MAX_RECLAIMED_TOKENS: u64 = 3;
+const ON_REF_DEPOSITS_GAS: Gas =
+    Gas(10_000_000_000_000
+        + (BALANCE_READ_GAS.0 + ON_REF_BALANCE_READ_GAS.0) * MAX_RECLAIMED_TOKENS);
+
+/// Deposits held by REF for an account
+#[ext_contract(ext_ref_deposits)]
+pub trait RefDeposits {
+    fn get_deposits(&self, account_id: AccountId) -> HashMap<AccountId, U128>;
+    fn withdraw(&mut self, token_id: AccountId, amount: U128, unregister: Option<bool>);
+}
+
+#[ext_contract(ext_self_ref_deposits)]
+pub trait ExtRefDeposits {
+    fn on_ref_deposits(&mut self, #[callback] deposits: HashMap<AccountId, U128>);
+    fn on_ref_balance_read(
+        &mut self,
+        #[callback_result] balance: Result<U128, PromiseError>,
+        token_id: AccountId,
+        amount: U128,
+    );
+    fn on_ref_deposit_withdrawn(
+        &mut self,
+        #[callback_result] balance: Result<U128, PromiseError>,
+        token_id: AccountId,
+        balance_before: U128,
+    );
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Withdraws tokens left in the contract's deposits on REF, e.g. refunds of partially
+    /// executed swaps. wNEAR goes back to the wNEAR buffer, reward tokens are farmed with
+    /// the next distribution and other tokens can be rescued.
+    pub fn reclaim_ref_deposits(&mut self) -> Promise {
+        self.assert_operator();
+        require!(
+            !self.distribution_in_flight,
+            "A distribution is in flight, retry after its swap"
+        );
+        ext_ref_deposits::get_deposits(
+            env::current_account_id(),
+            self.ref_finance_contract_id.clone(),
+            NO_DEPOSIT,
+            REF_READ_GAS,
+        )
+        .then(ext_self_ref_deposits::on_ref_deposits(
+            env::current_account_id(),
+            NO_DEPOSIT,
+            ON_REF_DEPOSITS_GAS,
+        ))
+    }
+
+    #[private]
+    pub fn on_ref_deposits(&mut self, #[callback] deposits: HashMap<AccountId, U128>) {
+        let mut deposits: Vec<(AccountId, U128)> = deposits
+            .into_iter()
+            .filter(|(_, amount)| amount.0 > 0)
+            .collect();
+        deposits.sort_by(|(a, _), (b, _)| a.cmp(b));
+        for (token_id, amount) in deposits.into_iter().take(MAX_RECLAIMED_TOKENS as usize) {
+            ext_fungible_token::ft_balance_of(
+                env::current_account_id(),
+                token_id.clone(),
+                NO_DEPOSIT,
+                BALANCE_READ_GAS,
+            )
+            .then(ext_self_ref_deposits::on_ref_balance_read(
+                token_id,
+                amount,
+                env::current_account_id(),
+                NO_DEPOSIT,
+                ON_REF_BALANCE_READ_GAS,
+            ));
+        }
+    }
+
+    /// Withdraws `amount` of `token_id` with its balance from before, as REF's `withdraw`
+    /// can succeed while its transfer fails and the deposit is restored.
+    #[private]
+    pub fn on_ref_balance_read(
+        &mut self,
+        #[callback_result] balance: Result<U128, PromiseError>,
+        token_id: AccountId,
+        amount: U128,
+    ) -> PromiseOrValue<()> {
+        let balance = match balance {
+            Ok(balance) => balance,
+            Err(_) => return PromiseOrValue::Value(()),
+        };
+        let promise = ext_ref_deposits::withdraw(
+            token_id.clone(),
+            amount,
+            None,
+            self.ref_finance_contract_id.clone(),
+            ONE_YOCTO,
+            REF_WITHDRAW_GAS,
+        )
+        .then(ext_fungible_token::ft_balance_of(
+            env::current_account_id(),
+            token_id.clone(),
+            NO_DEPOSIT,
+            BALANCE_READ_GAS,
+        ))
+        .then(ext_self_ref_deposits::on_ref_deposit_withdrawn(
+            token_id,
+            balance,
+            env::current_account_id(),
+            NO_DEPOSIT,
+            ON_REF_DEPOSIT_WITHDRAWN_GAS,
+        ));
+        PromiseOrValue::Promise(promise)
+    }
+
+    /// Counts what the balance grew by. Reclaimed wNEAR was counted as swapped when the swap
+    /// left it on REF, so it moves back from `near_swapped` into the wNEAR buffer.
+    #[private]
+    pub fn on_ref_deposit_withdrawn(
+        &mut self,
+        #[callback_result] balance: Result<U128, PromiseError>,
+        token_id: AccountId,
+        balance_before: U128,
+    ) {
+        let amount = match balance {
+            Ok(balance) => balance.0.saturating_sub(balance_before.0),
+            Err(_) => {
+                self.internal_record_error("ref_deposits", "balance_read_failed", 0);
+                return;
+            }
+        };
+        if amount == 0 {
+            return;
+        }
+        if token_id == self.wrap_near_contract_id {
+            let unswapped = std::cmp::min(amount, self.near_swapped);
+            self.near_swapped -= unswapped;
+            self.wrapped_amount += unswapped;
+            self.available_rewards += unswapped;
+        } else if token_id == self.reward_token_id {
+            self.internal_hold_back_reward_tokens(amount, "reclaimed_from_ref");
+        }
+        Event::RefDepositReclaimed {
+            token_id,
+            amount: amount.into(),
+        }
+        .emit();
+    }
+}
@@ -0,0 +1,87 @@
+use super::*;
+
+const REF_STORAGE_READ_GAS: Gas = Gas(5_000_000_000_000);
+const REF_STORAGE_DEPOSIT_GAS: Gas = Gas(10_000_000_000_000);
+const ON_REF_STORAGE_CHECKED_GAS: Gas = Gas(5_000_000_000_000);
+
+/// NEP-145 storage balance.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StorageBalance {
+    pub total: U128,
+    pub available: U128,
+}
+
+#[ext_contract(ext_ref_storage)]
+pub trait RefStorage {
+    fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance>;
+}
+
+#[ext_contract(ext_self_ref_storage)]
+pub trait ExtRefStorage {
+    fn on_ref_storage_checked(&mut self, #[callback] storage_balance: Option<StorageBalance>);
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Deposits `amount` from the contract balance for its storage on REF, which grows with
+    /// every token held there, then checks the registration.
+    pub fn ref_storage_deposit(&mut self, amount: U128) -> Promise {
+        self.assert_owner();
+        require!(amount.0 > 0, "Amount must be positive");
+        ext_storage_management::storage_deposit(
+            Some(env::current_account_id()),
+            Some(false),
+            self.ref_finance_contract_id.clone(),
+            amount.0,
+            REF_STORAGE_DEPOSIT_GAS,
+        )
+        .then(self.internal_check_ref_storage())
+    }
+
+    /// Reads the storage balance of the contract on REF and records whether it's registered.
+    pub fn check_ref_storage(&mut self) -> Promise {
+        self.assert_operator();
+        self.internal_check_ref_storage()
+    }
+
+    #[private]
+    pub fn on_ref_storage_checked(
+        &mut self,
+        #[callback] storage_balance: Option<StorageBalance>,
+    ) -> Option<StorageBalance> {
+        self.ref_storage_registered = storage_balance.is_some();
+        Event::RefStorageChecked {
+            registered: self.ref_storage_registered,
+            available: storage_balance
+                .as_ref()
+                .map_or(U128(0), |storage_balance| storage_balance.available),
+        }
+        .emit();
+        storage_balance
+    }
+}
+
+impl Contract {
+    fn internal_check_ref_storage(&self) -> Promise {
+        ext_ref_storage::storage_balance_of(
+            env::current_account_id(),
+            self.ref_finance_contract_id.clone(),
+            NO_DEPOSIT,
+            REF_STORAGE_READ_GAS,
+        )
+        .then(ext_self_ref_storage::on_ref_storage_checked(
+            env::current_account_id(),
+            NO_DEPOSIT,
+            ON_REF_STORAGE_CHECKED_GAS,
+        ))
+    }
+
+    /// REF refunds transfers of unregistered accounts, which looks like a slippage failure.
+    pub fn assert_ref_storage_registered(&self) {
+        require!(
+            self.ref_storage_registered,
+            "The contract isn't registered with REF, see check_ref_storage"
+        );
+    }
+}
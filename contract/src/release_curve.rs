@@ -0,0 +1,143 @@
+use super::*;
+
+/// Halvings past this leave nothing of any balance.
+const MAX_HALVINGS: u64 = 128;
+
+/// How `available_rewards` is released over the time since the last distribution.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum ReleaseCurve {
+    /// Releases everything linearly over `full_rewards_duration`.
+    Linear,
+    /// Releases half of the rewards every `half_life_sec`, interpolated linearly between
+    /// half-lives. Smooths out the dump after a downtime.
+    ExponentialDecay { half_life_sec: DurationSec },
+    /// Releases `amount` per whole `period_sec`.
+    FixedTranche {
+        amount: U128,
+        period_sec: DurationSec,
+    },
+}
+
+impl ReleaseCurve {
+    pub fn assert_valid(&self) {
+        match self {
+            ReleaseCurve::Linear => {}
+            ReleaseCurve::ExponentialDecay { half_life_sec } => {
+                require!(*half_life_sec > 0, "Half-life must be positive")
+            }
+            ReleaseCurve::FixedTranche { amount, period_sec } => require!(
+                amount.0 > 0 && *period_sec > 0,
+                "Tranche amount and period must be positive"
+            ),
+        }
+    }
+
+    /// Part of `available_rewards` released after `time_diff`.
+    pub fn released(
+        &self,
+        available_rewards: Balance,
+        time_diff: Duration,
+        full_rewards_duration: Duration,
+    ) -> Balance {
+        match self {
+            ReleaseCurve::Linear => {
+                if time_diff >= full_rewards_duration {
+                    available_rewards
+                } else {
+                    u128_ratio(
+                        available_rewards,
+                        time_diff as u128,
+                        full_rewards_duration as u128,
+                    )
+                }
+            }
+            ReleaseCurve::ExponentialDecay { half_life_sec } => {
+                let half_life = u64::from(*half_life_sec) * 10u64.pow(9);
+                let halvings = time_diff / half_life;
+                if halvings >= MAX_HALVINGS {
+                    return available_rewards;
+                }
+                let remaining = available_rewards >> halvings;
+                // Halving of what's left, spread linearly over the current half-life.
+                let partial = u128_ratio(
+                    remaining / 2,
+                    (time_diff % half_life) as u128,
+                    half_life as u128,
+                );
+                available_rewards - remaining + partial
+            }
+            ReleaseCurve::FixedTranche { amount, period_sec } => {
+                let periods = time_diff / (u64::from(*period_sec) * 10u64.pow(9));
+                std::cmp::min(amount.0.saturating_mul(periods as u128), available_rewards)
+            }
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn set_release_curve(&mut self, release_curve: ReleaseCurve) {
+        self.assert_owner_or_timelock();
+        release_curve.assert_valid();
+        self.release_curve = release_curve;
+        Event::ConfigChanged {
+            field: "release_curve",
+        }
+        .emit();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ONE_SEC: Duration = 10u64.pow(9);
+
+    #[test]
+    fn test_linear() {
+        let curve = ReleaseCurve::Linear;
+        assert_eq!(curve.released(1000, 25, 100), 250);
+        assert_eq!(curve.released(1000, 100, 100), 1000);
+        assert_eq!(curve.released(1000, 200, 100), 1000);
+    }
+
+    #[test]
+    fn test_exponential_decay() {
+        let curve = ReleaseCurve::ExponentialDecay { half_life_sec: 1 };
+        assert_eq!(curve.released(1000, 0, 0), 0);
+        assert_eq!(curve.released(1000, ONE_SEC, 0), 500);
+        // Half of the remaining half is spread over the second half-life.
+        assert_eq!(curve.released(1000, 3 * ONE_SEC / 2, 0), 625);
+        assert_eq!(curve.released(1000, 2 * ONE_SEC, 0), 750);
+        assert_eq!(curve.released(1000, MAX_HALVINGS * ONE_SEC, 0), 1000);
+        assert_eq!(curve.released(1000, u64::MAX, 0), 1000);
+    }
+
+    #[test]
+    fn test_fixed_tranche() {
+        let curve = ReleaseCurve::FixedTranche {
+            amount: U128(100),
+            period_sec: 1,
+        };
+        assert_eq!(curve.released(1000, ONE_SEC - 1, 0), 0);
+        assert_eq!(curve.released(1000, 5 * ONE_SEC / 2, 0), 200);
+        assert_eq!(curve.released(150, 5 * ONE_SEC, 0), 150);
+    }
+
+    #[test]
+    #[should_panic(expected = "Half-life must be positive")]
+    fn test_zero_half_life() {
+        ReleaseCurve::ExponentialDecay { half_life_sec: 0 }.assert_valid();
+    }
+
+    #[test]
+    #[should_panic(expected = "Tranche amount and period must be positive")]
+    fn test_zero_tranche() {
+        ReleaseCurve::FixedTranche {
+            amount: U128(0),
+            period_sec: 1,
+        }
+        .assert_valid();
+    }
+}
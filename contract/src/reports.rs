@@ -0,0 +1,313 @@
+use super::*;
+use near_sdk::json_types::I128;
+
+const ON_RECONCILE_BALANCE_GAS: Gas = Gas(10_000_000_000_000);
+
+/// Where every NEAR of reward that reached the contract went.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ConservationReport {
+    // Inflows
+    pub withdrawn: U128,
+    pub donated: U128,
+    /// Interest on the wNEAR supplied to Burrow.
+    pub burrow_interest: U128,
+    // Outflows
+    pub swapped: U128,
+    pub paid_out: U128,
+    pub wnear_farmed: U128,
+    pub owner_fees: U128,
+    pub keeper_rewards: U128,
+    pub restaked: U128,
+    // Holdings
+    pub available: U128,
+    pub queued_for_swap: U128,
+    /// Donations not vested yet.
+    pub vesting: U128,
+    /// Sent to swaps that haven't called back yet.
+    pub swapping: U128,
+    /// Excess NEAR swept by the owner, taken from outside the reward accounting.
+    pub excess_swept: U128,
+    /// Inflows minus outflows and holdings, 0 when everything is accounted for.
+    pub unexplained: I128,
+}
+
+/// The wNEAR balance checked against the wNEAR the contract tracks.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Reconciliation {
+    pub wnear_balance: U128,
+    pub wrapped_amount: U128,
+    /// Extra yoctoNEAR wrapped to cover rounding, left on the balance after the swaps.
+    pub rounding_adjustments: U128,
+    /// Balance minus the buffer and the rounding adjustments, 0 when everything is
+    /// accounted for.
+    pub unexplained: I128,
+}
+
+#[ext_contract(ext_self_reports)]
+pub trait ExtReports {
+    fn on_reconcile_balance(
+        &mut self,
+        #[callback_result] balance: Result<U128, PromiseError>,
+    ) -> Reconciliation;
+}
+
+/// Lifetime totals of the contract.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Stats {
+    /// NEAR rewards withdrawn from the staking pool.
+    pub near_withdrawn: U128,
+    pub near_swapped: U128,
+    pub reward_tokens_swapped: U128,
+    /// Reward tokens added to the farm.
+    pub reward_tokens_distributed: U128,
+    pub swaps_executed: u64,
+    /// Average reward tokens received per 1 NEAR, `None` before the first swap.
+    pub average_reward_per_near: Option<U128>,
+    /// Total stake of the staking pool as of the last ping.
+    pub pool_total_stake: U128,
+    /// Farm APR contribution estimated from the last 30 days, `None` until the pool stake
+    /// is known.
+    pub apr_estimate_bps: Option<u32>,
+}
+
+/// Storage used by the contract and how much more the balance can pay for.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StorageReport {
+    pub storage_usage: U64,
+    /// NEAR locked for `storage_usage`.
+    pub storage_cost: U128,
+    /// Bytes taken by each collection.
+    pub distributions_bytes: U64,
+    pub pool_snapshots_bytes: U64,
+    pub operators_bytes: U64,
+    pub donations_bytes: U64,
+    pub errors_bytes: U64,
+    /// NEAR not reserved for rewards, fees or storage, and the bytes it could pay for.
+    pub headroom: U128,
+    pub headroom_bytes: U64,
+}
+
+/// State to alert on, in one call.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Health {
+    /// `None` before the first distribution.
+    pub sec_since_last_distribution: Option<u64>,
+    /// `None` before the first withdraw from the staking pool.
+    pub sec_since_last_withdraw: Option<u64>,
+    /// wNEAR left on the balance, e.g. after failed swaps.
+    pub wrapped_amount: U128,
+    pub pipeline_stage: PipelineStage,
+    /// A swap or farm deposit hasn't called back yet.
+    pub in_flight: bool,
+    pub paused: bool,
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_health(&self) -> Health {
+        let sec_since =
+            |timestamp: Timestamp| env::block_timestamp().saturating_sub(timestamp) / 10u64.pow(9);
+        Health {
+            sec_since_last_distribution: self.internal_last_distribution_timestamp().map(sec_since),
+            sec_since_last_withdraw: Some(self.last_withdraw)
+                .filter(|timestamp| *timestamp > 0)
+                .map(sec_since),
+            wrapped_amount: self.wrapped_amount.into(),
+            pipeline_stage: self.pipeline_stage,
+            in_flight: self.distribution_in_flight
+                || self.output_swaps_in_flight > 0
+                || matches!(
+                    self.pipeline_stage,
+                    PipelineStage::SwapInFlight | PipelineStage::Distributing
+                ),
+            paused: self.paused,
+        }
+    }
+
+    pub fn get_conservation_report(&self) -> ConservationReport {
+        let owner_fees = self.owner_fees_accrued + self.owner_fees_claimed;
+        let outflows = self.near_swapped
+            + self.near_paid_out
+            + self.wnear_distributed
+            + owner_fees
+            + self.keeper_rewards_paid
+            + self.near_restaked;
+        let holdings = self.available_rewards
+            + self.queued_swap_amount
+            + self.vesting_amount
+            + self.near_swapping;
+        let inflows = self.rewards_received + self.burrow_interest_earned;
+        let unexplained = inflows as i128 - outflows as i128 - holdings as i128;
+        ConservationReport {
+            withdrawn: (self.rewards_received - self.near_donated).into(),
+            donated: self.near_donated.into(),
+            burrow_interest: self.burrow_interest_earned.into(),
+            swapped: self.near_swapped.into(),
+            paid_out: self.near_paid_out.into(),
+            wnear_farmed: self.wnear_distributed.into(),
+            owner_fees: owner_fees.into(),
+            keeper_rewards: self.keeper_rewards_paid.into(),
+            restaked: self.near_restaked.into(),
+            available: self.available_rewards.into(),
+            queued_for_swap: self.queued_swap_amount.into(),
+            vesting: self.vesting_amount.into(),
+            swapping: self.near_swapping.into(),
+            excess_swept: self.near_swept.into(),
+            unexplained: unexplained.into(),
+        }
+    }
+
+    /// Reads the wNEAR balance and nets out the rounding adjustments, which aren't part of
+    /// the wNEAR buffer. Needs no swap or Burrow withdrawal to be in flight.
+    pub fn reconcile(&mut self) -> Promise {
+        require!(
+            !self.distribution_in_flight
+                && self.output_swaps_in_flight == 0
+                && !self.burrow_pending,
+            "A distribution or Burrow withdrawal is in flight"
+        );
+        ext_fungible_token::ft_balance_of(
+            env::current_account_id(),
+            self.wrap_near_contract_id.clone(),
+            NO_DEPOSIT,
+            FT_BALANCE_OF_GAS,
+        )
+        .then(ext_self_reports::on_reconcile_balance(
+            env::current_account_id(),
+            NO_DEPOSIT,
+            ON_RECONCILE_BALANCE_GAS,
+        ))
+    }
+
+    #[private]
+    pub fn on_reconcile_balance(
+        &mut self,
+        #[callback_result] balance: Result<U128, PromiseError>,
+    ) -> Reconciliation {
+        let wnear_balance = balance.expect("Failed to read the wNEAR balance").0;
+        let tracked = self.wrapped_amount + self.rounding_adjustments;
+        let reconciliation = Reconciliation {
+            wnear_balance: wnear_balance.into(),
+            wrapped_amount: self.wrapped_amount.into(),
+            rounding_adjustments: self.rounding_adjustments.into(),
+            unexplained: (wnear_balance as i128 - tracked as i128).into(),
+        };
+        Event::Reconciled {
+            wnear_balance: reconciliation.wnear_balance,
+            unexplained: reconciliation.unexplained,
+        }
+        .emit();
+        reconciliation
+    }
+
+    pub fn get_stats(&self) -> Stats {
+        Stats {
+            near_withdrawn: (self.rewards_received - self.near_donated).into(),
+            near_swapped: self.near_swapped.into(),
+            reward_tokens_swapped: self.reward_tokens_swapped.into(),
+            reward_tokens_distributed: self.reward_tokens_distributed.into(),
+            swaps_executed: self.swaps_executed,
+            average_reward_per_near: if self.near_swapped > 0 {
+                Some(u128_ratio(self.reward_tokens_swapped, ONE_NEAR, self.near_swapped).into())
+            } else {
+                None
+            },
+            pool_total_stake: self.pool_total_stake.into(),
+            apr_estimate_bps: self.internal_apr_estimate_bps(),
+        }
+    }
+
+    pub fn get_storage_report(&self) -> StorageReport {
+        let storage_usage = env::storage_usage();
+        let headroom = self.get_excess_near().0;
+        StorageReport {
+            storage_usage: storage_usage.into(),
+            storage_cost: (Balance::from(storage_usage) * env::storage_byte_cost()).into(),
+            distributions_bytes: self.distributions_storage_bytes.into(),
+            pool_snapshots_bytes: self.pool_snapshots_storage_bytes.into(),
+            operators_bytes: self.operators_storage_bytes.into(),
+            donations_bytes: self.donations_storage_bytes.into(),
+            errors_bytes: self.errors_storage_bytes.into(),
+            headroom: headroom.into(),
+            headroom_bytes: ((headroom / env::storage_byte_cost()) as StorageUsage).into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    const ONE_NEAR: Balance = 10u128.pow(24);
+
+    fn account(account_id: &str) -> AccountId {
+        account_id.parse().unwrap()
+    }
+
+    fn contract() -> Contract {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id(account("contract.near"))
+            .predecessor_account_id(accounts(0))
+            .account_balance(1000 * ONE_NEAR)
+            .block_index(1)
+            .build());
+        Contract::new(
+            account("pool.near"),
+            accounts(0),
+            account("token.near"),
+            0,
+            account("oracle.near"),
+            account("ref.near"),
+            account("wrap.near"),
+            vec![Action {
+                pool_id: 0,
+                token_in: account("wrap.near"),
+                token_out: account("token.near"),
+                min_amount_out: U128(0),
+            }],
+            U128(100 * ONE_NEAR),
+        )
+    }
+
+    #[test]
+    fn test_conservation_report() {
+        let mut contract = contract();
+        contract.owner_fee_bps = 1000;
+        contract.keeper_reward_bps = 100;
+        contract.max_keeper_reward = ONE_NEAR / 2;
+        contract.restake_bps = 2000;
+        contract.internal_receive_rewards(100 * ONE_NEAR, accounts(1));
+
+        let report = contract.get_conservation_report();
+        assert_eq!(report.withdrawn.0, 100 * ONE_NEAR);
+        assert_eq!(report.owner_fees.0, 10 * ONE_NEAR);
+        assert_eq!(report.keeper_rewards.0, ONE_NEAR / 2);
+        assert_eq!(report.restaked.0, 17_900 * ONE_NEAR / 1000);
+        assert_eq!(report.available.0, 71_600 * ONE_NEAR / 1000);
+        assert_eq!(report.unexplained.0, 0);
+    }
+
+    #[test]
+    fn test_conservation_report_unexplained() {
+        let mut contract = contract();
+        contract.internal_receive_rewards(10 * ONE_NEAR, accounts(1));
+        contract.available_rewards -= ONE_NEAR;
+        assert_eq!(
+            contract.get_conservation_report().unexplained.0,
+            ONE_NEAR as i128
+        );
+
+        contract.near_swapping += 2 * ONE_NEAR;
+        assert_eq!(
+            contract.get_conservation_report().unexplained.0,
+            -(ONE_NEAR as i128)
+        );
+    }
+}
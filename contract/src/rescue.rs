@@ -0,0 +1,111 @@
+use super::*;
+
+const FT_TRANSFER_GAS: Gas = Gas(10_000_000_000_000);
+const ON_FT_RESCUED_GAS: Gas = Gas(5_000_000_000_000);
+const ON_NEAR_SWEPT_GAS: Gas = Gas(5_000_000_000_000);
+
+#[ext_contract(ext_self_rescue)]
+pub trait ExtRescue {
+    fn on_ft_rescued(&mut self, token_id: AccountId, receiver_id: AccountId, amount: U128);
+    fn on_near_swept(&mut self, receiver_id: AccountId, amount: U128);
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sends tokens that were airdropped or sent by mistake to `receiver_id`.
+    /// Tokens accounted for by the contract can't be rescued.
+    pub fn rescue_ft(
+        &mut self,
+        token_id: AccountId,
+        receiver_id: AccountId,
+        amount: U128,
+    ) -> Promise {
+        self.assert_owner();
+        require!(
+            token_id != self.reward_token_id
+                && token_id != self.wrap_near_contract_id
+                && self
+                    .buyback
+                    .as_ref()
+                    .map_or(true, |buyback| buyback.token_id != token_id)
+                && self
+                    .liquid_staking_source
+                    .as_ref()
+                    .map_or(true, |source| source.token_id != token_id)
+                && self
+                    .output_tokens
+                    .iter()
+                    .all(|output| output.token_id != token_id),
+            "Token is accounted for by the contract"
+        );
+        ext_fungible_token::ft_transfer(
+            receiver_id.clone(),
+            amount,
+            Some("Rescued tokens".to_string()),
+            token_id.clone(),
+            ONE_YOCTO,
+            FT_TRANSFER_GAS,
+        )
+        .then(ext_self_rescue::on_ft_rescued(
+            token_id,
+            receiver_id,
+            amount,
+            env::current_account_id(),
+            NO_DEPOSIT,
+            ON_FT_RESCUED_GAS,
+        ))
+    }
+
+    #[private]
+    pub fn on_ft_rescued(&mut self, token_id: AccountId, receiver_id: AccountId, amount: U128) {
+        require!(is_promise_success(), "Rescue transfer failed");
+        Event::FtRescued {
+            token_id,
+            receiver_id,
+            amount,
+        }
+        .emit();
+    }
+
+    /// NEAR on the balance that isn't needed for rewards, fees or storage.
+    pub fn get_excess_near(&self) -> U128 {
+        let reserved = self.available_rewards
+            + self.queued_swap_amount
+            + self.vesting_amount
+            + self.owner_fees_accrued
+            + Balance::from(env::storage_usage()) * env::storage_byte_cost();
+        env::account_balance().saturating_sub(reserved).into()
+    }
+
+    /// Sends the excess NEAR, e.g. refunded deposits, to `receiver_id` or the owner.
+    pub fn sweep_excess_near(&mut self, receiver_id: Option<AccountId>) -> Promise {
+        self.assert_owner();
+        let amount = self.get_excess_near().0;
+        require!(amount > 0, "No excess NEAR");
+        let receiver_id = receiver_id.unwrap_or_else(|| self.owner_id.clone());
+        self.near_swept += amount;
+        Promise::new(receiver_id.clone())
+            .transfer(amount)
+            .then(ext_self_rescue::on_near_swept(
+                receiver_id,
+                amount.into(),
+                env::current_account_id(),
+                NO_DEPOSIT,
+                ON_NEAR_SWEPT_GAS,
+            ))
+    }
+
+    #[private]
+    pub fn on_near_swept(&mut self, receiver_id: AccountId, amount: U128) {
+        if is_promise_success() {
+            Event::NearSwept {
+                receiver_id,
+                amount,
+            }
+            .emit();
+        } else {
+            // The transfer is refunded to the contract.
+            self.near_swept -= amount.0;
+        }
+    }
+}
@@ -0,0 +1,295 @@
+use super::*;
+use near_sdk::PromiseResult;
+
+const SETUP_READ_GAS: Gas = Gas(5_000_000_000_000);
+const ON_SETUP_CHECKED_GAS: Gas = Gas(10_000_000_000_000);
+/// Oracle data younger than this can be rejected just because of block time variance.
+const MIN_SAFE_ORACLE_STALENESS: Duration = 5_000_000_000;
+const STORAGE_DEPOSIT_GAS: Gas = Gas(10_000_000_000_000);
+const ON_STORAGE_REGISTERED_GAS: Gas = Gas(10_000_000_000_000);
+/// Attached to each `storage_deposit`, the part above the minimum is refunded.
+const STORAGE_DEPOSIT_AMOUNT: Balance = ONE_NEAR / 10;
+
+#[ext_contract(ext_ft_metadata)]
+pub trait FungibleTokenMetadataProvider {
+    fn ft_metadata(&self);
+}
+
+/// An account that has to be registered with a token or REF.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StorageRegistration {
+    pub contract_id: AccountId,
+    pub account_id: AccountId,
+}
+
+#[ext_contract(ext_storage_management)]
+pub trait StorageManagement {
+    fn storage_deposit(&mut self, account_id: Option<AccountId>, registration_only: Option<bool>);
+}
+
+#[ext_contract(ext_self_setup)]
+pub trait ExtSetup {
+    fn on_setup_checked(&mut self);
+    fn on_storage_registered(&mut self, registrations: Vec<StorageRegistration>);
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Verifies that the staking pool, the reward token and every pool of the swap path
+    /// respond as expected, and that the staking pool is whitelisted if there is a whitelist.
+    /// Distributions stay disabled until this succeeds.
+    pub fn finalize_setup(&mut self) -> Promise {
+        self.assert_owner();
+        let mut checks = ext_staking_pool::get_account(
+            env::current_account_id(),
+            self.staking_pool_account_id.clone(),
+            NO_DEPOSIT,
+            SETUP_READ_GAS,
+        )
+        .and(ext_ft_metadata::ft_metadata(
+            self.reward_token_id.clone(),
+            NO_DEPOSIT,
+            SETUP_READ_GAS,
+        ));
+        for hop_index in 0..self.swap_path.len() {
+            checks = checks.and(self.internal_dex_get_pool(0, hop_index, SETUP_READ_GAS));
+        }
+        if let Some(whitelist_id) = &self.staking_pool_whitelist_id {
+            checks = checks.and(ext_staking_pool_whitelist::is_whitelisted(
+                self.staking_pool_account_id.clone(),
+                whitelist_id.clone(),
+                NO_DEPOSIT,
+                SETUP_READ_GAS,
+            ));
+        }
+        checks.then(ext_self_setup::on_setup_checked(
+            env::current_account_id(),
+            NO_DEPOSIT,
+            ON_SETUP_CHECKED_GAS,
+        ))
+    }
+
+    #[private]
+    pub fn on_setup_checked(&mut self) {
+        let result = |index: u64| match env::promise_result(index) {
+            PromiseResult::Successful(value) => value,
+            _ => env::panic_str(&format!("Setup check #{} failed", index)),
+        };
+        serde_json::from_slice::<StakingPoolAccount>(&result(0))
+            .expect("Staking pool doesn't expose get_account");
+        result(1);
+        for (index, action) in self.swap_path.iter().enumerate() {
+            let pool: DexPoolInfo =
+                serde_json::from_slice(&result(index as u64 + 2)).expect("Pool is not found");
+            require!(
+                pool.trades(action),
+                format!("Pool of hop {} doesn't trade the configured tokens", index)
+            );
+        }
+        if self.staking_pool_whitelist_id.is_some() {
+            let whitelisted: bool =
+                serde_json::from_slice(&result(self.swap_path.len() as u64 + 2))
+                    .expect("Whitelist doesn't expose is_whitelisted");
+            require!(whitelisted, "Staking pool is not whitelisted");
+        }
+        self.setup_complete = true;
+        Event::ConfigChanged {
+            field: "setup_complete",
+        }
+        .emit();
+    }
+
+    /// Registers the contract and the staking pool with the tokens and REF they use.
+    /// Done on init and on token changes, can be repeated if a registration failed.
+    pub fn register_storage(&mut self) -> Promise {
+        self.assert_owner();
+        self.internal_register_storage()
+    }
+
+    #[private]
+    pub fn on_storage_registered(&mut self, registrations: Vec<StorageRegistration>) {
+        for (index, registration) in registrations.into_iter().enumerate() {
+            let StorageRegistration {
+                contract_id,
+                account_id,
+            } = registration;
+            match env::promise_result(index as u64) {
+                PromiseResult::Successful(_) => {
+                    if contract_id == self.ref_finance_contract_id
+                        && account_id == env::current_account_id()
+                    {
+                        self.ref_storage_registered = true;
+                    }
+                    Event::StorageRegistered {
+                        contract_id,
+                        account_id,
+                    }
+                    .emit()
+                }
+                _ => Event::StorageRegistrationFailed {
+                    contract_id,
+                    account_id,
+                }
+                .emit(),
+            }
+        }
+    }
+
+    /// Lists configuration that is valid but likely a mistake, for the operator UI.
+    pub fn get_config_warnings(&self) -> Vec<String> {
+        let mut warnings = vec![];
+        if !self.setup_complete {
+            warnings.push("Setup is not finalized, distributions are disabled".to_string());
+        }
+        if self.paused {
+            warnings.push("The contract is paused".to_string());
+        }
+        if !self.ref_storage_registered {
+            warnings.push("The contract isn't registered with REF, swaps will fail".to_string());
+        }
+        if self.farm_duration < self.full_rewards_duration {
+            warnings.push(
+                "Farm duration is shorter than the full rewards duration, farms will have gaps"
+                    .to_string(),
+            );
+        }
+        let swap_paths = std::iter::once(&self.swap_path).chain(&self.fallback_swap_paths);
+        for (index, swap_path) in swap_paths.enumerate() {
+            if swap_path.first().map(|action| &action.token_in) != Some(&self.wrap_near_contract_id)
+                || swap_path.last().map(|action| &action.token_out) != Some(&self.reward_token_id)
+            {
+                warnings.push(format!(
+                    "Swap path #{} doesn't lead from wNEAR to the reward token",
+                    index
+                ));
+            }
+        }
+        if self.oracle_max_staleness < MIN_SAFE_ORACLE_STALENESS {
+            warnings.push(
+                "Oracle staleness limit is within block time variance, oracle calls may fail"
+                    .to_string(),
+            );
+        }
+        if self.max_price_deviation_bps > 0
+            && self.max_price_deviation_bps < self.internal_slippage_bps()
+        {
+            warnings.push(
+                "Price deviation limit is tighter than the swap slippage, swaps may be refused"
+                    .to_string(),
+            );
+        }
+        if self.max_near_reward == 0 {
+            warnings.push("Max NEAR reward is zero, nothing will be distributed".to_string());
+        }
+        if self.swap_fraction_bps == 0 {
+            warnings
+                .push("Swap fraction is zero, no reward tokens will be distributed".to_string());
+        }
+        if self.min_wrap_amount > self.max_near_reward {
+            warnings.push(
+                "Minimum wrap amount is above the max NEAR reward, swaps will be postponed"
+                    .to_string(),
+            );
+        }
+        if self.require_route_prices && matches!(self.price_source, PriceSource::Pyth(_)) {
+            let swap_path = &self.swap_path;
+            let unpriced = swap_path[..swap_path.len() - 1].iter().any(|action| {
+                !self
+                    .pyth_intermediate_feeds
+                    .iter()
+                    .any(|feed| feed.token_id == action.token_out)
+            });
+            if unpriced {
+                warnings.push(
+                    "Swap path goes through a token without a Pyth feed, swaps will be refused"
+                        .to_string(),
+                );
+            }
+        }
+        warnings
+    }
+}
+
+impl Contract {
+    pub fn internal_register_storage(&self) -> Promise {
+        let current_account_id = env::current_account_id();
+        let mut registrations = vec![
+            (self.reward_token_id.clone(), current_account_id.clone()),
+            (
+                self.reward_token_id.clone(),
+                self.staking_pool_account_id.clone(),
+            ),
+            (
+                self.wrap_near_contract_id.clone(),
+                current_account_id.clone(),
+            ),
+            (self.ref_finance_contract_id.clone(), current_account_id),
+        ];
+        for dex_contract_id in self.internal_extra_dex_contract_ids() {
+            registrations.push((dex_contract_id, env::current_account_id()));
+        }
+        if self.wnear_farm_id.is_some() || self.direct_wnear_farm_id.is_some() {
+            registrations.push((
+                self.wrap_near_contract_id.clone(),
+                self.staking_pool_account_id.clone(),
+            ));
+        }
+        for split in &self.farm_splits {
+            registrations.push((
+                self.reward_token_id.clone(),
+                split.config.contract_id.clone(),
+            ));
+        }
+        for output in &self.output_tokens {
+            if output.token_id != self.wrap_near_contract_id {
+                registrations.push((output.token_id.clone(), env::current_account_id()));
+            }
+            registrations.push((
+                output.token_id.clone(),
+                self.staking_pool_account_id.clone(),
+            ));
+        }
+        if let Some(burrow_contract_id) = &self.burrow_contract_id {
+            registrations.push((burrow_contract_id.clone(), env::current_account_id()));
+        }
+        if let Some(treasury_account_id) = &self.treasury_account_id {
+            registrations.push((self.reward_token_id.clone(), treasury_account_id.clone()));
+        }
+        if let Some(buyback) = &self.buyback {
+            registrations.push((buyback.token_id.clone(), env::current_account_id()));
+            if let BurnTarget::Account(receiver_id) = &buyback.burn_target {
+                registrations.push((buyback.token_id.clone(), receiver_id.clone()));
+            }
+        }
+        let promise = registrations
+            .iter()
+            .map(|(contract_id, account_id)| {
+                ext_storage_management::storage_deposit(
+                    Some(account_id.clone()),
+                    Some(true),
+                    contract_id.clone(),
+                    STORAGE_DEPOSIT_AMOUNT,
+                    STORAGE_DEPOSIT_GAS,
+                )
+            })
+            .reduce(|promise, deposit| promise.and(deposit))
+            .unwrap();
+        promise.then(ext_self_setup::on_storage_registered(
+            registrations
+                .into_iter()
+                .map(|(contract_id, account_id)| StorageRegistration {
+                    contract_id,
+                    account_id,
+                })
+                .collect(),
+            env::current_account_id(),
+            NO_DEPOSIT,
+            ON_STORAGE_REGISTERED_GAS,
+        ))
+    }
+
+    pub fn assert_setup_complete(&self) {
+        require!(self.setup_complete, "Setup is not finalized");
+    }
+}
@@ -0,0 +1,66 @@
+use super::*;
+
+/// What the next distribution would do, computed from the current state.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DistributionSimulation {
+    /// NEAR reward due now, before the payout and swap split.
+    pub near_reward: U128,
+    /// Share of `near_reward` paid out as NEAR.
+    pub near_payout: U128,
+    /// NEAR swapped, including queued chunks of earlier rewards.
+    pub swap_amount: U128,
+    /// NEAR deposited for wNEAR on top of the wNEAR buffer.
+    pub wrap_amount: U128,
+    /// The wrap amount is below `min_wrap_amount` and the distribution would be postponed.
+    pub wrap_postponed: bool,
+    /// Swap output at the given prices and the minimum accepted, `None` without prices.
+    pub expected_amount_out: Option<U128>,
+    pub min_amount_out: Option<U128>,
+    pub distribution_mode: DistributionMode,
+    pub farm_id: u64,
+    pub farm_end_date: U64,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Simulates the next price triggered distribution without executing it.
+    /// The minimum output is only derived when both prices are given.
+    pub fn simulate_distribution(
+        &self,
+        wnear_price: Option<Price>,
+        reward_price: Option<Price>,
+    ) -> DistributionSimulation {
+        let step = self.internal_distribution_step();
+        let swap_amount = step.swap_amount;
+        let wrap_amount = wrap_amount(swap_amount, self.wrapped_amount);
+        let expected_amount_out = match (wnear_price, reward_price) {
+            (Some(wnear_price), Some(reward_price)) if swap_amount > 0 => {
+                wnear_price.assert_valid();
+                reward_price.assert_valid();
+                Some(oracle_amount_out(swap_amount, &wnear_price, &reward_price))
+            }
+            _ => None,
+        };
+        let farm_duration = self.next_farm_duration.unwrap_or(self.farm_duration);
+        let farm_start_date = self
+            .next_farm_start_date
+            .unwrap_or_else(env::block_timestamp);
+        DistributionSimulation {
+            near_reward: step.released.into(),
+            near_payout: step.near_payout.into(),
+            swap_amount: swap_amount.into(),
+            wrap_amount: wrap_amount.into(),
+            wrap_postponed: wrap_amount > 0 && wrap_amount < self.min_wrap_amount,
+            expected_amount_out: expected_amount_out.map(U128),
+            // Slippage as applied to oracle priced swaps
+            min_amount_out: expected_amount_out
+                .map(|expected_amount_out| U128(self.internal_apply_slippage(expected_amount_out))),
+            distribution_mode: self
+                .next_distribution_mode
+                .unwrap_or(self.distribution_mode),
+            farm_id: self.farm_id,
+            farm_end_date: (farm_start_date + farm_duration).into(),
+        }
+    }
+}
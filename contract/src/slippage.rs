@@ -0,0 +1,130 @@
+use super::*;
+
+/// Slippage allowed on oracle priced swaps before any widening.
+pub const BASE_SLIPPAGE_BPS: u32 = 100;
+const MAX_SLIPPAGE_BPS: u32 = 2_000;
+
+/// What happens after `SlippagePolicy::max_failures` consecutive slippage failures.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum SlippageEscalation {
+    /// Moves to the next swap path.
+    SwitchPath,
+    /// Adds `step_bps` to the slippage of oracle priced swaps, up to `max_bps` in total.
+    /// The slippage is back to the base one after a successful swap.
+    WidenSlippage { step_bps: u32, max_bps: u32 },
+    /// Pauses the contract for the owner to look into it.
+    Pause,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SlippagePolicy {
+    pub max_failures: u32,
+    pub escalation: SlippageEscalation,
+}
+
+impl Default for SlippagePolicy {
+    fn default() -> Self {
+        Self {
+            max_failures: 1,
+            escalation: SlippageEscalation::SwitchPath,
+        }
+    }
+}
+
+impl SlippagePolicy {
+    pub fn assert_valid(&self) {
+        require!(self.max_failures > 0, "Max failures must be positive");
+        if let SlippageEscalation::WidenSlippage { step_bps, max_bps } = self.escalation {
+            require!(
+                step_bps > 0 && max_bps > BASE_SLIPPAGE_BPS && max_bps <= MAX_SLIPPAGE_BPS,
+                "Invalid slippage widening"
+            );
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn set_slippage_policy(&mut self, slippage_policy: SlippagePolicy) {
+        self.assert_owner_or_timelock();
+        slippage_policy.assert_valid();
+        self.slippage_policy = slippage_policy;
+        self.consecutive_slippage_failures = 0;
+        self.extra_slippage_bps = 0;
+        Event::ConfigChanged {
+            field: "slippage_policy",
+        }
+        .emit();
+    }
+
+    pub fn get_slippage_policy(&self) -> SlippagePolicy {
+        self.slippage_policy
+    }
+
+    /// Slippage applied to oracle priced swaps now.
+    pub fn get_slippage_bps(&self) -> u32 {
+        self.internal_slippage_bps()
+    }
+}
+
+impl Contract {
+    pub fn internal_slippage_bps(&self) -> u32 {
+        BASE_SLIPPAGE_BPS + self.extra_slippage_bps
+    }
+
+    /// Minimum output of `amount` at the current slippage.
+    pub fn internal_apply_slippage(&self, amount: Balance) -> Balance {
+        u128_ratio(
+            amount,
+            (MAX_BPS - self.internal_slippage_bps()) as u128,
+            MAX_BPS as u128,
+        )
+    }
+
+    /// Minimum output of `amount` NEAR at the rate of the last oracle prices, or of the last
+    /// swap before any, for swaps that aren't priced by the oracle.
+    pub fn internal_reference_min_amount_out(&self, amount: Balance) -> Balance {
+        let rate = self
+            .last_oracle_rate
+            .or(self.last_swap_rate)
+            .expect("No oracle or swap rate to price the swap");
+        self.internal_apply_slippage(u128_ratio(amount, rate, ONE_NEAR))
+    }
+
+    pub fn internal_on_swap_succeeded(&mut self) {
+        self.consecutive_slippage_failures = 0;
+        self.extra_slippage_bps = 0;
+        self.swap_path_index = 0;
+    }
+
+    /// Escalates once failures reach the limit of the policy.
+    pub fn internal_on_slippage_failure(&mut self) {
+        self.consecutive_slippage_failures += 1;
+        if self.consecutive_slippage_failures < self.slippage_policy.max_failures {
+            return;
+        }
+        Event::SlippageEscalated {
+            failures: self.consecutive_slippage_failures,
+            escalation: self.slippage_policy.escalation,
+        }
+        .emit();
+        self.consecutive_slippage_failures = 0;
+        match self.slippage_policy.escalation {
+            SlippageEscalation::SwitchPath => self.internal_switch_to_next_swap_path(),
+            SlippageEscalation::WidenSlippage { step_bps, max_bps } => {
+                self.extra_slippage_bps = std::cmp::min(
+                    self.extra_slippage_bps + step_bps,
+                    max_bps - BASE_SLIPPAGE_BPS,
+                );
+            }
+            SlippageEscalation::Pause => {
+                if !self.paused {
+                    self.paused = true;
+                    Event::Paused.emit();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use super::*;
+
+const MAX_SPLIT_ROUTES: usize = 4;
+
+/// A route on REF taking `weight_bps` of each swap.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SplitRoute {
+    pub swap_path: Vec<Action>,
+    pub weight_bps: u32,
+}
+
+/// REF action with an explicit input, which lets one transfer run several routes.
+/// Hops without `amount_in` swap the output of the previous hop.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct SplitAction {
+    pool_id: u64,
+    token_in: AccountId,
+    amount_in: Option<U128>,
+    token_out: AccountId,
+    min_amount_out: U128,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct SplitRefArgs {
+    actions: Vec<SplitAction>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    referral_id: Option<AccountId>,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Splits each swap across the given routes by weight to reduce the price impact on
+    /// single pools. Weights add up to 100%, an empty list swaps through the swap path.
+    pub fn set_split_routes(&mut self, split_routes: Vec<SplitRoute>) {
+        self.assert_owner_or_timelock();
+        self.split_routes = split_routes;
+        self.assert_valid_split_routes();
+        Event::ConfigChanged {
+            field: "split_routes",
+        }
+        .emit();
+        self.internal_fetch_token_decimals();
+    }
+
+    pub fn get_split_routes(&self) -> Vec<SplitRoute> {
+        self.split_routes.clone()
+    }
+}
+
+impl Contract {
+    pub fn assert_valid_split_routes(&self) {
+        if self.split_routes.is_empty() {
+            return;
+        }
+        require!(
+            self.split_routes.len() >= 2 && self.split_routes.len() <= MAX_SPLIT_ROUTES,
+            "Expected between 2 and 4 split routes"
+        );
+        require!(
+            self.split_routes.iter().all(|route| route.weight_bps > 0)
+                && self
+                    .split_routes
+                    .iter()
+                    .map(|route| route.weight_bps)
+                    .sum::<u32>()
+                    == MAX_BPS,
+            "Split route weights must be positive and add up to 100%"
+        );
+        for route in &self.split_routes {
+            self.internal_assert_valid_path(&route.swap_path);
+        }
+    }
+
+    /// Receiver and message of the `ft_transfer_call` swapping `reward` across the split
+    /// routes. Each route has to return its share of `min_amount_out`.
+    pub fn internal_split_swap_call(
+        &self,
+        reward: Balance,
+        min_amount_out: Balance,
+    ) -> (AccountId, String) {
+        let mut actions = vec![];
+        let mut remaining = reward;
+        for (index, route) in self.split_routes.iter().enumerate() {
+            // The last route takes the rounding remainder.
+            let amount_in = if index + 1 == self.split_routes.len() {
+                remaining
+            } else {
+                u128_ratio(reward, route.weight_bps as u128, MAX_BPS as u128)
+            };
+            remaining -= amount_in;
+            let route_min_amount_out = u128_ratio(min_amount_out, amount_in, reward);
+            let last_hop_index = route.swap_path.len() - 1;
+            for (hop_index, action) in route.swap_path.iter().enumerate() {
+                actions.push(SplitAction {
+                    pool_id: action.pool_id,
+                    token_in: action.token_in.clone(),
+                    amount_in: if hop_index == 0 {
+                        Some(amount_in.into())
+                    } else {
+                        None
+                    },
+                    token_out: action.token_out.clone(),
+                    min_amount_out: if hop_index == last_hop_index {
+                        route_min_amount_out.into()
+                    } else {
+                        U128(0)
+                    },
+                });
+            }
+        }
+        (
+            self.ref_finance_contract_id.clone(),
+            serde_json::to_string(&SplitRefArgs {
+                actions,
+                referral_id: self.referral_id.clone(),
+            })
+            .unwrap(),
+        )
+    }
+}
@@ -0,0 +1,116 @@
+use super::*;
+use near_sdk::json_types::Base64VecU8;
+use near_sdk::CurveType;
+
+const MAX_QUOTE_AGE_SEC: DurationSec = 600;
+
+/// Route and minimum output computed by an off-chain router for `amount_in` wNEAR.
+/// Actual swaps scale the minimums to the amount being swapped.
+#[derive(BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SwapQuote {
+    /// Path from wNEAR to the reward token. `min_amount_out` of the hops is optional.
+    pub swap_path: Vec<Action>,
+    pub amount_in: U128,
+    pub min_amount_out: U128,
+    /// When the quote was computed, in nanoseconds.
+    pub timestamp: U64,
+    /// Has to increase with every quote, so a quote can't be replayed.
+    pub nonce: u64,
+}
+
+/// A quote with the ed25519 signature of the quote signer over the borsh serialized
+/// `(contract_id, quote)`.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SignedSwapQuote {
+    pub quote: SwapQuote,
+    pub signature: Base64VecU8,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the key routers sign swap quotes with and how long a quote stays valid.
+    /// `None` stops accepting quotes.
+    pub fn set_quote_signer(&mut self, quote_signer: Option<PublicKey>, max_quote_age_sec: u32) {
+        self.assert_owner_or_timelock();
+        if let Some(quote_signer) = &quote_signer {
+            require!(
+                quote_signer.curve_type() == CurveType::ED25519,
+                "Quote signer must be an ed25519 key"
+            );
+        }
+        require!(
+            max_quote_age_sec > 0 && max_quote_age_sec <= MAX_QUOTE_AGE_SEC,
+            "Invalid maximum quote age"
+        );
+        self.quote_signer = quote_signer;
+        self.max_quote_age = max_quote_age_sec as Duration * 10u64.pow(9);
+        Event::ConfigChanged {
+            field: "quote_signer",
+        }
+        .emit();
+    }
+}
+
+impl Contract {
+    /// Checks the signature, age and nonce of a quote and consumes its nonce.
+    pub fn internal_verify_swap_quote(&mut self, signed_quote: SignedSwapQuote) -> SwapQuote {
+        let SignedSwapQuote { quote, signature } = signed_quote;
+        let quote_signer = self
+            .quote_signer
+            .as_ref()
+            .expect("Swap quotes are not accepted");
+        let message = (env::current_account_id(), &quote).try_to_vec().unwrap();
+        require!(
+            verify_ed25519(quote_signer, &message, &signature.0),
+            "Invalid quote signature"
+        );
+        let timestamp = env::block_timestamp();
+        require!(
+            quote.timestamp.0 <= timestamp,
+            "Quote timestamp is in the future"
+        );
+        require!(
+            timestamp - quote.timestamp.0 <= self.max_quote_age,
+            "Quote is too old"
+        );
+        require!(
+            quote.nonce > self.last_quote_nonce,
+            "Quote nonce was already used"
+        );
+        require!(
+            quote.amount_in.0 > 0 && quote.min_amount_out.0 > 0,
+            "Invalid quote amounts"
+        );
+        assert_valid_path_hops(&quote.swap_path);
+        assert_eq!(
+            quote.swap_path.first().unwrap().token_in,
+            self.wrap_near_contract_id
+        );
+        assert_eq!(
+            quote.swap_path.last().unwrap().token_out,
+            self.reward_token_id
+        );
+        self.assert_intermediate_tokens(&quote.swap_path);
+        self.last_quote_nonce = quote.nonce;
+        Event::SwapQuoteAccepted {
+            nonce: quote.nonce,
+            amount_in: quote.amount_in,
+            min_amount_out: quote.min_amount_out,
+        }
+        .emit();
+        quote
+    }
+}
+
+fn verify_ed25519(public_key: &PublicKey, message: &[u8], signature: &[u8]) -> bool {
+    let public_key = match ed25519_dalek::PublicKey::from_bytes(&public_key.as_bytes()[1..]) {
+        Ok(public_key) => public_key,
+        Err(_) => return false,
+    };
+    match ed25519_dalek::Signature::try_from(signature) {
+        Ok(signature) => public_key.verify_strict(message, &signature).is_ok(),
+        Err(_) => false,
+    }
+}
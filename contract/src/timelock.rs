@@ -0,0 +1,184 @@
+use super::*;
+use near_sdk::json_types::Base64VecU8;
+
+/// Methods that only take effect through `propose_change` and `execute_change` while
+/// `timelock_delay` is set, as they can redirect the rewards or lift the limits on swaps.
+const TIMELOCKED_METHODS: &[&str] = &[
+    "set_swap_path",
+    "update_token",
+    "set_oracles",
+    "set_price_source",
+    "set_swap_executor",
+    "set_quote_signer",
+    "set_owner_fee_bps",
+    "set_keeper_reward",
+    "set_treasury_split",
+    "set_timelock_delay",
+    "upgrade",
+    "start_staking_pool_change",
+    "set_staking_pool_whitelist",
+    "set_oracle_contract",
+    "set_ref_finance_contract",
+    "set_wrap_near_contract",
+    "set_swap_path_dex",
+    "set_split_routes",
+    "set_slippage_policy",
+    "set_burrow_contract",
+    "set_liquid_staking_source",
+    "set_output_tokens",
+    "set_oracle_assets",
+    "update_config",
+    "set_fallback_swap_paths",
+    "set_intermediate_tokens",
+    "set_swap_fraction",
+    "set_fallback_to_wnear",
+    "set_referral_id",
+    "set_buyback",
+    "set_distribution_mode",
+    "set_farm_splits",
+    "set_direct_wnear_farm",
+    "add_operator",
+    "set_full_rewards_duration",
+    "set_max_near_reward",
+    "set_release_curve",
+    "set_min_fill_bps",
+    "set_max_price_deviation_bps",
+    "set_oracle_limits",
+    "set_reward_price_band",
+    "set_restake_bps",
+    "set_farm_duration",
+    "set_farm_duration_bounds",
+    "set_accounting_snapshot_interval",
+    "set_min_distribution_interval",
+    "set_max_reward_rate",
+    "set_farm_top_up_window",
+    "set_require_top_up_due",
+    "set_max_swap_chunk",
+    "set_min_wrap_amount",
+    "set_min_swap_amount",
+    "set_max_swap_delay_blocks",
+    "set_require_route_prices",
+    "set_check_pool_pause",
+    "set_pre_swap_quote_tolerance",
+    "set_min_donation",
+    "set_donations_enabled",
+];
+const MAX_PENDING_CHANGES: usize = 10;
+const MAX_TIMELOCK_DELAY_SEC: DurationSec = 30 * 24 * 60 * 60;
+/// Gas kept by `execute_change` for itself, the rest goes to the timelocked call.
+const EXECUTE_CHANGE_RESERVED_GAS: Gas = Gas(10_000_000_000_000);
+
+/// A call to a timelocked method of the contract, executable after the delay.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingChange {
+    pub id: u64,
+    pub method_name: String,
+    /// JSON arguments of the call, or the code for `upgrade`.
+    pub args: Base64VecU8,
+    #[serde(with = "u64_dec_format")]
+    pub executable_at: Timestamp,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Delays timelocked changes by `timelock_delay_sec`. Shortening the delay is timelocked.
+    pub fn set_timelock_delay(&mut self, timelock_delay_sec: DurationSec) {
+        require!(
+            timelock_delay_sec <= MAX_TIMELOCK_DELAY_SEC,
+            "Timelock delay is too long"
+        );
+        let timelock_delay = timelock_delay_sec as Duration * 10u64.pow(9);
+        if timelock_delay < self.timelock_delay {
+            self.assert_owner_or_timelock();
+        } else {
+            self.assert_owner();
+        }
+        self.timelock_delay = timelock_delay;
+        Event::ConfigChanged {
+            field: "timelock_delay",
+        }
+        .emit();
+    }
+
+    /// Proposes a call to a timelocked method, returning the ID of the change.
+    pub fn propose_change(&mut self, method_name: String, args: Base64VecU8) -> u64 {
+        self.assert_owner();
+        require!(is_timelocked(&method_name), "Method is not timelocked");
+        require!(
+            self.pending_changes.len() < MAX_PENDING_CHANGES,
+            "Too many pending changes"
+        );
+        let id = self.next_change_id;
+        self.next_change_id += 1;
+        let executable_at = env::block_timestamp() + self.timelock_delay;
+        Event::ChangeProposed {
+            id,
+            method_name: method_name.clone(),
+            executable_at: executable_at.into(),
+        }
+        .emit();
+        self.pending_changes.push(PendingChange {
+            id,
+            method_name,
+            args,
+            executable_at,
+        });
+        id
+    }
+
+    /// Calls the method of a change whose delay has passed.
+    pub fn execute_change(&mut self, id: u64) -> Promise {
+        self.assert_owner();
+        let change = self.internal_take_pending_change(id);
+        require!(
+            env::block_timestamp() >= change.executable_at,
+            "The change is still timelocked"
+        );
+        Event::ChangeExecuted { id }.emit();
+        Promise::new(env::current_account_id()).function_call(
+            change.method_name,
+            change.args.0,
+            NO_DEPOSIT,
+            env::prepaid_gas() - env::used_gas() - EXECUTE_CHANGE_RESERVED_GAS,
+        )
+    }
+
+    pub fn cancel_change(&mut self, id: u64) {
+        self.assert_owner();
+        self.internal_take_pending_change(id);
+        Event::ChangeCancelled { id }.emit();
+    }
+
+    pub fn get_pending_changes(&self) -> Vec<PendingChange> {
+        self.pending_changes.clone()
+    }
+}
+
+impl Contract {
+    /// Passes calls the contract makes to itself, from `execute_change` or an approved council
+    /// proposal, and owner calls while there is no timelock.
+    pub fn assert_owner_or_timelock(&self) {
+        if env::predecessor_account_id() == env::current_account_id() {
+            return;
+        }
+        self.assert_owner();
+        require!(
+            self.timelock_delay == 0,
+            "The change is timelocked, use propose_change"
+        );
+    }
+
+    fn internal_take_pending_change(&mut self, id: u64) -> PendingChange {
+        let index = self
+            .pending_changes
+            .iter()
+            .position(|change| change.id == id)
+            .expect("Change is not found");
+        self.pending_changes.remove(index)
+    }
+}
+
+pub fn is_timelocked(method_name: &str) -> bool {
+    TIMELOCKED_METHODS.contains(&method_name)
+}
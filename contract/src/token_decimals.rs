@@ -0,0 +1,127 @@
+use super::*;
+use near_sdk::PromiseResult;
+
+const FT_METADATA_GAS: Gas = Gas(5_000_000_000_000);
+const ON_TOKEN_DECIMALS_GAS: Gas = Gas(10_000_000_000_000);
+
+/// Decimals of a token of the swap paths as read from its `ft_metadata`.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenDecimals {
+    pub token_id: AccountId,
+    pub decimals: u8,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FungibleTokenMetadata {
+    pub decimals: u8,
+}
+
+#[ext_contract(ext_self_token_decimals)]
+pub trait ExtTokenDecimals {
+    fn on_token_decimals(&mut self, token_ids: Vec<AccountId>);
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Reads the decimals of the swap path tokens again, also done when a path is set.
+    pub fn refresh_token_decimals(&mut self) -> Promise {
+        self.assert_operator();
+        self.internal_fetch_token_decimals()
+    }
+
+    #[private]
+    pub fn on_token_decimals(&mut self, token_ids: Vec<AccountId>) {
+        for (index, token_id) in token_ids.into_iter().enumerate() {
+            let metadata = match env::promise_result(index as u64) {
+                PromiseResult::Successful(value) => {
+                    serde_json::from_slice::<FungibleTokenMetadata>(&value).ok()
+                }
+                _ => None,
+            };
+            // A token without readable metadata fails the price checks until the next refresh.
+            self.token_decimals
+                .retain(|cached| cached.token_id != token_id);
+            if let Some(FungibleTokenMetadata { decimals }) = metadata {
+                self.token_decimals
+                    .push(TokenDecimals { token_id, decimals });
+            }
+        }
+        Event::ConfigChanged {
+            field: "token_decimals",
+        }
+        .emit();
+    }
+
+    pub fn get_token_decimals(&self) -> Vec<TokenDecimals> {
+        self.token_decimals.clone()
+    }
+}
+
+impl Contract {
+    /// Tokens of the swap path, the fallback paths, the split routes and the output tokens.
+    fn internal_path_token_ids(&self) -> Vec<AccountId> {
+        let mut token_ids = vec![self.swap_path[0].token_in.clone()];
+        let paths = std::iter::once(&self.swap_path)
+            .chain(self.fallback_swap_paths.iter())
+            .chain(self.split_routes.iter().map(|route| &route.swap_path))
+            .chain(self.output_tokens.iter().map(|output| &output.swap_path));
+        for action in paths.flatten() {
+            if !token_ids.contains(&action.token_out) {
+                token_ids.push(action.token_out.clone());
+            }
+        }
+        token_ids
+    }
+
+    /// Reads the decimals of every token of the swap paths, dropping those of other tokens.
+    pub fn internal_fetch_token_decimals(&mut self) -> Promise {
+        let token_ids = self.internal_path_token_ids();
+        self.token_decimals
+            .retain(|cached| token_ids.contains(&cached.token_id));
+        token_ids
+            .iter()
+            .map(|token_id| {
+                ext_ft_metadata::ft_metadata(token_id.clone(), NO_DEPOSIT, FT_METADATA_GAS)
+            })
+            .reduce(|promise, read| promise.and(read))
+            .unwrap()
+            .then(ext_self_token_decimals::on_token_decimals(
+                token_ids,
+                env::current_account_id(),
+                NO_DEPOSIT,
+                ON_TOKEN_DECIMALS_GAS,
+            ))
+    }
+
+    /// Oracle prices are per smallest token unit, so a price reported with the decimals of
+    /// another variant of a token is off by orders of magnitude. Prices of the same oracle
+    /// have to carry the same number of decimals on top of the token decimals. A priced
+    /// token of the swap paths without known decimals fails the check.
+    pub fn internal_prices_match_decimals(&self, prices: &HashMap<AccountId, Price>) -> bool {
+        let mut extra_decimals = None;
+        for token_id in self.internal_path_token_ids() {
+            let price = match prices.get(&token_id) {
+                Some(price) => price,
+                None => continue,
+            };
+            let decimals = match self
+                .token_decimals
+                .iter()
+                .find(|cached| cached.token_id == token_id)
+            {
+                Some(cached) => cached.decimals,
+                None => return false,
+            };
+            let extra = match price.decimals.checked_sub(decimals) {
+                Some(extra) => extra,
+                None => return false,
+            };
+            if *extra_decimals.get_or_insert(extra) != extra {
+                return false;
+            }
+        }
+        true
+    }
+}
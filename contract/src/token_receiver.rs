@@ -0,0 +1,169 @@
+use super::*;
+use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+
+const KICKSTART_MSG: &str = "kickstart";
+const ON_KICKSTART_WNEAR_FARMED_GAS: Gas = Gas(10_000_000_000_000);
+
+#[ext_contract(ext_self_token_receiver)]
+pub trait ExtTokenReceiver {
+    fn on_kickstart_wnear_farmed(
+        &mut self,
+        #[callback_result] used_amount: Result<U128, PromiseError>,
+        amount: U128,
+    );
+}
+
+#[near_bindgen]
+impl FungibleTokenReceiver for Contract {
+    /// With an empty message the reward token and wNEAR are accepted as donations, other tokens are
+    /// returned. See `internal_kickstart_farm` for the `kickstart` message.
+    fn ft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        amount: U128,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        if msg.starts_with(KICKSTART_MSG) {
+            return self.internal_kickstart_farm(sender_id, amount, msg);
+        }
+        require!(msg.is_empty(), "Unsupported message");
+        self.assert_not_paused();
+        require!(self.donations_enabled, "Donations are disabled");
+        let token_id = env::predecessor_account_id();
+        if token_id == self.wrap_near_contract_id {
+            self.assert_donation_accepted(amount.0);
+            // Burrow withdrawals and liquid staking collections are measured by the growth of
+            // the wNEAR balance.
+            require!(
+                !self.burrow_pending && !self.distribution_in_flight,
+                "wNEAR donations wait for the distribution or Burrow withdrawal"
+            );
+        }
+        self.internal_maybe_snapshot_accounting();
+        if token_id == self.reward_token_id {
+            // Picked up by the next `distribute_reward_tokens`.
+            self.pending_reward_donations += amount.0;
+        } else if token_id == self.wrap_near_contract_id {
+            // Treated like a NEAR donation that is already wrapped.
+            self.wrapped_amount += amount.0;
+            self.rewards_received += amount.0;
+            self.available_rewards += amount.0;
+            self.near_donated += amount.0;
+        } else {
+            return PromiseOrValue::Value(amount);
+        }
+        Event::TokenDonation {
+            donor_id: sender_id,
+            token_id,
+            amount,
+        }
+        .emit();
+        PromiseOrValue::Value(U128(0))
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// wNEAR refunded by the farm stays as a donation of the owner.
+    #[private]
+    pub fn on_kickstart_wnear_farmed(
+        &mut self,
+        #[callback_result] used_amount: Result<U128, PromiseError>,
+        amount: U128,
+    ) {
+        let used_amount = used_amount.map_or(0, |used_amount| used_amount.0);
+        let refunded_amount = amount.0 - used_amount;
+        if refunded_amount == 0 {
+            return;
+        }
+        self.wrapped_amount += refunded_amount;
+        self.rewards_received += refunded_amount;
+        self.available_rewards += refunded_amount;
+        self.near_donated += refunded_amount;
+        self.internal_record_error("farm", "kickstart_refunded", refunded_amount);
+    }
+}
+
+impl Contract {
+    pub fn assert_donation_accepted(&self, amount: Balance) {
+        require!(self.donations_enabled, "Donations are disabled");
+        require!(
+            amount > 0 && amount >= self.min_donation,
+            "Donation is below the minimum"
+        );
+    }
+
+    /// Adds reward tokens or wNEAR sent by the owner straight to the matching farm, e.g. to start
+    /// a farm before the first rewards accrue. The message is `kickstart` for the default
+    /// farm duration or `kickstart:<farm_duration_sec>`.
+    fn internal_kickstart_farm(
+        &mut self,
+        sender_id: AccountId,
+        amount: U128,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        require!(self.is_owner(&sender_id), "Not an owner!");
+        let farm_duration = match msg.strip_prefix(KICKSTART_MSG) {
+            Some("") => self.farm_duration,
+            Some(duration) => {
+                let farm_duration_sec: u32 = duration
+                    .strip_prefix(':')
+                    .and_then(|duration| duration.parse().ok())
+                    .expect("Invalid farm duration");
+                require!(farm_duration_sec > 0, "Invalid farm duration");
+                u64::from(farm_duration_sec) * 10u64.pow(9)
+            }
+            None => env::panic_str("Unsupported message"),
+        };
+
+        let token_id = env::predecessor_account_id();
+        let farm_id = if token_id == self.reward_token_id {
+            self.farm_id
+        } else if token_id == self.wrap_near_contract_id {
+            self.wnear_farm_id.expect("Missing wNEAR farm ID")
+        } else {
+            env::panic_str("Unsupported token")
+        };
+        // Reward tokens are capped by `max_reward_rate` like distributed ones.
+        let (amount, farm_duration) = if token_id == self.reward_token_id {
+            self.internal_limit_reward_rate(
+                Some(farm_id),
+                amount.0,
+                env::block_timestamp(),
+                farm_duration,
+            )
+        } else {
+            (amount.0, farm_duration)
+        };
+        Event::FarmKickstarted {
+            token_id: token_id.clone(),
+            amount: amount.into(),
+            farm_id,
+        }
+        .emit();
+        if amount == 0 {
+            return PromiseOrValue::Value(U128(0));
+        }
+        let promise =
+            self.internal_farm_tokens(token_id.clone(), Some(farm_id), amount, None, farm_duration);
+        // Refunds are accounted like those of distributions.
+        if token_id == self.reward_token_id {
+            self.reward_tokens_distributed += amount;
+            promise.then(ext_self::on_reward_tokens_farmed(
+                amount.into(),
+                Some(farm_id),
+                env::current_account_id(),
+                NO_DEPOSIT,
+                self.gas.on_reward_tokens_farmed,
+            ));
+        } else {
+            promise.then(ext_self_token_receiver::on_kickstart_wnear_farmed(
+                amount.into(),
+                env::current_account_id(),
+                NO_DEPOSIT,
+                ON_KICKSTART_WNEAR_FARMED_GAS,
+            ));
+        }
+        PromiseOrValue::Value(U128(0))
+    }
+}
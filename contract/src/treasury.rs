@@ -0,0 +1,111 @@
+use super::*;
+
+/// A plain `ft_transfer` made from `on_swap`, where little gas is left besides the farm.
+const TREASURY_TRANSFER_GAS: Gas = Gas(5_000_000_000_000);
+const ON_TREASURY_PAID_GAS: Gas = Gas(5_000_000_000_000);
+
+#[ext_contract(ext_self_treasury)]
+pub trait ExtTreasury {
+    fn on_treasury_paid(&mut self, amount: U128);
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TreasurySplit {
+    pub treasury_account_id: Option<AccountId>,
+    pub treasury_share_bps: u32,
+    /// Reward tokens sent to the treasury.
+    pub treasury_sent: U128,
+    /// Reward tokens added to the farm.
+    pub farmed: U128,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sends `treasury_share_bps` of each swap output to the treasury, the rest is farmed.
+    pub fn set_treasury_split(
+        &mut self,
+        treasury_account_id: Option<AccountId>,
+        treasury_share_bps: u32,
+    ) {
+        self.assert_owner_or_timelock();
+        require!(
+            treasury_share_bps <= MAX_BPS,
+            "Treasury share can't exceed 100%"
+        );
+        require!(
+            treasury_share_bps == 0 || treasury_account_id.is_some(),
+            "Missing treasury account"
+        );
+        self.treasury_account_id = treasury_account_id;
+        self.treasury_share_bps = treasury_share_bps;
+        Event::ConfigChanged {
+            field: "treasury_split",
+        }
+        .emit();
+        if self.treasury_account_id.is_some() {
+            self.internal_register_storage();
+        }
+    }
+
+    /// A failed transfer, e.g. for a treasury not registered with the reward token, is
+    /// retried by farming the tokens with the next distribution.
+    #[private]
+    pub fn on_treasury_paid(&mut self, amount: U128) {
+        if is_promise_success() {
+            return;
+        }
+        self.treasury_sent -= amount.0;
+        self.internal_hold_back_reward_tokens(amount.0, "treasury_transfer_failed");
+        self.internal_record_error("treasury", "treasury_transfer_failed", amount.0);
+    }
+
+    pub fn get_treasury_split(&self) -> TreasurySplit {
+        TreasurySplit {
+            treasury_account_id: self.treasury_account_id.clone(),
+            treasury_share_bps: self.treasury_share_bps,
+            treasury_sent: self.treasury_sent.into(),
+            farmed: (self.reward_tokens_distributed + self.internal_farm_splits_distributed())
+                .into(),
+        }
+    }
+}
+
+impl Contract {
+    /// Sends the treasury share of `reward_amount` and returns the rest.
+    pub fn internal_send_treasury_share(&mut self, reward_amount: Balance) -> Balance {
+        let treasury_amount = u128_ratio(
+            reward_amount,
+            self.treasury_share_bps as u128,
+            MAX_BPS as u128,
+        );
+        if treasury_amount == 0 {
+            return reward_amount;
+        }
+        let receiver_id = self
+            .treasury_account_id
+            .clone()
+            .expect("Missing treasury account");
+        Event::TreasuryPaid {
+            receiver_id: receiver_id.clone(),
+            amount: treasury_amount.into(),
+        }
+        .emit();
+        self.treasury_sent += treasury_amount;
+        ext_fungible_token::ft_transfer(
+            receiver_id,
+            treasury_amount.into(),
+            Some("Treasury share of staking rewards".to_string()),
+            self.reward_token_id.clone(),
+            ONE_YOCTO,
+            TREASURY_TRANSFER_GAS,
+        )
+        .then(ext_self_treasury::on_treasury_paid(
+            treasury_amount.into(),
+            env::current_account_id(),
+            NO_DEPOSIT,
+            ON_TREASURY_PAID_GAS,
+        ));
+        reward_amount - treasury_amount
+    }
+}
@@ -22,8 +22,14 @@ pub struct PriceData {
     pub prices: Vec<AssetOptionalPrice>,
 }
 
+#[allow(dead_code)]
 pub trait OraclePriceReceiver {
-    fn oracle_on_call(&mut self, sender_id: AccountId, data: PriceData, msg: String) -> Promise;
+    fn oracle_on_call(
+        &mut self,
+        sender_id: AccountId,
+        data: PriceData,
+        msg: String,
+    ) -> PromiseOrValue<OracleResult>;
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Copy)]
@@ -38,6 +44,116 @@ impl Price {
     pub fn assert_valid(&self) {
         assert!(self.decimals <= MAX_VALID_DECIMALS);
     }
+
+    /// Compares prices given with different decimals.
+    pub fn cmp_value(&self, other: &Price) -> std::cmp::Ordering {
+        let decimals = std::cmp::max(self.decimals, other.decimals);
+        let scale = |price: &Price| {
+            U256::from(10)
+                .checked_pow(U256::from(decimals - price.decimals))
+                .and_then(|factor| factor.checked_mul(U256::from(price.multiplier)))
+                .unwrap_or(U256::MAX)
+        };
+        scale(self).cmp(&scale(other))
+    }
+}
+
+/// Converts `amount` of a token priced at `price_in` into the token priced at `price_out`.
+pub fn oracle_amount_out(amount: Balance, price_in: &Price, price_out: &Price) -> Balance {
+    let in_extra = if price_in.decimals < price_out.decimals {
+        10u128.pow((price_out.decimals - price_in.decimals) as _)
+    } else {
+        1
+    };
+
+    let out_extra = if price_out.decimals < price_in.decimals {
+        10u128.pow((price_in.decimals - price_out.decimals) as _)
+    } else {
+        1
+    };
+
+    u128_ratio(
+        amount,
+        price_in.multiplier * in_extra,
+        price_out.multiplier * out_extra,
+    )
+}
+
+/// Output of each hop of `swap_path` for `amount` at oracle prices. Each hop chains the cross
+/// rate from the last priced token, so a hop into a token without a price is `None`.
+pub fn route_oracle_amounts(
+    amount: Balance,
+    swap_path: &[Action],
+    prices: &HashMap<AccountId, Price>,
+) -> Vec<Option<Balance>> {
+    let mut last_priced = prices
+        .get(&swap_path[0].token_in)
+        .map(|price| (amount, *price));
+    swap_path
+        .iter()
+        .map(|action| {
+            let (amount_in, price_in) = last_priced?;
+            let price_out = *prices.get(&action.token_out)?;
+            let amount_out = oracle_amount_out(amount_in, &price_in, &price_out);
+            last_priced = Some((amount_out, price_out));
+            Some(amount_out)
+        })
+        .collect()
+}
+
+/// Checks that the hops of a swap path chain into each other through distinct pools.
+pub fn assert_valid_path_hops(path: &[Action]) {
+    require!(!path.is_empty(), "Swap path is empty");
+    require!(
+        path.len() <= MAX_SWAP_PATH_HOPS,
+        "Too many hops in the swap path"
+    );
+    for (index, action) in path.iter().enumerate() {
+        if let Some(next_action) = path.get(index + 1) {
+            require!(
+                action.token_out == next_action.token_in,
+                "Swap path hops don't chain"
+            );
+        }
+        require!(
+            path[..index]
+                .iter()
+                .all(|previous| previous.pool_id != action.pool_id),
+            "Swap path uses a pool twice"
+        );
+    }
+}
+
+/// Runs `write` and adds the storage it took, or subtracts the storage it freed, to `bytes`.
+pub fn track_storage<R>(bytes: &mut StorageUsage, write: impl FnOnce() -> R) -> R {
+    let usage = env::storage_usage();
+    let result = write();
+    *bytes = (*bytes + env::storage_usage()).saturating_sub(usage);
+    result
+}
+
+/// Inclusive range of acceptable prices.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Copy)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PriceBand {
+    pub min: Price,
+    pub max: Price,
+}
+
+impl PriceBand {
+    /// Whether `price` fell below the band, e.g. after a depeg.
+    pub fn is_below(&self, price: &Price) -> bool {
+        price.cmp_value(&self.min) == std::cmp::Ordering::Less
+    }
+
+    /// `price` capped at the top of the band.
+    pub fn cap(&self, price: Price) -> Price {
+        if price.cmp_value(&self.max) == std::cmp::Ordering::Greater {
+            self.max
+        } else {
+            price
+        }
+    }
 }
 
 pub mod u128_dec_format {
@@ -61,6 +177,48 @@ pub mod u128_dec_format {
     }
 }
 
+pub mod option_u128_dec_format {
+    use near_sdk::serde::Serializer;
+
+    pub fn serialize<S>(num: &Option<u128>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match num {
+            Some(num) => serializer.serialize_some(&num.to_string()),
+            None => serializer.serialize_none(),
+        }
+    }
+}
+
+pub mod option_u64_dec_format {
+    use near_sdk::serde::Serializer;
+
+    pub fn serialize<S>(num: &Option<u64>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match num {
+            Some(num) => serializer.serialize_some(&num.to_string()),
+            None => serializer.serialize_none(),
+        }
+    }
+}
+
+/// Tells a missing field, `None`, from an explicit `null`, `Some(None)`. Needs
+/// `#[serde(default)]` on the field.
+pub mod double_option {
+    use near_sdk::serde::{Deserialize, Deserializer};
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+    where
+        T: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        Option::<T>::deserialize(deserializer).map(Some)
+    }
+}
+
 pub mod u64_dec_format {
     use near_sdk::serde::de;
     use near_sdk::serde::{Deserialize, Deserializer, Serializer};
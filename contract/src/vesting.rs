@@ -0,0 +1,161 @@
+use super::*;
+
+const MAX_VESTING_DONATIONS: usize = 20;
+const MAX_VESTING_DURATION_SEC: DurationSec = 365 * 24 * 60 * 60;
+
+/// A donation released into `available_rewards` linearly until `end_date`.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VestingDonation {
+    pub donor_id: AccountId,
+    pub amount: U128,
+    /// Part of `amount` already added to `available_rewards`.
+    pub unlocked: U128,
+    pub start_date: U64,
+    pub end_date: U64,
+}
+
+impl VestingDonation {
+    fn vested(&self, timestamp: Timestamp) -> Balance {
+        if timestamp >= self.end_date.0 {
+            self.amount.0
+        } else {
+            u128_ratio(
+                self.amount.0,
+                u128::from(timestamp - self.start_date.0),
+                u128::from(self.end_date.0 - self.start_date.0),
+            )
+        }
+    }
+}
+
+/// Donations of at least `min_amount` vest over at least `duration_sec`. Smaller donations
+/// can't ask for a vesting either.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DonationVesting {
+    pub min_amount: U128,
+    pub duration_sec: DurationSec,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Makes large donations vest instead of being distributed right away, `None` disables it.
+    /// Donors can still ask for a longer vesting.
+    pub fn set_donation_vesting(&mut self, donation_vesting: Option<DonationVesting>) {
+        self.assert_owner();
+        if let Some(donation_vesting) = &donation_vesting {
+            require!(
+                donation_vesting.duration_sec > 0
+                    && donation_vesting.duration_sec <= MAX_VESTING_DURATION_SEC,
+                "Vesting duration is out of bounds"
+            );
+        }
+        self.donation_vesting = donation_vesting;
+        Event::ConfigChanged {
+            field: "donation_vesting",
+        }
+        .emit();
+    }
+
+    /// Adds the vested part of the vesting donations to `available_rewards`. Also done by
+    /// pings and price triggered distributions.
+    pub fn unlock_vested_donations(&mut self) {
+        self.assert_not_paused();
+        self.internal_unlock_vested_donations();
+    }
+
+    pub fn get_vesting_donations(&self) -> Vec<VestingDonation> {
+        self.vesting_donations.clone()
+    }
+}
+
+impl Contract {
+    /// Vesting duration of a donation of `amount` with `duration_sec` requested by the donor.
+    /// Only donations of at least `donation_vesting.min_amount` vest.
+    pub fn internal_donation_vesting_duration(
+        &self,
+        amount: Balance,
+        duration_sec: Option<DurationSec>,
+    ) -> DurationSec {
+        let vesting = match self.donation_vesting {
+            Some(vesting) if amount >= vesting.min_amount.0 => vesting,
+            _ => {
+                require!(
+                    duration_sec.unwrap_or(0) == 0,
+                    "Donation is too small to vest"
+                );
+                return 0;
+            }
+        };
+        let duration_sec = duration_sec.unwrap_or(0);
+        require!(
+            duration_sec <= MAX_VESTING_DURATION_SEC,
+            "Vesting duration is too long"
+        );
+        std::cmp::max(vesting.duration_sec, duration_sec)
+    }
+
+    /// Adds a donation vesting until `duration_sec` from now. Once all slots are taken, it's
+    /// merged into the donor's slot or the one ending last, whose rest then vests with it
+    /// until the later end date.
+    pub fn internal_add_vesting_donation(
+        &mut self,
+        donor_id: AccountId,
+        amount: Balance,
+        duration_sec: DurationSec,
+    ) {
+        self.internal_unlock_vested_donations();
+        let start_date = env::block_timestamp();
+        let end_date = start_date + u64::from(duration_sec) * 10u64.pow(9);
+        Event::DonationVestingStarted {
+            donor_id: donor_id.clone(),
+            amount: amount.into(),
+            end_date: end_date.into(),
+        }
+        .emit();
+        self.vesting_amount += amount;
+        if self.vesting_donations.len() < MAX_VESTING_DONATIONS {
+            self.vesting_donations.push(VestingDonation {
+                donor_id,
+                amount: amount.into(),
+                unlocked: U128(0),
+                start_date: start_date.into(),
+                end_date: end_date.into(),
+            });
+            return;
+        }
+        let index = self
+            .vesting_donations
+            .iter()
+            .position(|donation| donation.donor_id == donor_id)
+            .unwrap_or_else(|| {
+                (0..self.vesting_donations.len())
+                    .max_by_key(|index| self.vesting_donations[*index].end_date.0)
+                    .unwrap()
+            });
+        let slot = &mut self.vesting_donations[index];
+        let rest = slot.amount.0 - slot.unlocked.0;
+        *slot = VestingDonation {
+            donor_id: slot.donor_id.clone(),
+            amount: (rest + amount).into(),
+            unlocked: U128(0),
+            start_date: start_date.into(),
+            end_date: std::cmp::max(slot.end_date.0, end_date).into(),
+        };
+    }
+
+    pub fn internal_unlock_vested_donations(&mut self) {
+        let timestamp = env::block_timestamp();
+        let mut unlocked = 0;
+        for donation in self.vesting_donations.iter_mut() {
+            let vested = donation.vested(timestamp);
+            unlocked += vested - donation.unlocked.0;
+            donation.unlocked = vested.into();
+        }
+        self.vesting_donations
+            .retain(|donation| donation.unlocked.0 < donation.amount.0);
+        self.vesting_amount -= unlocked;
+        self.available_rewards += unlocked;
+    }
+}
@@ -0,0 +1,351 @@
+use std::convert::TryFrom;
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::{LazyOption, UnorderedMap};
+use near_sdk::json_types::{Base64VecU8, U128};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::serde_json::{self, Value};
+use near_sdk::{
+    env, ext_contract, is_promise_success, log, near_bindgen, require, AccountId, Balance,
+    BorshStorageKey, Gas, PanicOnDefault, Promise,
+};
+
+const NO_DEPOSIT: Balance = 0;
+const INIT_GAS: Gas = Gas(100_000_000_000_000);
+const UPGRADE_GAS: Gas = Gas(150_000_000_000_000);
+const ON_INSTANCE_DEPLOYED_GAS: Gas = Gas(10_000_000_000_000);
+const ON_INSTANCE_UPGRADED_GAS: Gas = Gas(10_000_000_000_000);
+
+/// Limit on instances called by a single `call_instances`.
+const MAX_INSTANCES_PER_CALL: u64 = 10;
+
+/// Deployed owner contract of a staking pool. The factory is its owner.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Instance {
+    pub account_id: AccountId,
+    pub staking_pool_account_id: AccountId,
+    /// Version of the stored code the instance runs.
+    pub code_version: u32,
+}
+
+#[ext_contract(ext_self)]
+pub trait ExtFactory {
+    /* Callback from creating and initializing an instance */
+    fn on_instance_deployed(&mut self, instance: Instance, deposit: U128);
+    /* Callback from pushing the stored code to an instance */
+    fn on_instance_upgraded(&mut self, staking_pool_account_id: AccountId, code_version: u32);
+}
+
+#[derive(BorshStorageKey, BorshSerialize)]
+pub(crate) enum StorageKey {
+    Code,
+    Instances,
+}
+
+#[near_bindgen]
+#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
+pub struct Factory {
+    owner_id: AccountId,
+    /// Code of the staking pool owner contract deployed to new instances.
+    code: LazyOption<Vec<u8>>,
+    code_version: u32,
+    /// Instances by their staking pool.
+    instances: UnorderedMap<AccountId, Instance>,
+}
+
+#[near_bindgen]
+impl Factory {
+    #[init]
+    pub fn new(owner_id: AccountId) -> Self {
+        Self {
+            owner_id,
+            code: LazyOption::new(StorageKey::Code, None),
+            code_version: 0,
+            instances: UnorderedMap::new(StorageKey::Instances),
+        }
+    }
+
+    /// Stores the owner contract code used for new instances and upgrades.
+    pub fn set_code(&mut self, code: Base64VecU8) {
+        self.assert_owner();
+        self.code.set(&code.into());
+        self.code_version += 1;
+        log!("Stored code version {}", self.code_version);
+    }
+
+    pub fn get_code_version(&self) -> u32 {
+        self.code_version
+    }
+
+    /// Creates `<name>.<factory>` with the attached deposit, deploys the stored code and
+    /// calls `new` with `init_args`. The staking pool and the owner are filled in.
+    #[payable]
+    pub fn deploy_instance(
+        &mut self,
+        name: String,
+        staking_pool_account_id: AccountId,
+        init_args: Value,
+    ) -> Promise {
+        self.assert_owner();
+        require!(
+            self.instances.get(&staking_pool_account_id).is_none(),
+            "Staking pool already has an instance"
+        );
+        let code = self.code.get().expect("Code is not set");
+        let mut init_args = match init_args {
+            Value::Object(init_args) => init_args,
+            _ => env::panic_str("Init args must be an object"),
+        };
+        init_args.insert(
+            "staking_pool_account_id".to_string(),
+            Value::String(staking_pool_account_id.to_string()),
+        );
+        init_args.insert(
+            "owner_id".to_string(),
+            Value::String(env::current_account_id().to_string()),
+        );
+        let account_id = AccountId::try_from(format!("{}.{}", name, env::current_account_id()))
+            .expect("Invalid instance name");
+        let deposit = env::attached_deposit();
+        Promise::new(account_id.clone())
+            .create_account()
+            .transfer(deposit)
+            .deploy_contract(code)
+            .function_call(
+                "new".to_string(),
+                serde_json::to_vec(&init_args).unwrap(),
+                NO_DEPOSIT,
+                INIT_GAS,
+            )
+            .then(ext_self::on_instance_deployed(
+                Instance {
+                    account_id,
+                    staking_pool_account_id,
+                    code_version: self.code_version,
+                },
+                deposit.into(),
+                env::current_account_id(),
+                NO_DEPOSIT,
+                ON_INSTANCE_DEPLOYED_GAS,
+            ))
+    }
+
+    #[private]
+    pub fn on_instance_deployed(&mut self, instance: Instance, deposit: U128) {
+        if is_promise_success() {
+            log!(
+                "Deployed {} for {}",
+                instance.account_id,
+                instance.staking_pool_account_id
+            );
+            self.instances
+                .insert(&instance.staking_pool_account_id, &instance);
+        } else {
+            // The deposit of a failed account creation is refunded to the factory.
+            log!("Failed to deploy {}", instance.account_id);
+            Promise::new(self.owner_id.clone()).transfer(deposit.0);
+        }
+    }
+
+    /// Pushes the stored code to an instance, which deploys it and runs `migrate`.
+    pub fn upgrade_instance(&mut self, staking_pool_account_id: AccountId) -> Promise {
+        self.assert_owner();
+        let instance = self.internal_get_instance(&staking_pool_account_id);
+        let code = self.code.get().expect("Code is not set");
+        Promise::new(instance.account_id)
+            .function_call("upgrade".to_string(), code, NO_DEPOSIT, UPGRADE_GAS)
+            .then(ext_self::on_instance_upgraded(
+                staking_pool_account_id,
+                self.code_version,
+                env::current_account_id(),
+                NO_DEPOSIT,
+                ON_INSTANCE_UPGRADED_GAS,
+            ))
+    }
+
+    #[private]
+    pub fn on_instance_upgraded(&mut self, staking_pool_account_id: AccountId, code_version: u32) {
+        require!(is_promise_success(), "Upgrade failed");
+        let mut instance = self.internal_get_instance(&staking_pool_account_id);
+        instance.code_version = code_version;
+        self.instances.insert(&staking_pool_account_id, &instance);
+    }
+
+    /// Calls an owner method, e.g. a config setter, on the instance of a staking pool.
+    pub fn call_instance(
+        &mut self,
+        staking_pool_account_id: AccountId,
+        method_name: String,
+        args: Value,
+        gas: Gas,
+    ) -> Promise {
+        self.assert_owner();
+        let instance = self.internal_get_instance(&staking_pool_account_id);
+        Promise::new(instance.account_id).function_call(
+            method_name,
+            serde_json::to_vec(&args).unwrap(),
+            NO_DEPOSIT,
+            gas,
+        )
+    }
+
+    /// Calls the same owner method on a page of instances, in `get_instances` order.
+    pub fn call_instances(
+        &mut self,
+        method_name: String,
+        args: Value,
+        gas: Gas,
+        from_index: u64,
+        limit: u64,
+    ) {
+        self.assert_owner();
+        require!(limit <= MAX_INSTANCES_PER_CALL, "Too many instances");
+        let args = serde_json::to_vec(&args).unwrap();
+        for instance in self.get_instances(from_index, limit) {
+            Promise::new(instance.account_id).function_call(
+                method_name.clone(),
+                args.clone(),
+                NO_DEPOSIT,
+                gas,
+            );
+        }
+    }
+
+    pub fn get_instance(&self, staking_pool_account_id: AccountId) -> Option<Instance> {
+        self.instances.get(&staking_pool_account_id)
+    }
+
+    pub fn get_instances(&self, from_index: u64, limit: u64) -> Vec<Instance> {
+        let values = self.instances.values_as_vector();
+        let to_index = std::cmp::min(from_index.saturating_add(limit), values.len());
+        (from_index..to_index)
+            .map(|index| values.get(index).unwrap())
+            .collect()
+    }
+
+    pub fn get_instances_count(&self) -> u64 {
+        self.instances.len()
+    }
+}
+
+impl Factory {
+    fn internal_get_instance(&self, staking_pool_account_id: &AccountId) -> Instance {
+        self.instances
+            .get(staking_pool_account_id)
+            .expect("No instance for the staking pool")
+    }
+
+    fn assert_owner(&self) {
+        assert_eq!(
+            &self.owner_id,
+            &env::predecessor_account_id(),
+            "Not an owner!"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, PromiseResult, RuntimeFeesConfig, VMConfig};
+    use std::collections::HashMap;
+
+    fn factory_id() -> AccountId {
+        "factory.near".parse().unwrap()
+    }
+
+    fn context(predecessor_account_id: AccountId) -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .current_account_id(factory_id())
+            .predecessor_account_id(predecessor_account_id);
+        builder
+    }
+
+    #[test]
+    fn test_new() {
+        testing_env!(context(accounts(0)).build());
+        let factory = Factory::new(accounts(0));
+        assert_eq!(factory.get_code_version(), 0);
+        assert_eq!(factory.get_instances_count(), 0);
+    }
+
+    #[test]
+    fn test_deploy_instance() {
+        testing_env!(context(accounts(0))
+            .attached_deposit(5 * 10u128.pow(24))
+            .build());
+        let mut factory = Factory::new(accounts(0));
+        factory.set_code(vec![0; 16].into());
+        assert_eq!(factory.get_code_version(), 1);
+        factory.deploy_instance(
+            "pool".to_string(),
+            accounts(1),
+            serde_json::json!({ "farm_id": 0 }),
+        );
+        // The instance is only recorded once it's created and initialized.
+        assert_eq!(factory.get_instances_count(), 0);
+
+        testing_env!(
+            context(factory_id()).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            HashMap::default(),
+            vec![PromiseResult::Successful(vec![])]
+        );
+        factory.on_instance_deployed(
+            Instance {
+                account_id: "pool.factory.near".parse().unwrap(),
+                staking_pool_account_id: accounts(1),
+                code_version: 1,
+            },
+            U128(5 * 10u128.pow(24)),
+        );
+        let instance = factory.get_instance(accounts(1)).unwrap();
+        assert_eq!(instance.account_id.as_str(), "pool.factory.near");
+        assert_eq!(instance.code_version, 1);
+        assert_eq!(factory.get_instances_count(), 1);
+    }
+
+    #[test]
+    fn test_failed_deploy_is_not_recorded() {
+        testing_env!(context(accounts(0)).build());
+        let mut factory = Factory::new(accounts(0));
+        testing_env!(
+            context(factory_id()).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            HashMap::default(),
+            vec![PromiseResult::Failed]
+        );
+        factory.on_instance_deployed(
+            Instance {
+                account_id: "pool.factory.near".parse().unwrap(),
+                staking_pool_account_id: accounts(1),
+                code_version: 1,
+            },
+            U128(5 * 10u128.pow(24)),
+        );
+        assert!(factory.get_instance(accounts(1)).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Code is not set")]
+    fn test_deploy_instance_without_code() {
+        testing_env!(context(accounts(0)).build());
+        let mut factory = Factory::new(accounts(0));
+        factory.deploy_instance("pool".to_string(), accounts(1), serde_json::json!({}));
+    }
+
+    #[test]
+    #[should_panic(expected = "Not an owner!")]
+    fn test_deploy_instance_not_owner() {
+        testing_env!(context(accounts(0)).build());
+        let mut factory = Factory::new(accounts(0));
+        factory.set_code(vec![0; 16].into());
+        testing_env!(context(accounts(2)).build());
+        factory.deploy_instance("pool".to_string(), accounts(1), serde_json::json!({}));
+    }
+}